@@ -1,6 +1,3 @@
-#[macro_use]
-extern crate neural_network;
-
 mod mnist_import;
 
 use mnist_import::parse_files;
@@ -8,16 +5,17 @@ use mnist_import::parse_files;
 use neural_network::{ActivationFunction, CostFunction, InitType, Network};
 
 fn main() {
-	let mut network = Network::new(vec![
-		input!(784),
-		feedforward!(ActivationFunction::Sigmoid, InitType::NormalisedXavier, 30),
-		output!(
-			ActivationFunction::Sigmoid,
-			CostFunction::CrossEntropy,
-			InitType::NormalisedXavier,
-			10
-		),
-	]);
+	let mut network = Network::builder()
+		.default_activation(ActivationFunction::Sigmoid)
+		.default_init(InitType::NormalisedXavier)
+		.input([784, 1, 1])
+		.dense(30)
+		.output(10)
+		.cost(CostFunction::CrossEntropy)
+		.build()
+		.unwrap();
+
+	network.summary();
 
 	let training_data = parse_files(
 		"mnist/train-images-idx3-ubyte",
@@ -3,13 +3,13 @@ use std::io::{Read, Result as IoResult};
 use std::path::Path;
 
 use byteorder::{BigEndian, ReadBytesExt};
+use neural_network::Float;
 
 const IMAGE_MAGIC: u32 = 0x00000803;
 const LABEL_MAGIC: u32 = 0x00000801;
 
 pub const IMAGE_SIZE: u32 = 28;
 
-pub type Float = f32;
 pub type Data = Vec<(Vec<Float>, Vec<Float>)>;
 
 //--------
@@ -0,0 +1,56 @@
+#[path = "../mnist/mnist_import.rs"]
+mod mnist_import;
+
+use mnist_import::parse_files;
+
+use neural_network::{set_simd, simd_enabled, ActivationFunction, CostFunction, InitType, Network};
+
+use std::time::Instant;
+
+const EPOCHS: usize = 3;
+
+// times training epochs on MNIST with the vectorised elementwise kernels turned on and off,
+// run with --release as the scalar loops are far slower in debug builds
+fn main() {
+	let training_data = parse_files(
+		"mnist/train-images-idx3-ubyte",
+		"mnist/train-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	for simd in [true, false] {
+		set_simd(simd);
+		if simd && !simd_enabled() {
+			println!("simd isn't supported on this cpu, skipping");
+			continue;
+		}
+
+		let mut network = Network::builder()
+			.default_init(InitType::He)
+			.input([784, 1, 1])
+			.dense(100)
+			.activation(ActivationFunction::ReLU)
+			.dense(100)
+			.activation(ActivationFunction::ReLU)
+			.output(10)
+			.activation(ActivationFunction::Sigmoid)
+			.init(InitType::NormalisedXavier)
+			.cost(CostFunction::CrossEntropy)
+			.build()
+			.unwrap();
+
+		let mut total = 0.0;
+		for epoch in 1..=EPOCHS {
+			let start = Instant::now();
+			network.sgd(training_data.clone(), None, 1, 10, 0.05, None);
+			let seconds = start.elapsed().as_secs_f64();
+			total += seconds;
+			println!("simd {}: epoch {} took {:.2}s", simd, epoch, seconds);
+		}
+		println!(
+			"simd {}: {:.2}s per epoch on average\n",
+			simd,
+			total / EPOCHS as f64
+		);
+	}
+}
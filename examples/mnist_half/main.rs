@@ -0,0 +1,48 @@
+#[path = "../mnist/mnist_import.rs"]
+mod mnist_import;
+
+use mnist_import::parse_files;
+
+use neural_network::{
+	ActivationFunction, CostFunction, HalfType, InitType, MixedPrecision, Network,
+};
+
+fn network() -> Network {
+	Network::builder()
+		.default_activation(ActivationFunction::Sigmoid)
+		.default_init(InitType::NormalisedXavier)
+		.input([784, 1, 1])
+		.dense(100)
+		.output(10)
+		.cost(CostFunction::CrossEntropy)
+		.build()
+		.unwrap()
+}
+
+// trains a network as usual then networks with f16 and bf16 mixed precision and compares their accuracy
+fn main() {
+	let training_data = parse_files(
+		"mnist/train-images-idx3-ubyte",
+		"mnist/train-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let test_data = parse_files(
+		"mnist/t10k-images-idx3-ubyte",
+		"mnist/t10k-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let mut full = network();
+	full.sgd(training_data.clone(), None, 5, 10, 0.25, None);
+
+	for half_type in [HalfType::F16, HalfType::BF16] {
+		let mut half = network();
+		let mut mixed = MixedPrecision::new(half_type);
+		mixed
+			.sgd(&mut half, training_data.clone(), 5, 10, 0.25)
+			.unwrap();
+		let report = mixed.report(&mut full, &half, &test_data).unwrap();
+		println!("{}\n", report);
+	}
+}
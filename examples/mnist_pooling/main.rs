@@ -0,0 +1,53 @@
+#[path = "../mnist/mnist_import.rs"]
+mod mnist_import;
+
+use mnist_import::{parse_files, IMAGE_SIZE};
+
+use neural_network::{ActivationFunction, CostFunction, InitType, Network};
+
+const FILTERS: usize = 64;
+const KERNEL: usize = 5;
+
+// a convolutional feature extractor followed by global max pooling instead of a large dense
+// layer, there is no 2D convolution yet so every row of the image is an input channel and
+// the kernel slides along the rows (across the columns)
+fn main() {
+	let size = IMAGE_SIZE as usize;
+	let mut network = Network::builder()
+		.default_init(InitType::He)
+		.input([size, size, 1])
+		.conv1d(FILTERS, KERNEL)
+		.activation(ActivationFunction::ReLU)
+		// the filters become channels so pooling takes the strongest response of each filter
+		.reshape([size - KERNEL + 1, 1, FILTERS])
+		.global_max_pool()
+		.output(10)
+		.activation(ActivationFunction::Sigmoid)
+		.init(InitType::NormalisedXavier)
+		.cost(CostFunction::CrossEntropy)
+		.build()
+		.unwrap();
+
+	network.summary();
+
+	let training_data = parse_files(
+		"mnist/train-images-idx3-ubyte",
+		"mnist/train-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let test_data = parse_files(
+		"mnist/t10k-images-idx3-ubyte",
+		"mnist/t10k-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	network.sgd(
+		training_data,
+		Some(test_data),
+		10,
+		10,
+		0.1,
+		Some("mnist pooling results"),
+	);
+}
@@ -0,0 +1,65 @@
+#[path = "../mnist/mnist_import.rs"]
+mod mnist_import;
+
+use mnist_import::parse_files;
+
+use neural_network::{
+	ActivationFunction, CostFunction, Float, InitType, Network, PruningReport, PruningSchedule,
+};
+
+const ROUNDS: i32 = 5;
+// the fraction of the remaining weights pruned each round
+const RATE: Float = 0.5;
+
+fn build_network() -> Network {
+	Network::builder()
+		.default_activation(ActivationFunction::Sigmoid)
+		.default_init(InitType::NormalisedXavier)
+		.input([784, 1, 1])
+		.dense(300)
+		.dense(100)
+		.output(10)
+		.cost(CostFunction::CrossEntropy)
+		.build()
+		.unwrap()
+}
+
+// finds lottery tickets with iterative magnitude pruning, rewinding every parameter left after
+// each round to its initial value before training again, then compares gradual pruning
+fn main() {
+	let training_data = parse_files(
+		"mnist/train-images-idx3-ubyte",
+		"mnist/train-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let test_data = parse_files(
+		"mnist/t10k-images-idx3-ubyte",
+		"mnist/t10k-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let mut network = build_network();
+	let initial = network.parameter_snapshot();
+	network.sgd(training_data.clone(), None, 3, 10, 0.25, None);
+
+	let mut report = PruningReport::new(&mut network, &test_data).unwrap();
+	for round in 1..=ROUNDS {
+		network.prune(1.0 - (1.0 - RATE).powi(round));
+		network.rewind(&initial);
+		network.sgd(training_data.clone(), None, 3, 10, 0.25, None);
+		report.add(&mut network, &test_data).unwrap();
+	}
+	println!("Lottery tickets\n{}\n", report);
+
+	let mut network = build_network();
+	let schedule = PruningSchedule {
+		end_epoch: 4,
+		final_sparsity: 0.95,
+		initial_sparsity: 0.0,
+		start_epoch: 1,
+	};
+	network.sgd_gradual_pruning(training_data, 6, 10, 0.25, schedule);
+	let report = PruningReport::new(&mut network, &test_data).unwrap();
+	println!("Gradual pruning\n{}", report);
+}
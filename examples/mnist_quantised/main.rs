@@ -0,0 +1,43 @@
+#[path = "../mnist/mnist_import.rs"]
+mod mnist_import;
+
+use mnist_import::parse_files;
+
+use neural_network::{
+	ActivationFunction, CostFunction, Granularity, InitType, Network, QuantisedNetwork,
+};
+
+const CALIBRATION_SAMPLES: usize = 500;
+
+// trains a small network then compares it with int8 versions using per layer and per channel scales
+fn main() {
+	let mut network = Network::builder()
+		.default_activation(ActivationFunction::Sigmoid)
+		.default_init(InitType::NormalisedXavier)
+		.input([784, 1, 1])
+		.dense(100)
+		.output(10)
+		.cost(CostFunction::CrossEntropy)
+		.build()
+		.unwrap();
+
+	let training_data = parse_files(
+		"mnist/train-images-idx3-ubyte",
+		"mnist/train-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let test_data = parse_files(
+		"mnist/t10k-images-idx3-ubyte",
+		"mnist/t10k-labels-idx1-ubyte",
+	)
+	.unwrap();
+
+	let calibration = training_data[..CALIBRATION_SAMPLES].to_vec();
+	network.sgd(training_data, None, 5, 10, 0.25, None);
+
+	for granularity in [Granularity::PerLayer, Granularity::PerChannel] {
+		let quantised = QuantisedNetwork::new(&mut network, &calibration, granularity).unwrap();
+		println!("{}\n", quantised.report(&mut network, &test_data));
+	}
+}
@@ -0,0 +1,71 @@
+use neural_network::{flatten_sequence, ActivationFunction, Float, InitType, Network, NetworkData};
+
+use rand::{thread_rng, Rng};
+
+const SEQUENCE_LENGTH: usize = 8;
+const SYMBOLS: usize = 3;
+const MODEL_SIZE: usize = 8;
+
+// each timestep is a one hot encoded symbol followed by a sinusoidal position encoding,
+// the network has to find which symbol appears the most in a sequence
+fn generate_data(len: usize) -> NetworkData {
+	let mut rng = thread_rng();
+	let mut data = Vec::with_capacity(len);
+	while data.len() < len {
+		let symbols: Vec<usize> = (0..SEQUENCE_LENGTH)
+			.map(|_| rng.gen_range(0..SYMBOLS))
+			.collect();
+
+		let mut counts = [0; SYMBOLS];
+		for &symbol in &symbols {
+			counts[symbol] += 1;
+		}
+		let most = *counts.iter().max().unwrap();
+		// skip sequences without a single most common symbol
+		if counts.iter().filter(|&&count| count == most).count() > 1 {
+			continue;
+		}
+
+		let sequence: Vec<Vec<Float>> = symbols
+			.iter()
+			.enumerate()
+			.map(|(t, &symbol)| {
+				let mut step = vec![0.0; MODEL_SIZE];
+				step[symbol] = 1.0;
+				for (i, value) in step[SYMBOLS..].iter_mut().enumerate() {
+					let frequency = 1.0 / (10.0 as Float).powi(i as i32 / 2);
+					*value = match i % 2 {
+						0 => (t as Float * frequency).sin(),
+						_ => (t as Float * frequency).cos(),
+					};
+				}
+				step
+			})
+			.collect();
+
+		let mut expected_output = vec![0.0; SYMBOLS];
+		expected_output[counts.iter().position(|&count| count == most).unwrap()] = 1.0;
+
+		data.push((flatten_sequence(&sequence).unwrap(), expected_output));
+	}
+	data
+}
+
+fn main() {
+	let mut network = Network::builder()
+		.default_init(InitType::Xavier)
+		.input([SEQUENCE_LENGTH, MODEL_SIZE, 1])
+		.transformer_encoder(2, 16)
+		.activation(ActivationFunction::ReLU)
+		.output(SYMBOLS)
+		.activation(ActivationFunction::Sigmoid)
+		.build()
+		.unwrap();
+
+	network.summary();
+
+	let training_data = generate_data(2000);
+	let test_data = generate_data(500);
+
+	network.sgd(training_data, Some(test_data), 10, 10, 0.1, None);
+}
@@ -0,0 +1,48 @@
+use neural_network::{
+	layer::recurrent::CellType, sequence_data, ActivationFunction, CostFunction, Float, Network,
+	SequenceData,
+};
+
+const SEQUENCE_LENGTH: usize = 10;
+
+// predicts the next value of a sine wave from the previous SEQUENCE_LENGTH values,
+// values are scaled to between 0 and 1 to match the sigmoid output
+fn main() {
+	let mut network = Network::builder()
+		.input([SEQUENCE_LENGTH, 1, 1])
+		.recurrent(CellType::LSTM, 16)
+		.truncation(5)
+		.output(1)
+		.activation(ActivationFunction::Sigmoid)
+		.cost(CostFunction::Quadratic)
+		.build()
+		.unwrap();
+
+	network.summary();
+
+	let wave = |x: Float| 0.5 + 0.4 * (x * 0.3).sin();
+	let data = |start: usize, len: usize| -> SequenceData {
+		(start..(start + len))
+			.map(|i| {
+				let sequence = (0..SEQUENCE_LENGTH)
+					.map(|t| vec![wave((i + t) as Float)])
+					.collect();
+				(sequence, vec![wave((i + SEQUENCE_LENGTH) as Float)])
+			})
+			.collect()
+	};
+
+	let training_data = sequence_data(data(0, 1000)).unwrap();
+	let test_data = sequence_data(data(1000, 200)).unwrap();
+
+	network.sgd(training_data, None, 20, 10, 0.5, None);
+
+	let error: Float = test_data
+		.iter()
+		.map(|(input, expected_output)| {
+			(network.forward(input.clone())[0] - expected_output[0]).powi(2)
+		})
+		.sum::<Float>()
+		/ test_data.len() as Float;
+	println!("Mean squared error on unseen data: {}", error);
+}
@@ -36,13 +36,13 @@ pub fn graph_results(name: &str, epochs: usize, data: Vec<(f32, f64)>, min: f64,
 			5,
 			&RED,
 			&|c, s, st| {
-				return EmptyElement::at(c)
+				EmptyElement::at(c)
 					+ Circle::new((0, 0), s, st.filled())
 					+ Text::new(
 						format!("{:.3?}", c),
 						(10, 0),
 						("sans-serif", 10).into_font(),
-					);
+					)
 			},
 		))
 		.unwrap();
@@ -1,3 +1,6 @@
+#[cfg(feature = "openblas")]
+extern crate openblas_src;
+
 mod front_end;
 mod network;
 
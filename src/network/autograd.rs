@@ -0,0 +1,265 @@
+// reverse mode automatic differentiation
+//
+// operations on a Tape record their inputs and result, backward then walks the tape in
+// reverse to find the gradient of an output with respect to every recorded value
+// so layers and costs can be written as a forward pass only
+
+use crate::network::tensor::{Layout, ShapeError, Tensor};
+use crate::network::{ActivationFunction, Float};
+
+// a value recorded on a tape
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Var(usize);
+
+#[derive(Copy, Clone, Debug)]
+enum Operation {
+	Input,
+	Activation(Var, ActivationFunction),
+	Add(Var, Var),
+	Ln(Var),
+	MatMul(Var, Var),
+	Multiply(Var, Var),
+	Scale(Var, Float),
+	Subtract(Var, Var),
+	Sum(Var),
+}
+
+struct Node {
+	operation: Operation,
+	value: Tensor,
+}
+
+#[derive(Default)]
+pub struct Tape {
+	nodes: Vec<Node>,
+}
+
+// the gradient of each value on a tape, values that the output doesn't depend on have none
+pub struct Gradients {
+	gradients: Vec<Option<Tensor>>,
+}
+
+impl Tape {
+	pub fn new() -> Self {
+		Tape::default()
+	}
+
+	// inputs, parameters and constants are all recorded the same way
+	pub fn input(&mut self, value: Tensor) -> Var {
+		self.push(Operation::Input, value)
+	}
+
+	pub fn value(&self, var: Var) -> &Tensor {
+		&self.nodes[var.0].value
+	}
+
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn activation(&mut self, a: Var, activation_function: ActivationFunction) -> Var {
+		let value = self.value(a).to_layout(Layout::ColumnMajor);
+		let output = activation_function.evaluate_all(value.as_slice());
+		let value = Tensor::new(output, value.shape()).unwrap();
+		self.push(Operation::Activation(a, activation_function), value)
+	}
+
+	pub fn add(&mut self, a: Var, b: Var) -> Result<Var, ShapeError> {
+		let value = self.value(a).add(self.value(b))?;
+		Ok(self.push(Operation::Add(a, b), value))
+	}
+
+	pub fn ln(&mut self, a: Var) -> Var {
+		let value = self.value(a).map(Float::ln);
+		self.push(Operation::Ln(a), value)
+	}
+
+	pub fn matmul(&mut self, a: Var, b: Var) -> Result<Var, ShapeError> {
+		let value = self.value(a).matmul(self.value(b))?;
+		Ok(self.push(Operation::MatMul(a, b), value))
+	}
+
+	// the elementwise product
+	pub fn multiply(&mut self, a: Var, b: Var) -> Result<Var, ShapeError> {
+		let value = self.value(a).multiply(self.value(b))?;
+		Ok(self.push(Operation::Multiply(a, b), value))
+	}
+
+	pub fn scale(&mut self, a: Var, multiplier: Float) -> Var {
+		let value = self.value(a).scale(multiplier);
+		self.push(Operation::Scale(a, multiplier), value)
+	}
+
+	pub fn subtract(&mut self, a: Var, b: Var) -> Result<Var, ShapeError> {
+		let value = self.value(a).add(&self.value(b).scale(-1.0))?;
+		Ok(self.push(Operation::Subtract(a, b), value))
+	}
+
+	// the sum of every element as a tensor of shape [1]
+	pub fn sum(&mut self, a: Var) -> Var {
+		let sum = self.value(a).as_slice().iter().sum();
+		self.push(Operation::Sum(a), Tensor::new(vec![sum], &[1]).unwrap())
+	}
+
+	// output_gradient is the gradient of the cost with respect to output,
+	// a tensor of ones gives the gradient of the sum of output
+	pub fn backward(&self, output: Var, output_gradient: Tensor) -> Result<Gradients, ShapeError> {
+		if output_gradient.shape() != self.value(output).shape() {
+			return Err(ShapeError::IncompatibleShapes {
+				operation: "backpropagate",
+				left: self.value(output).shape().to_vec(),
+				right: output_gradient.shape().to_vec(),
+			});
+		}
+
+		let mut gradients: Vec<Option<Tensor>> = vec![None; output.0 + 1];
+		gradients[output.0] = Some(output_gradient);
+
+		for index in (0..=output.0).rev() {
+			let gradient = match gradients[index].take() {
+				Some(gradient) => gradient,
+				None => continue,
+			};
+			let value = |var: Var| &self.nodes[var.0].value;
+
+			// the gradient of each input of the operation
+			let input_gradients = match self.nodes[index].operation {
+				Operation::Input => Vec::new(),
+				Operation::Activation(a, activation_function) => {
+					let values = value(a).to_layout(Layout::ColumnMajor);
+					let gradient = gradient.to_layout(Layout::ColumnMajor);
+					let input_gradient = activation_function.backward_all(
+						values.as_slice(),
+						self.nodes[index].value.as_slice(),
+						gradient.as_slice(),
+					);
+					vec![(a, Tensor::new(input_gradient, values.shape()).unwrap())]
+				}
+				Operation::Add(a, b) => vec![(a, gradient.clone()), (b, gradient.clone())],
+				Operation::Ln(a) => {
+					let reciprocal = value(a).map(|value| 1.0 / value);
+					vec![(a, gradient.multiply(&reciprocal)?)]
+				}
+				Operation::MatMul(a, b) => vec![
+					(a, gradient.matmul(&transpose(value(b)))?),
+					(b, transpose(value(a)).matmul(&gradient)?),
+				],
+				Operation::Multiply(a, b) => vec![
+					(a, gradient.multiply(value(b))?),
+					(b, gradient.multiply(value(a))?),
+				],
+				Operation::Scale(a, multiplier) => vec![(a, gradient.scale(multiplier))],
+				Operation::Subtract(a, b) => vec![(a, gradient.clone()), (b, gradient.scale(-1.0))],
+				Operation::Sum(a) => {
+					let sum_gradient = gradient.as_slice()[0];
+					let shape = value(a).shape();
+					let gradient = vec![sum_gradient; value(a).len()];
+					vec![(a, Tensor::new(gradient, shape).unwrap())]
+				}
+			};
+
+			for (var, input_gradient) in input_gradients {
+				gradients[var.0] = Some(match gradients[var.0].take() {
+					Some(gradient) => gradient.add(&input_gradient)?,
+					None => input_gradient,
+				});
+			}
+			gradients[index] = Some(gradient);
+		}
+
+		Ok(Gradients { gradients })
+	}
+
+	fn push(&mut self, operation: Operation, value: Tensor) -> Var {
+		self.nodes.push(Node { operation, value });
+		Var(self.nodes.len() - 1)
+	}
+}
+
+impl Gradients {
+	pub fn get(&self, var: Var) -> Option<&Tensor> {
+		self.gradients
+			.get(var.0)
+			.and_then(|gradient| gradient.as_ref())
+	}
+}
+
+fn transpose(tensor: &Tensor) -> Tensor {
+	tensor.view().transpose().to_tensor(Layout::ColumnMajor)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn tensor(shape: &[usize], seed: Float) -> Tensor {
+		let length = shape.iter().product::<usize>();
+		let data = (0..length)
+			.map(|i| (i as Float * 0.7 + seed).sin())
+			.collect();
+		Tensor::new(data, shape).unwrap()
+	}
+
+	// a dense layer followed by a quadratic cost
+	fn cost(tape: &mut Tape, weights: Tensor, input: Tensor) -> (Var, Var) {
+		let weights = tape.input(weights);
+		let input = tape.input(input);
+		let z = tape.matmul(weights, input).unwrap();
+		let a = tape.activation(z, ActivationFunction::Tanh);
+		let target = tape.input(Tensor::new(vec![0.5, -0.5, 0.0], &[3, 1]).unwrap());
+		let error = tape.subtract(a, target).unwrap();
+		let squared = tape.multiply(error, error).unwrap();
+		let cost = tape.sum(squared);
+		(weights, tape.scale(cost, 0.5))
+	}
+
+	#[test]
+	fn gradient_check() {
+		let weights = tensor(&[3, 4], 0.1);
+		let input = tensor(&[4, 1], 0.9);
+
+		let mut tape = Tape::new();
+		let (weights_var, cost_var) = cost(&mut tape, weights.clone(), input.clone());
+		let gradients = tape
+			.backward(cost_var, Tensor::new(vec![1.0], &[1]).unwrap())
+			.unwrap();
+		let gradient = gradients.get(weights_var).unwrap();
+		assert_eq!(gradient.shape(), &[3, 4]);
+
+		let epsilon = 1e-2;
+		for i in 0..weights.len() {
+			let cost_of = |offset: Float| {
+				let mut weights = weights.clone();
+				weights.as_mut_slice()[i] += offset;
+				let mut tape = Tape::new();
+				let (_, cost) = cost(&mut tape, weights, input.clone());
+				tape.value(cost).as_slice()[0]
+			};
+			let numerical = (cost_of(epsilon) - cost_of(-epsilon)) / (2.0 * epsilon);
+			assert!((numerical - gradient.as_slice()[i]).abs() < 1e-2);
+		}
+	}
+
+	#[test]
+	fn reused_values() {
+		// d/dx (x * x + ln(x)) = 2x + 1/x
+		let mut tape = Tape::new();
+		let x = tape.input(Tensor::new(vec![2.0], &[1]).unwrap());
+		let squared = tape.multiply(x, x).unwrap();
+		let ln = tape.ln(x);
+		let y = tape.add(squared, ln).unwrap();
+
+		let gradients = tape
+			.backward(y, Tensor::new(vec![1.0], &[1]).unwrap())
+			.unwrap();
+		assert_eq!(gradients.get(x).unwrap().as_slice(), &[4.5]);
+
+		let a = tape.input(Tensor::zeros(&[2, 3]));
+		assert!(tape.matmul(a, a).is_err());
+		assert!(tape.backward(y, Tensor::zeros(&[2])).is_err());
+	}
+}
@@ -0,0 +1,263 @@
+// pure rust versions of the BLAS routines used by utility, these are used
+// unless the openblas feature is enabled
+//
+// matrices are column major and take the same arguments as the BLAS routines,
+// trans is b'N' or b'T' and the leading dimension is the distance between columns
+
+use std::ops::{Add, AddAssign, Mul};
+
+pub trait Number: Copy + PartialEq + Add<Output = Self> + AddAssign + Mul<Output = Self> {
+	const ZERO: Self;
+}
+
+impl Number for f32 {
+	const ZERO: Self = 0.0;
+}
+
+impl Number for f64 {
+	const ZERO: Self = 0.0;
+}
+
+// block sizes for gemm, a block of a (M_BLOCK * K_BLOCK) and a column of c
+// fit in the L2 cache of most cpus for f32 and f64
+const M_BLOCK: usize = 128;
+const K_BLOCK: usize = 256;
+
+// y = alpha * x + y
+pub fn axpy<T: Number>(n: usize, alpha: T, x: &[T], incx: usize, y: &mut [T], incy: usize) {
+	for i in 0..n {
+		y[i * incy] += alpha * x[i * incx];
+	}
+}
+
+// x = alpha * x
+pub fn scal<T: Number>(n: usize, alpha: T, x: &mut [T], incx: usize) {
+	for i in 0..n {
+		x[i * incx] = alpha * x[i * incx];
+	}
+}
+
+// a = alpha * x * y^T + a where a is [m, n]
+#[allow(clippy::too_many_arguments)]
+pub fn ger<T: Number>(
+	m: usize,
+	n: usize,
+	alpha: T,
+	x: &[T],
+	incx: usize,
+	y: &[T],
+	incy: usize,
+	a: &mut [T],
+	lda: usize,
+) {
+	for j in 0..n {
+		let scale = alpha * y[j * incy];
+		if scale == T::ZERO {
+			continue;
+		}
+		let column = &mut a[(j * lda)..(j * lda + m)];
+		for (i, value) in column.iter_mut().enumerate() {
+			*value += scale * x[i * incx];
+		}
+	}
+}
+
+// y = alpha * op(a) * x + beta * y where a is [m, n]
+#[allow(clippy::too_many_arguments)]
+pub fn gemv<T: Number>(
+	trans: u8,
+	m: usize,
+	n: usize,
+	alpha: T,
+	a: &[T],
+	lda: usize,
+	x: &[T],
+	incx: usize,
+	beta: T,
+	y: &mut [T],
+	incy: usize,
+) {
+	let length = match trans {
+		b'N' => m,
+		_ => n,
+	};
+	scale_output(length, beta, y, incy);
+
+	match trans {
+		// adds each column of a scaled by x
+		b'N' => {
+			for j in 0..n {
+				let scale = alpha * x[j * incx];
+				if scale == T::ZERO {
+					continue;
+				}
+				for (i, value) in a[(j * lda)..(j * lda + m)].iter().enumerate() {
+					y[i * incy] += scale * *value;
+				}
+			}
+		}
+		// each output is the dot product of a column of a with x
+		_ => {
+			for j in 0..n {
+				let mut sum = T::ZERO;
+				for (i, value) in a[(j * lda)..(j * lda + m)].iter().enumerate() {
+					sum += *value * x[i * incx];
+				}
+				y[j * incy] += alpha * sum;
+			}
+		}
+	}
+}
+
+// c = alpha * op(a) * op(b) + beta * c where op(a) is [m, k], op(b) is [k, n] and c is [m, n]
+//
+// blocks of op(a) are copied into a contiguous buffer so the inner loop always runs down
+// a column of the block and a column of c, which the compiler can vectorise
+#[allow(clippy::too_many_arguments)]
+pub fn gemm<T: Number>(
+	transa: u8,
+	transb: u8,
+	m: usize,
+	n: usize,
+	k: usize,
+	alpha: T,
+	a: &[T],
+	lda: usize,
+	b: &[T],
+	ldb: usize,
+	beta: T,
+	c: &mut [T],
+	ldc: usize,
+) {
+	for j in 0..n {
+		scale_output(m, beta, &mut c[(j * ldc)..], 1);
+	}
+	if alpha == T::ZERO || k == 0 {
+		return;
+	}
+
+	let a_value = |i: usize, p: usize| match transa {
+		b'N' => a[i + p * lda],
+		_ => a[p + i * lda],
+	};
+	let b_value = |p: usize, j: usize| match transb {
+		b'N' => b[p + j * ldb],
+		_ => b[j + p * ldb],
+	};
+
+	let mut block = Vec::with_capacity(M_BLOCK * K_BLOCK);
+	for p_start in (0..k).step_by(K_BLOCK) {
+		let p_end = (p_start + K_BLOCK).min(k);
+		for i_start in (0..m).step_by(M_BLOCK) {
+			let i_end = (i_start + M_BLOCK).min(m);
+			let rows = i_end - i_start;
+
+			block.clear();
+			for p in p_start..p_end {
+				block.extend((i_start..i_end).map(|i| a_value(i, p)));
+			}
+
+			for j in 0..n {
+				let column = &mut c[(j * ldc + i_start)..(j * ldc + i_end)];
+				for (p, block_column) in (p_start..p_end).zip(block.chunks_exact(rows)) {
+					let scale = alpha * b_value(p, j);
+					if scale == T::ZERO {
+						continue;
+					}
+					for (value, a) in column.iter_mut().zip(block_column) {
+						*value += scale * *a;
+					}
+				}
+			}
+		}
+	}
+}
+
+// y = beta * y, following BLAS y is set to 0 when beta is 0 even if it contains NaN
+fn scale_output<T: Number>(n: usize, beta: T, y: &mut [T], incy: usize) {
+	for i in 0..n {
+		y[i * incy] = match beta == T::ZERO {
+			true => T::ZERO,
+			false => beta * y[i * incy],
+		};
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::Float;
+
+	// the value at row i and column j of a column major matrix made of sines
+	fn matrix(rows: usize, columns: usize, seed: f64) -> Vec<f64> {
+		(0..(rows * columns))
+			.map(|i| (i as f64 * 0.37 + seed).sin())
+			.collect()
+	}
+
+	#[test]
+	fn blocked_gemm() {
+		// larger than the blocks in every dimension
+		let (m, n, k) = (M_BLOCK + 17, 9, K_BLOCK + 31);
+
+		for transa in [b'N', b'T'] {
+			for transb in [b'N', b'T'] {
+				let a = matrix(m, k, 0.1);
+				let b = matrix(k, n, 0.7);
+				let (lda, ldb) = (
+					if transa == b'N' { m } else { k },
+					if transb == b'N' { k } else { n },
+				);
+				let mut c = matrix(m, n, 1.3);
+				let mut expected = c.clone();
+
+				gemm(
+					transa, transb, m, n, k, 0.5, &a, lda, &b, ldb, 2.0, &mut c, m,
+				);
+
+				for j in 0..n {
+					for i in 0..m {
+						let sum: f64 = (0..k)
+							.map(|p| {
+								let a = if transa == b'N' {
+									a[i + p * m]
+								} else {
+									a[p + i * k]
+								};
+								let b = if transb == b'N' {
+									b[p + j * k]
+								} else {
+									b[j + p * n]
+								};
+								a * b
+							})
+							.sum();
+						expected[i + j * m] = 0.5 * sum + 2.0 * expected[i + j * m];
+					}
+				}
+				for (value, expected) in c.iter().zip(expected) {
+					assert!((value - expected).abs() < 1e-9);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn zero_beta_ignores_nan() {
+		let mut c = [Float::NAN; 2];
+		gemv(
+			b'T',
+			2,
+			2,
+			1.0,
+			&[1.0, 2.0, 3.0, 4.0],
+			2,
+			&[1.0, 1.0],
+			1,
+			0.0,
+			&mut c,
+			1,
+		);
+		assert_eq!(c, [3.0, 7.0]);
+	}
+}
@@ -0,0 +1,681 @@
+use crate::network::layer::{
+	attention::AttentionInfo,
+	batchnorm::BatchNormInfo,
+	conv1d::{Conv1DInfo, Padding},
+	convtranspose2d::ConvTranspose2DInfo,
+	dropout::DropoutInfo,
+	embedding::EmbeddingInfo,
+	feedforward::FeedForwardInfo,
+	inputlayer::InputLayerInfo,
+	layernorm::{LayerNormInfo, NormType},
+	merge::{MergeInfo, MergeType},
+	outputlayer::OutputLayerInfo,
+	pooling::{GlobalPoolInfo, PoolType},
+	recurrent::{CellType, RecurrentInfo},
+	reshape::ReshapeInfo,
+	transformer::TransformerEncoderInfo,
+	upsample::{UpsampleInfo, UpsampleType},
+	CustomLayerInfo, LayerInfo, LayerInfoTrait,
+};
+use crate::network::{ActivationFunction, CostFunction, Float, InitType, Network, NetworkError};
+
+/// Builds a `Network` one layer at a time.
+///
+/// Options such as `activation` or `init` apply to the most recently added layer,
+/// layers that aren't given an option use the builder defaults.
+///
+/// Each layer takes its input from the layer added before it unless `inputs` is used,
+/// layers are refered to by the order they were added in (the input layer is 0).
+/// ```
+/// # use neural_network::{ActivationFunction, CostFunction, InitType, Network, NetworkError};
+/// # fn main() -> Result<(), NetworkError> {
+/// let network = Network::builder()
+///     .input([28, 28, 1])
+///     .dense(30)
+///     .activation(ActivationFunction::Sigmoid)
+///     .init(InitType::He)
+///     .output(10)
+///     .cost(CostFunction::CrossEntropy)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NetworkBuilder {
+	activation_function: ActivationFunction,
+	connections: Vec<Vec<usize>>,
+	cost_function: CostFunction,
+	error: Option<NetworkError>,
+	init_type: InitType,
+	layer_infos: Vec<LayerInfo>,
+}
+
+impl NetworkBuilder {
+	pub fn new() -> Self {
+		NetworkBuilder {
+			activation_function: ActivationFunction::Sigmoid,
+			connections: Vec::new(),
+			cost_function: CostFunction::CrossEntropy,
+			error: None,
+			init_type: InitType::NormalisedXavier,
+			layer_infos: Vec::new(),
+		}
+	}
+
+	// defaults only apply to layers added after they are set
+	pub fn default_activation(mut self, activation_function: ActivationFunction) -> Self {
+		self.activation_function = activation_function;
+		self
+	}
+
+	pub fn default_cost(mut self, cost_function: CostFunction) -> Self {
+		self.cost_function = cost_function;
+		self
+	}
+
+	pub fn default_init(mut self, init_type: InitType) -> Self {
+		self.init_type = init_type;
+		self
+	}
+
+	pub fn input(self, sizes: [usize; 3]) -> Self {
+		self.layer(LayerInfo::InputLayer(InputLayerInfo::new(sizes)))
+	}
+
+	pub fn batch_norm(self) -> Self {
+		self.layer(LayerInfo::BatchNorm(BatchNormInfo::new()))
+	}
+
+	pub fn layer_norm(self) -> Self {
+		self.layer(LayerInfo::LayerNorm(LayerNormInfo::new(NormType::Layer)))
+	}
+
+	pub fn rms_norm(self) -> Self {
+		self.layer(LayerInfo::LayerNorm(LayerNormInfo::new(NormType::RMS)))
+	}
+
+	pub fn dropout(self, rate: Float) -> Self {
+		self.layer(LayerInfo::Dropout(DropoutInfo::new(rate)))
+	}
+
+	// reduces [w, h, c] inputs to [c, 1, 1]
+	pub fn global_average_pool(self) -> Self {
+		self.layer(LayerInfo::GlobalPool(GlobalPoolInfo::new(
+			PoolType::Average,
+		)))
+	}
+
+	pub fn global_max_pool(self) -> Self {
+		self.layer(LayerInfo::GlobalPool(GlobalPoolInfo::new(PoolType::Max)))
+	}
+
+	// outputs the expected x and y position of each channel ([2c, 1, 1])
+	pub fn spatial_softmax(self) -> Self {
+		self.layer(LayerInfo::GlobalPool(GlobalPoolInfo::new(
+			PoolType::SpatialSoftmax,
+		)))
+	}
+
+	pub fn flatten(self) -> Self {
+		self.layer(LayerInfo::Reshape(ReshapeInfo::flatten()))
+	}
+
+	// shape must have the same number of values as the input
+	pub fn reshape(self, shape: [usize; 3]) -> Self {
+		self.layer(LayerInfo::Reshape(ReshapeInfo::new(shape)))
+	}
+
+	// input should be token ids with the shape [seq_len, 1, 1]
+	pub fn embedding(self, vocabulary: usize, dimensions: usize) -> Self {
+		let info = EmbeddingInfo::new(vocabulary, dimensions, self.init_type);
+		self.layer(LayerInfo::Embedding(info))
+	}
+
+	pub fn dense(self, length: usize) -> Self {
+		let info = FeedForwardInfo::new(self.activation_function, self.init_type, length);
+		self.layer(LayerInfo::FeedForward(info))
+	}
+
+	// input should have the shape [length, channels, 1]
+	pub fn conv1d(self, filters: usize, kernel: usize) -> Self {
+		let info = Conv1DInfo::new(self.activation_function, self.init_type, filters, kernel);
+		self.layer(LayerInfo::Conv1D(info))
+	}
+
+	// kernel is [width, height], use stride to increase the width and height of the input
+	pub fn conv_transpose2d(self, filters: usize, kernel: [usize; 2]) -> Self {
+		let info =
+			ConvTranspose2DInfo::new(self.activation_function, self.init_type, filters, kernel);
+		self.layer(LayerInfo::ConvTranspose2D(info))
+	}
+
+	pub fn upsample(self, upsample_type: UpsampleType, scale: usize) -> Self {
+		self.layer(LayerInfo::Upsample(UpsampleInfo::new(upsample_type, scale)))
+	}
+
+	pub fn output(self, length: usize) -> Self {
+		let info = OutputLayerInfo::new(
+			self.activation_function,
+			self.cost_function,
+			self.init_type,
+			length,
+		);
+		self.layer(LayerInfo::OutputLayer(info))
+	}
+
+	// input should have the shape [seq_len, features, 1]
+	pub fn recurrent(self, cell_type: CellType, length: usize) -> Self {
+		let info = RecurrentInfo::new(cell_type, self.activation_function, self.init_type, length);
+		self.layer(LayerInfo::Recurrent(info))
+	}
+
+	// input should have the shape [seq_len, d_model, 1]
+	pub fn attention(self, heads: usize) -> Self {
+		let info = AttentionInfo::new(heads, self.init_type);
+		self.layer(LayerInfo::Attention(info))
+	}
+
+	// hidden is the size of the feed-forward network applied to each timestep
+	pub fn transformer_encoder(self, heads: usize, hidden: usize) -> Self {
+		let info =
+			TransformerEncoderInfo::new(heads, hidden, self.activation_function, self.init_type);
+		self.layer(LayerInfo::TransformerEncoder(info))
+	}
+
+	pub fn custom<T: CustomLayerInfo + 'static>(self, layer_info: T) -> Self {
+		self.layer(LayerInfo::Custom(Box::new(layer_info)))
+	}
+
+	// merges the output of the previous layer with the outputs of other_inputs
+	pub fn merge(self, merge_type: MergeType, other_inputs: &[usize]) -> Self {
+		let previous = self.layer_infos.len().saturating_sub(1);
+		let inputs: Vec<usize> = std::iter::once(previous)
+			.chain(other_inputs.iter().copied())
+			.collect();
+
+		self.layer(LayerInfo::Merge(MergeInfo::new(merge_type)))
+			.inputs(&inputs)
+	}
+
+	// same as merge with MergeType::Concatenate
+	pub fn concatenate(self, other_inputs: &[usize]) -> Self {
+		self.merge(MergeType::Concatenate, other_inputs)
+	}
+
+	pub fn layer(mut self, layer_info: LayerInfo) -> Self {
+		let index = self.layer_infos.len();
+		self.connections.push(match index {
+			0 => Vec::new(),
+			_ => vec![index - 1],
+		});
+		self.layer_infos.push(layer_info);
+		self
+	}
+
+	// index of the last layer added, used with inputs and merge
+	pub fn last_index(&self) -> usize {
+		self.layer_infos.len().saturating_sub(1)
+	}
+
+	pub fn inputs(mut self, inputs: &[usize]) -> Self {
+		if self.error.is_some() {
+			return self;
+		}
+
+		self.error = match self.connections.len() {
+			0 => Some(NetworkError::OptionWithoutLayer { option: "inputs" }),
+			1 => Some(NetworkError::InvalidOption {
+				index: 0,
+				option: "inputs",
+			}),
+			len => {
+				self.connections[len - 1] = inputs.to_vec();
+				None
+			}
+		};
+		self
+	}
+
+	pub fn activation(self, activation_function: ActivationFunction) -> Self {
+		self.set_option("activation", |info| match info {
+			LayerInfo::FeedForward(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			LayerInfo::OutputLayer(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			LayerInfo::Conv1D(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			LayerInfo::ConvTranspose2D(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			LayerInfo::Recurrent(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			LayerInfo::TransformerEncoder(info) => {
+				info.activation_function = activation_function;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	pub fn bias(self, bias: Float) -> Self {
+		self.set_option("bias", |info| match info {
+			LayerInfo::FeedForward(info) => {
+				info.bias = bias;
+				true
+			}
+			LayerInfo::OutputLayer(info) => {
+				info.bias = bias;
+				true
+			}
+			LayerInfo::Conv1D(info) => {
+				info.bias = bias;
+				true
+			}
+			LayerInfo::ConvTranspose2D(info) => {
+				info.bias = bias;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	pub fn cost(self, cost_function: CostFunction) -> Self {
+		self.set_option("cost", |info| match info {
+			LayerInfo::OutputLayer(info) => {
+				info.cost_function = cost_function;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	pub fn init(self, init_type: InitType) -> Self {
+		self.set_option("init", |info| match info {
+			LayerInfo::FeedForward(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::OutputLayer(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::Attention(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::Conv1D(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::ConvTranspose2D(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::Embedding(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::Recurrent(info) => {
+				info.init_type = init_type;
+				true
+			}
+			LayerInfo::TransformerEncoder(info) => {
+				info.init_type = init_type;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	// outputs the hidden state of every timestep so recurrent layers can be stacked
+	pub fn return_sequences(self) -> Self {
+		self.set_option("return_sequences", |info| match info {
+			LayerInfo::Recurrent(info) => {
+				info.return_sequences = true;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	// limits backpropagation through time to blocks of steps timesteps
+	pub fn truncation(self, steps: usize) -> Self {
+		self.set_option("truncation", |info| match info {
+			LayerInfo::Recurrent(info) => {
+				info.truncation = Some(steps);
+				true
+			}
+			_ => false,
+		})
+	}
+
+	pub fn stride(self, stride: usize) -> Self {
+		self.set_option("stride", |info| match info {
+			LayerInfo::Conv1D(info) => {
+				info.stride = stride;
+				true
+			}
+			LayerInfo::ConvTranspose2D(info) => {
+				info.stride = stride;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	pub fn dilation(self, dilation: usize) -> Self {
+		self.set_option("dilation", |info| match info {
+			LayerInfo::Conv1D(info) => {
+				info.dilation = dilation;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	// the padding of 1D convolutions
+	pub fn padding(self, padding: Padding) -> Self {
+		self.set_option("padding", |info| match info {
+			LayerInfo::Conv1D(info) => {
+				info.padding = padding;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	// the number of positions removed from each side of the output of transposed convolutions
+	pub fn crop(self, padding: usize) -> Self {
+		self.set_option("crop", |info| match info {
+			LayerInfo::ConvTranspose2D(info) => {
+				info.padding = padding;
+				true
+			}
+			_ => false,
+		})
+	}
+
+	// applies an option to the last layer, set_fn returns false if the option doesn't apply
+	fn set_option<F>(mut self, option: &'static str, set_fn: F) -> Self
+	where
+		F: FnOnce(&mut LayerInfo) -> bool,
+	{
+		if self.error.is_some() {
+			return self;
+		}
+
+		let index = self.layer_infos.len();
+		self.error = match self.layer_infos.last_mut() {
+			Some(info) => {
+				if set_fn(info) {
+					None
+				} else {
+					Some(NetworkError::InvalidOption {
+						index: index - 1,
+						option,
+					})
+				}
+			}
+			None => Some(NetworkError::OptionWithoutLayer { option }),
+		};
+		self
+	}
+
+	pub fn build(self) -> Result<Network, NetworkError> {
+		if let Some(error) = self.error {
+			return Err(error);
+		}
+
+		let mut layer_infos = self.layer_infos;
+		let info_len = layer_infos.len();
+
+		if info_len == 0 {
+			return Err(NetworkError::EmptyNetwork);
+		}
+
+		if !matches!(layer_infos[0], LayerInfo::InputLayer(_)) {
+			return Err(NetworkError::FirstLayerNotInput);
+		}
+
+		for (index, info) in layer_infos.iter().enumerate() {
+			match info {
+				LayerInfo::InputLayer(_) if index != 0 => {
+					return Err(NetworkError::InputLayerNotFirst { index });
+				}
+				LayerInfo::OutputLayer(_) if index != info_len - 1 => {
+					return Err(NetworkError::OutputLayerNotLast { index });
+				}
+				_ => {}
+			}
+		}
+
+		if !matches!(layer_infos[info_len - 1], LayerInfo::OutputLayer(_)) {
+			return Err(NetworkError::LastLayerNotOutput);
+		}
+
+		let connections = self.connections;
+		for (index, inputs) in connections.iter().enumerate() {
+			if let Some(&input) = inputs.iter().find(|&&input| input >= index) {
+				return Err(NetworkError::InvalidConnection { index, input });
+			}
+		}
+
+		// token ids only come from the input layer, the output of other layers isn't ids
+		for (index, info) in layer_infos.iter().enumerate() {
+			if let LayerInfo::Embedding(_) = info {
+				if let Some(&input) = connections[index].iter().find(|&&input| input != 0) {
+					return Err(NetworkError::EmbeddingInput { index, input });
+				}
+			}
+		}
+
+		for index in 0..(info_len - 1) {
+			if !connections.iter().any(|inputs| inputs.contains(&index)) {
+				return Err(NetworkError::UnusedLayer { index });
+			}
+		}
+
+		let mut layers = Vec::with_capacity(info_len);
+		let mut shapes: Vec<[usize; 3]> = Vec::with_capacity(info_len);
+
+		for (index, info) in layer_infos.iter_mut().enumerate() {
+			let input_shapes: Vec<[usize; 3]> = connections[index]
+				.iter()
+				.map(|&input| shapes[input])
+				.collect();
+
+			info.check_parameters()
+				.map_err(|reason| NetworkError::InvalidParameter { index, reason })?;
+			if let Some(activation) = info.activation_function() {
+				if !info.supports_activation(activation) {
+					return Err(NetworkError::InvalidActivation { index, activation });
+				}
+			}
+
+			// the input layer has no previous layer to check against
+			if index != 0 {
+				info.set_inputs(&input_shapes).map_err(|reason| {
+					NetworkError::IncompatibleShape {
+						index,
+						inputs: input_shapes.clone(),
+						reason,
+					}
+				})?;
+			}
+
+			let shape = info.output();
+			if shape.contains(&0) {
+				return Err(NetworkError::ZeroSizedLayer { index, shape });
+			}
+
+			layers.push(info.create_layer(&input_shapes));
+
+			shapes.push(shape);
+		}
+
+		Ok(Network::from_layers(layers, connections))
+	}
+}
+
+impl Default for NetworkBuilder {
+	fn default() -> Self {
+		NetworkBuilder::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn builder_matches_macros() {
+		let network = Network::builder()
+			.input([784, 1, 1])
+			.dense(30)
+			.output(10)
+			.build()
+			.unwrap();
+
+		let macro_network = Network::new(vec![
+			crate::input!(784),
+			crate::feedforward!(ActivationFunction::Sigmoid, InitType::NormalisedXavier, 30),
+			crate::output!(
+				ActivationFunction::Sigmoid,
+				CostFunction::CrossEntropy,
+				InitType::NormalisedXavier,
+				10
+			),
+		])
+		.unwrap();
+
+		assert_eq!(network.layers.len(), macro_network.layers.len());
+	}
+
+	#[test]
+	fn bias_option() {
+		let mut network = Network::builder()
+			.input([1, 1, 1])
+			.output(1)
+			.bias(2.0)
+			.build()
+			.unwrap();
+
+		// with an input of 0 the output only depends on the bias
+		assert_eq!(
+			network.forward(vec![0.0])[0],
+			1.0 / (1.0 + (-2.0 as Float).exp())
+		);
+	}
+
+	#[test]
+	fn invalid_options() {
+		assert_eq!(
+			Network::builder()
+				.activation(ActivationFunction::Sigmoid)
+				.build()
+				.err(),
+			Some(NetworkError::OptionWithoutLayer {
+				option: "activation"
+			})
+		);
+		assert_eq!(
+			Network::builder()
+				.input([2, 1, 1])
+				.dense(3)
+				.cost(CostFunction::Quadratic)
+				.output(1)
+				.build()
+				.err(),
+			Some(NetworkError::InvalidOption {
+				index: 1,
+				option: "cost"
+			})
+		);
+	}
+
+	#[test]
+	fn embedding_input() {
+		let result = Network::builder()
+			.input([2, 1, 1])
+			.dense(2)
+			.embedding(4, 3)
+			.output(2)
+			.build();
+		assert_eq!(
+			result.err(),
+			Some(NetworkError::EmbeddingInput { index: 2, input: 1 })
+		);
+	}
+
+	#[test]
+	fn invalid_parameters() {
+		let result = Network::builder()
+			.input([2, 1, 1])
+			.dense(3)
+			.dropout(1.0)
+			.output(1)
+			.build();
+		assert!(matches!(
+			result,
+			Err(NetworkError::InvalidParameter { index: 2, .. })
+		));
+
+		let mut batch_norm = BatchNormInfo::new();
+		batch_norm.momentum = 1.5;
+		let result = Network::builder()
+			.input([2, 1, 1])
+			.layer(LayerInfo::BatchNorm(batch_norm))
+			.output(1)
+			.build();
+		assert!(matches!(
+			result,
+			Err(NetworkError::InvalidParameter { index: 1, .. })
+		));
+	}
+
+	#[test]
+	fn softmax_rejected() {
+		let builders = [
+			Network::builder().input([8, 2, 1]).conv1d(2, 3),
+			Network::builder()
+				.input([4, 4, 1])
+				.conv_transpose2d(2, [2, 2]),
+			Network::builder()
+				.input([4, 2, 1])
+				.recurrent(CellType::RNN, 3),
+			Network::builder()
+				.input([4, 4, 1])
+				.transformer_encoder(2, 8),
+		];
+		for builder in builders {
+			let result = builder
+				.activation(ActivationFunction::Softmax)
+				.output(2)
+				.build();
+			assert_eq!(
+				result.err(),
+				Some(NetworkError::InvalidActivation {
+					index: 1,
+					activation: ActivationFunction::Softmax
+				})
+			);
+		}
+
+		// lstm cells don't use the activation function
+		assert!(Network::builder()
+			.input([4, 2, 1])
+			.recurrent(CellType::LSTM, 3)
+			.activation(ActivationFunction::Softmax)
+			.output(2)
+			.build()
+			.is_ok());
+	}
+}
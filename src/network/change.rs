@@ -29,3 +29,32 @@ impl OutputLayerChange {
 		}
 	}
 }
+
+pub struct NormalisationChange {
+	pub gamma: Vec<Float>,
+	pub beta: Vec<Float>,
+}
+
+impl NormalisationChange {
+	pub fn new(features: usize) -> Self {
+		NormalisationChange {
+			gamma: vec![0.0; features],
+			beta: vec![0.0; features],
+		}
+	}
+}
+
+// shared by the convolution, recurrent, attention and transformer layers
+pub struct ParameterChange {
+	pub weights: Vec<Float>,
+	pub biases: Vec<Float>,
+}
+
+impl ParameterChange {
+	pub fn new(weights: usize, biases: usize) -> Self {
+		ParameterChange {
+			weights: vec![0.0; weights],
+			biases: vec![0.0; biases],
+		}
+	}
+}
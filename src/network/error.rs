@@ -0,0 +1,187 @@
+use crate::network::ActivationFunction;
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkError {
+	EmptyNetwork,
+	FirstLayerNotInput,
+	InputLayerNotFirst {
+		index: usize,
+	},
+	OutputLayerNotLast {
+		index: usize,
+	},
+	LastLayerNotOutput,
+	ZeroSizedLayer {
+		index: usize,
+		shape: [usize; 3],
+	},
+	IncompatibleShape {
+		index: usize,
+		inputs: Vec<[usize; 3]>,
+		reason: String,
+	},
+	InvalidConnection {
+		index: usize,
+		input: usize,
+	},
+	EmbeddingInput {
+		index: usize,
+		input: usize,
+	},
+	InvalidParameter {
+		index: usize,
+		reason: String,
+	},
+	InvalidActivation {
+		index: usize,
+		activation: ActivationFunction,
+	},
+	UnusedLayer {
+		index: usize,
+	},
+	InvalidOption {
+		index: usize,
+		option: &'static str,
+	},
+	OptionWithoutLayer {
+		option: &'static str,
+	},
+	InputShape {
+		expected: [usize; 3],
+		found: Vec<usize>,
+	},
+	InvalidToken {
+		index: usize,
+		token: usize,
+		vocabulary: usize,
+	},
+	SparseInputUnsupported,
+	RaggedSequence {
+		timestep: usize,
+		features: usize,
+		expected: usize,
+	},
+	TensorShape {
+		layer: String,
+		tensor: &'static str,
+		shape: Vec<usize>,
+		expected: Vec<usize>,
+	},
+}
+
+impl fmt::Display for NetworkError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			NetworkError::EmptyNetwork => write!(f, "can't create a network with no layers"),
+			NetworkError::FirstLayerNotInput => {
+				write!(f, "the first layer (layer 0) must be an input layer")
+			}
+			NetworkError::InputLayerNotFirst { index } => write!(
+				f,
+				"layer {} is an input layer but only the first layer can be an input layer",
+				index
+			),
+			NetworkError::OutputLayerNotLast { index } => write!(
+				f,
+				"layer {} is an output layer but only the last layer can be an output layer",
+				index
+			),
+			NetworkError::LastLayerNotOutput => {
+				write!(f, "the last layer must be an output layer")
+			}
+			NetworkError::ZeroSizedLayer { index, shape } => write!(
+				f,
+				"layer {} has an output shape of {:?} which contains a zero sized dimension",
+				index, shape
+			),
+			NetworkError::IncompatibleShape {
+				index,
+				inputs,
+				reason,
+			} => {
+				let shapes: Vec<String> =
+					inputs.iter().map(|shape| format!("{:?}", shape)).collect();
+				write!(
+					f,
+					"layer {} can't take input(s) of shape {}: {}",
+					index,
+					shapes.join(", "),
+					reason
+				)
+			}
+			NetworkError::InvalidConnection { index, input } => write!(
+				f,
+				"layer {} can't take input from layer {}, layers can only take input from earlier layers",
+				index, input
+			),
+			NetworkError::EmbeddingInput { index, input } => write!(
+				f,
+				"layer {} is an embedding layer so must take token ids from the input layer but takes input from layer {}",
+				index, input
+			),
+			NetworkError::InvalidParameter { index, reason } => {
+				write!(f, "layer {} has an invalid parameter: {}", index, reason)
+			}
+			NetworkError::InvalidActivation { index, activation } => write!(
+				f,
+				"layer {} can't use the {:?} activation function as it applies it to each value separately",
+				index, activation
+			),
+			NetworkError::UnusedLayer { index } => write!(
+				f,
+				"the output of layer {} isn't used by any other layer",
+				index
+			),
+			NetworkError::InvalidOption { index, option } => {
+				write!(f, "option `{}` doesn't apply to layer {}", option, index)
+			}
+			NetworkError::OptionWithoutLayer { option } => write!(
+				f,
+				"option `{}` was set before any layers were added",
+				option
+			),
+			NetworkError::InputShape { expected, found } => write!(
+				f,
+				"the network takes input of shape {:?} but was given a tensor of shape {:?}",
+				expected, found
+			),
+			NetworkError::InvalidToken {
+				index,
+				token,
+				vocabulary,
+			} => write!(
+				f,
+				"token {} is outside the vocabulary of {} tokens of layer {}",
+				token, vocabulary, index
+			),
+			NetworkError::SparseInputUnsupported => write!(
+				f,
+				"sparse input needs layer 1 to be a dense layer and the only layer that takes input from the input layer"
+			),
+			NetworkError::RaggedSequence {
+				timestep,
+				features,
+				expected,
+			} => write!(
+				f,
+				"timestep {} has {} features but timestep 0 has {}, every timestep must have the same number of features",
+				timestep, features, expected
+			),
+			NetworkError::TensorShape {
+				layer,
+				tensor,
+				shape,
+				expected,
+			} => write!(
+				f,
+				"the {} of a {} layer has the shape {:?} but should have the shape {:?}",
+				tensor, layer, shape, expected
+			),
+		}
+	}
+}
+
+impl Error for NetworkError {}
@@ -0,0 +1,47 @@
+// data and parameters shared by the tests of the quantised, pruned, sparse and mixed precision
+// networks
+
+use crate::network::layer::LayerTrait;
+use crate::network::{Float, Network, NetworkData};
+
+// length samples with features inputs from value(sample, feature) split into two classes by
+// whether the sum of the first summed inputs is positive
+pub fn labelled_data(
+	length: usize,
+	features: usize,
+	summed: usize,
+	value: impl Fn(usize, usize) -> Float,
+) -> NetworkData {
+	(0..length)
+		.map(|i| {
+			let input: Vec<Float> = (0..features).map(|j| value(i, j)).collect();
+			let class = (input[..summed].iter().sum::<Float>() > 0.0) as usize;
+			let mut expected = vec![0.0; 2];
+			expected[class] = 1.0;
+			(input, expected)
+		})
+		.collect()
+}
+
+// 6 inputs classified by the sign of the sum of the first two
+pub fn data(length: usize) -> NetworkData {
+	labelled_data(length, 6, 2, |i, j| ((i * 6 + j) as Float * 0.37).sin())
+}
+
+// fixed parameters so tests don't depend on the random initialisation
+pub fn fix_parameters(network: &mut Network) {
+	for (i, parameter) in network
+		.layers
+		.iter_mut()
+		.flat_map(|layer| layer.parameters_mut())
+		.flat_map(|parameters| parameters.iter_mut())
+		.enumerate()
+	{
+		*parameter = (i as Float * 0.61).sin() * 0.5;
+	}
+}
+
+// every parameter of network in order
+pub fn parameters(network: &mut Network) -> Vec<Float> {
+	network.parameter_snapshot().concat()
+}
@@ -0,0 +1,360 @@
+use crate::network::layer::{shaped, Layer, LayerTrait};
+use crate::network::{Float, Network};
+
+// the gradient of the cost with respect to one parameter from backpropagation and from
+// finite differences, index is the position of the parameter in the layer's parameters_mut
+#[derive(Copy, Clone, Debug)]
+pub struct ParameterGradient {
+	pub backpropagation: Float,
+	pub index: usize,
+	pub layer: usize,
+	pub numerical: Float,
+}
+
+impl ParameterGradient {
+	pub fn error(&self) -> Float {
+		relative_error(self.backpropagation, self.numerical)
+	}
+}
+
+// the difference relative to the size of the gradients, gradients smaller than 0.01 are
+// compared with 0.01 so rounding errors in tiny gradients don't count
+pub(crate) fn relative_error(backpropagation: Float, numerical: Float) -> Float {
+	(backpropagation - numerical).abs() / backpropagation.abs().max(numerical.abs()).max(1e-2)
+}
+
+// compares the gradients found by backpropagation with the central differences
+// (cost(p + epsilon) - cost(p - epsilon)) / (2 * epsilon) for every parameter of the network
+//
+// the network is run in evaluation mode so dropout is turned off and batch norm uses its
+// running statistics, recurrent layers with truncation aren't expected to match
+pub fn gradient_check(
+	network: &mut Network,
+	input: &[Float],
+	target: &[Float],
+	epsilon: Float,
+) -> Vec<ParameterGradient> {
+	let training = network.training();
+	network.set_training(false);
+	let gradients = compare(network, &[(input.to_vec(), target.to_vec())], epsilon);
+	network.set_training(training);
+	gradients
+}
+
+// the same as gradient_check in training mode with the cost summed over a mini batch so batch
+// norm uses the statistics of the mini batch, dropout isn't expected to match
+pub fn gradient_check_batch(
+	network: &mut Network,
+	mini_batch: &[(Vec<Float>, Vec<Float>)],
+	epsilon: Float,
+) -> Vec<ParameterGradient> {
+	let training = network.training();
+	network.set_training(true);
+	let gradients = compare(network, mini_batch, epsilon);
+	network.set_training(training);
+	gradients
+}
+
+fn compare(
+	network: &mut Network,
+	mini_batch: &[(Vec<Float>, Vec<Float>)],
+	epsilon: Float,
+) -> Vec<ParameterGradient> {
+	let original: Vec<Vec<Float>> = network.layers.iter_mut().map(parameters).collect();
+
+	// with a learning rate of 1 and a mini batch size of 1 update subtracts the gradient
+	network.backpropagation(mini_batch);
+	network.apply_layer_changes(1.0, 1);
+
+	let updated: Vec<Vec<Float>> = network.layers.iter_mut().map(parameters).collect();
+	for (layer, original) in network.layers.iter_mut().zip(&original) {
+		set_parameters(layer, original);
+	}
+
+	let mut gradients = Vec::new();
+	for (layer, (original, updated)) in original.iter().zip(updated).enumerate() {
+		for (index, (parameter, updated)) in original.iter().zip(updated).enumerate() {
+			let mut cost = |value: Float| {
+				*parameter_mut(&mut network.layers[layer], index) = value;
+				cost(network, mini_batch)
+			};
+			let numerical =
+				(cost(parameter + epsilon) - cost(parameter - epsilon)) / (2.0 * epsilon);
+			*parameter_mut(&mut network.layers[layer], index) = *parameter;
+
+			gradients.push(ParameterGradient {
+				backpropagation: parameter - updated,
+				index,
+				layer,
+				numerical,
+			});
+		}
+	}
+	gradients
+}
+
+// the cost summed over a mini batch that is run through the network together
+fn cost(network: &mut Network, mini_batch: &[(Vec<Float>, Vec<Float>)]) -> Float {
+	let shape = network.layers[0].info().output();
+	let mut outputs = vec![network.layers[0].forward_batch(
+		mini_batch
+			.iter()
+			.map(|data| vec![shaped(data.0.clone(), &shape)])
+			.collect(),
+	)];
+	for index in 1..network.layers.len() - 1 {
+		let layer_inputs = (0..mini_batch.len())
+			.map(|sample| {
+				network.connections[index]
+					.iter()
+					.map(|&input| outputs[input][sample].clone())
+					.collect()
+			})
+			.collect();
+		outputs.push(network.layers[index].forward_batch(layer_inputs));
+	}
+
+	let last_input = *network.connections.last().unwrap().first().unwrap();
+	match network.layers.last_mut() {
+		Some(Layer::OutputLayer(output)) => outputs[last_input]
+			.iter()
+			.zip(mini_batch)
+			.map(|(input, (_, target))| {
+				output.forward(input.clone());
+				output.cost(target)
+			})
+			.sum(),
+		_ => unreachable!("the last layer of a network is always an output layer"),
+	}
+}
+
+fn parameters(layer: &mut Layer) -> Vec<Float> {
+	layer.parameters_mut().concat()
+}
+
+pub(crate) fn set_parameters(layer: &mut (impl LayerTrait + ?Sized), values: &[Float]) {
+	for (parameter, value) in layer
+		.parameters_mut()
+		.into_iter()
+		.flat_map(|parameters| parameters.iter_mut())
+		.zip(values)
+	{
+		*parameter = *value;
+	}
+}
+
+pub(crate) fn parameter_mut(layer: &mut (impl LayerTrait + ?Sized), index: usize) -> &mut Float {
+	layer
+		.parameters_mut()
+		.into_iter()
+		.flat_map(|parameters| parameters.iter_mut())
+		.nth(index)
+		.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{merge::MergeType, recurrent::CellType, upsample::UpsampleType};
+	use crate::network::{ActivationFunction, CostFunction, NetworkBuilder};
+
+	// fixed parameters so the check doesn't depend on whether a random
+	// initialisation puts a value close to the kink of ReLU or max pooling
+	fn network(builder: NetworkBuilder) -> Network {
+		let mut network = builder.build().unwrap();
+		for (layer_index, layer) in network.layers.iter_mut().enumerate() {
+			for (i, parameter) in layer
+				.parameters_mut()
+				.into_iter()
+				.flat_map(|parameters| parameters.iter_mut())
+				.enumerate()
+			{
+				*parameter = ((i * 7 + layer_index * 13) as Float * 0.67).sin() * 0.5;
+			}
+		}
+		network
+	}
+
+	fn assert_gradients(network: &Network, gradients: Vec<ParameterGradient>) {
+		assert_eq!(gradients.len(), network.parameter_count());
+		for gradient in gradients {
+			let layer = network.layers().nth(gradient.layer).unwrap();
+			assert!(
+				gradient.error() < 2e-2,
+				"{} (layer {}): {:?}",
+				layer.info().name(),
+				gradient.layer,
+				gradient
+			);
+		}
+	}
+
+	fn check(builder: NetworkBuilder, input: &[Float], target: &[Float]) {
+		let mut network = network(builder);
+		let gradients = gradient_check(&mut network, input, target, 3e-3);
+		assert_gradients(&network, gradients);
+	}
+
+	fn input(length: usize) -> Vec<Float> {
+		(0..length).map(|i| (i as Float * 0.7).sin()).collect()
+	}
+
+	#[test]
+	fn relative_error_floor() {
+		assert!(relative_error(100.0, 101.0) < 2e-2);
+		assert!(relative_error(0.5, 0.52) > 2e-2);
+		// tiny gradients are compared with the floor rather than with each other
+		assert!(relative_error(1e-4, 2e-4) < 2e-2);
+	}
+
+	#[test]
+	fn activations_and_costs() {
+		let combinations = [
+			(ActivationFunction::Sigmoid, CostFunction::CrossEntropy),
+			(ActivationFunction::Sigmoid, CostFunction::LogLikelyhood),
+			(ActivationFunction::Sigmoid, CostFunction::Quadratic),
+			(ActivationFunction::Softmax, CostFunction::CrossEntropy),
+			(ActivationFunction::Softmax, CostFunction::LogLikelyhood),
+			(ActivationFunction::Softmax, CostFunction::Quadratic),
+			(ActivationFunction::Tanh, CostFunction::Quadratic),
+			(ActivationFunction::ReLU, CostFunction::Quadratic),
+		];
+
+		for hidden in [
+			ActivationFunction::ReLU,
+			ActivationFunction::Sigmoid,
+			ActivationFunction::Tanh,
+		] {
+			for &(activation_function, cost_function) in &combinations {
+				let builder = Network::builder()
+					.input([4, 1, 1])
+					.dense(5)
+					.activation(hidden)
+					.output(3)
+					.activation(activation_function)
+					.cost(cost_function);
+				check(builder, &input(4), &[0.0, 1.0, 0.0]);
+			}
+		}
+	}
+
+	#[test]
+	fn normalisation_layers() {
+		let builder = Network::builder()
+			.input([3, 2, 2])
+			.batch_norm()
+			.layer_norm()
+			.dropout(0.5)
+			.rms_norm()
+			.output(2);
+		check(builder, &input(12), &[1.0, 0.0]);
+	}
+
+	#[test]
+	fn batch_norm_training() {
+		let mut network = network(
+			Network::builder()
+				.input([3, 1, 1])
+				.dense(4)
+				.activation(ActivationFunction::Tanh)
+				.batch_norm()
+				.output(2),
+		);
+		let mini_batch: Vec<(Vec<Float>, Vec<Float>)> = (0..4)
+			.map(|i| {
+				let target = (i % 2) as Float;
+				(input(3 + i)[i..].to_vec(), vec![target, 1.0 - target])
+			})
+			.collect();
+
+		let gradients = gradient_check_batch(&mut network, &mini_batch, 3e-3);
+		assert!(!network.training());
+		assert_gradients(&network, gradients);
+	}
+
+	#[test]
+	fn sequence_layers() {
+		for cell_type in [CellType::RNN, CellType::LSTM, CellType::GRU] {
+			let builder = Network::builder()
+				.input([4, 2, 1])
+				.recurrent(cell_type, 4)
+				.activation(ActivationFunction::Tanh)
+				.return_sequences()
+				.attention(2)
+				.transformer_encoder(2, 6)
+				.activation(ActivationFunction::Tanh)
+				.output(2);
+			check(builder, &input(8), &[1.0, 0.0]);
+		}
+
+		let builder = Network::builder()
+			.input([3, 1, 1])
+			.embedding(5, 2)
+			.output(2);
+		check(builder, &[4.0, 0.0, 2.0], &[0.0, 1.0]);
+	}
+
+	#[test]
+	fn convolution_layers() {
+		let builder = Network::builder()
+			.input([4, 3, 1])
+			.conv1d(2, 2)
+			.activation(ActivationFunction::Tanh)
+			.reshape([3, 1, 2])
+			.conv_transpose2d(2, [2, 2])
+			.activation(ActivationFunction::Tanh)
+			.stride(2)
+			.upsample(UpsampleType::Bilinear, 2)
+			.global_average_pool()
+			.output(2);
+		check(builder, &input(12), &[1.0, 0.0]);
+
+		let builder = Network::builder()
+			.input([3, 3, 2])
+			.upsample(UpsampleType::Nearest, 2)
+			.spatial_softmax()
+			.output(2);
+		check(builder, &input(18), &[0.0, 1.0]);
+	}
+
+	#[test]
+	fn global_max_pool() {
+		let builder = || {
+			Network::builder()
+				.input([4, 3, 1])
+				.conv1d(2, 2)
+				.activation(ActivationFunction::Tanh)
+				.reshape([3, 1, 2])
+				.global_max_pool()
+				.output(2)
+		};
+		check(builder(), &input(12), &[1.0, 0.0]);
+
+		// the largest value of each channel is well clear of the rest so moving a parameter
+		// by epsilon doesn't change which position is the max
+		let mut network = network(builder());
+		network.forward(input(12));
+		let pooled = network.layers().nth(2).unwrap().last_output();
+		for channel in pooled.as_slice().chunks(3) {
+			let mut channel = channel.to_vec();
+			channel.sort_by(|a, b| b.total_cmp(a));
+			assert!(channel[0] - channel[1] > 0.05, "{:?}", channel);
+		}
+	}
+
+	#[test]
+	fn merge_layers() {
+		for merge_type in [MergeType::Add, MergeType::Concatenate, MergeType::Multiply] {
+			let builder = Network::builder()
+				.input([4, 1, 1])
+				.dense(3)
+				.activation(ActivationFunction::Tanh)
+				.dense(3)
+				.activation(ActivationFunction::Tanh)
+				.merge(merge_type, &[1])
+				.flatten()
+				.output(2);
+			check(builder, &input(4), &[0.0, 1.0]);
+		}
+	}
+}
@@ -0,0 +1,154 @@
+// 16 bit floating point storage formats, both are converted to and from f32 for arithmetic
+//
+// f16 (IEEE 754 binary16) has 5 exponent and 10 mantissa bits so it is precise but only covers
+// about 6e-8 to 65504, bf16 keeps the 8 exponent bits of f32 with only 7 mantissa bits
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HalfType {
+	F16,
+	BF16,
+}
+
+impl HalfType {
+	// rounds to the nearest representable value, ties to even
+	pub fn from_f32(&self, value: f32) -> u16 {
+		match self {
+			HalfType::F16 => f32_to_f16(value),
+			HalfType::BF16 => f32_to_bf16(value),
+		}
+	}
+
+	pub fn to_f32(&self, bits: u16) -> f32 {
+		match self {
+			HalfType::F16 => f16_to_f32(bits),
+			HalfType::BF16 => f32::from_bits((bits as u32) << 16),
+		}
+	}
+
+	// the value after being stored in this format
+	pub fn round(&self, value: f32) -> f32 {
+		self.to_f32(self.from_f32(value))
+	}
+}
+
+fn f32_to_f16(value: f32) -> u16 {
+	let bits = value.to_bits();
+	let sign = ((bits >> 16) & 0x8000) as u16;
+	let exponent = ((bits >> 23) & 0xff) as i32;
+	let mantissa = bits & 0x7f_ffff;
+
+	// infinity and NaN, NaN keeps the top of its mantissa and stays a NaN
+	if exponent == 0xff {
+		return match mantissa {
+			0 => sign | 0x7c00,
+			_ => sign | 0x7e00 | (mantissa >> 13) as u16,
+		};
+	}
+
+	let exponent = exponent - 127 + 15;
+	if exponent >= 0x1f {
+		return sign | 0x7c00;
+	}
+
+	if exponent <= 0 {
+		// less than half the smallest subnormal rounds to 0
+		if exponent < -10 {
+			return sign;
+		}
+		// subnormal, the implicit leading 1 becomes part of the mantissa
+		let mantissa = mantissa | 0x80_0000;
+		let shift = (14 - exponent) as u32;
+		let half = mantissa >> shift;
+		let remainder = mantissa & ((1 << shift) - 1);
+		return sign | round_to_even(half, remainder, 1 << (shift - 1)) as u16;
+	}
+
+	// a mantissa that rounds up to 0x400 carries into the exponent, which is correct
+	// even when it makes the value infinity
+	let half = ((exponent as u32) << 10) | (mantissa >> 13);
+	sign | round_to_even(half, mantissa & 0x1fff, 0x1000) as u16
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+	let sign = ((bits & 0x8000) as u32) << 16;
+	let exponent = ((bits >> 10) & 0x1f) as u32;
+	let mantissa = (bits & 0x3ff) as u32;
+
+	match exponent {
+		// zero and subnormals, mantissa * 2^-24 is exact in f32
+		0 => {
+			let value = mantissa as f32 * (-24.0f32).exp2();
+			f32::from_bits(sign | value.to_bits())
+		}
+		0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+		_ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+	}
+}
+
+fn f32_to_bf16(value: f32) -> u16 {
+	let bits = value.to_bits();
+	if value.is_nan() {
+		// sets a mantissa bit so truncating can't turn it into infinity
+		return ((bits >> 16) | 0x40) as u16;
+	}
+	(round_to_even(bits >> 16, bits & 0xffff, 0x8000)) as u16
+}
+
+// rounds truncated up when remainder is more than halfway or exactly halfway and truncated is odd
+fn round_to_even(truncated: u32, remainder: u32, halfway: u32) -> u32 {
+	match remainder > halfway || (remainder == halfway && truncated & 1 == 1) {
+		true => truncated + 1,
+		false => truncated,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn f16() {
+		let f16 = HalfType::F16;
+		assert_eq!(f16.from_f32(1.0), 0x3c00);
+		assert_eq!(f16.from_f32(-2.0), 0xc000);
+		assert_eq!(f16.from_f32(0.1), 0x2e66);
+		assert_eq!(f16.from_f32(65504.0), 0x7bff);
+		// past the largest value and halfway to the next power of two
+		assert_eq!(f16.from_f32(65520.0), 0x7c00);
+		assert_eq!(f16.from_f32(f32::INFINITY), 0x7c00);
+		assert_eq!(f16.from_f32((-24.0f32).exp2()), 0x0001);
+		assert_eq!(f16.from_f32((-25.0f32).exp2()), 0x0000);
+		assert_eq!(f16.from_f32(1.5 * (-25.0f32).exp2()), 0x0001);
+		assert_eq!(f16.from_f32(1e-8), 0x0000);
+		// 1 + 2^-11 is halfway between 1 and the next value so it rounds to even
+		assert_eq!(f16.from_f32(1.0 + (-11.0f32).exp2()), 0x3c00);
+		assert!(f16.to_f32(f16.from_f32(f32::NAN)).is_nan());
+
+		// every value round trips
+		for bits in 0..=u16::MAX {
+			let value = f16.to_f32(bits);
+			if !value.is_nan() {
+				assert_eq!(f16.from_f32(value), bits);
+			}
+		}
+	}
+
+	#[test]
+	fn bf16() {
+		let bf16 = HalfType::BF16;
+		assert_eq!(bf16.from_f32(1.0), 0x3f80);
+		assert_eq!(bf16.from_f32(-2.0), 0xc000);
+		assert_eq!(bf16.to_f32(bf16.from_f32(3.140625)), 3.140625);
+		assert_eq!(bf16.round(1.0 + (-8.0f32).exp2()), 1.0);
+		// the range of f32 is kept with about 3 significant figures
+		assert!((bf16.round(1e-30) - 1e-30).abs() < 1e-30 / 256.0);
+		assert!(bf16.to_f32(bf16.from_f32(f32::NAN)).is_nan());
+
+		for bits in 0..=u16::MAX {
+			let value = bf16.to_f32(bits);
+			if !value.is_nan() {
+				assert_eq!(bf16.from_f32(value), bits);
+			}
+		}
+	}
+}
@@ -0,0 +1,422 @@
+use crate::network::change::ParameterChange;
+use crate::network::utility::{general_matrix_multiply, plus_equals_matrix_multiplied};
+use crate::network::{Float, InitType, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+// the order the projections are stored in the weights and biases
+const QUERY: usize = 0;
+const KEY: usize = 1;
+const VALUE: usize = 2;
+const OUTPUT: usize = 3;
+
+// takes inputs with the shape [seq_len, d_model, 1], these are treated as column major
+// [seq_len, d_model] matrices with a row for each timestep
+#[derive(Copy, Clone)]
+pub struct AttentionInfo {
+	pub heads: usize,
+	pub init_type: InitType,
+	input: [usize; 3],
+}
+
+// values from the last forward pass that are needed by backward
+#[derive(Default)]
+struct AttentionOutput {
+	input: Vec<Float>,
+	// the queries, keys and values ([seq_len, d_model] each) one after another
+	projections: Vec<Float>,
+	// the attention weights of each head ([seq_len, seq_len] each)
+	weights: Vec<Float>,
+	// the output of every head joined together before the output projection
+	heads: Vec<Float>,
+	output: Vec<Float>,
+}
+
+pub struct Attention {
+	biases: Vec<Float>,
+	change: ParameterChange,
+	info: AttentionInfo,
+	outputs: AttentionOutput,
+	// the query, key, value and output projections ([d_model, d_model] each),
+	// inputs are multiplied on the left of the projections (x * W)
+	weights: Vec<Float>,
+}
+
+impl AttentionInfo {
+	pub fn new(heads: usize, init_type: InitType) -> Self {
+		AttentionInfo {
+			heads,
+			init_type,
+			input: [0; 3],
+		}
+	}
+
+	fn sequence_length(&self) -> usize {
+		self.input[0]
+	}
+
+	fn model_size(&self) -> usize {
+		self.input[1]
+	}
+}
+
+impl LayerInfoTrait for AttentionInfo {
+	fn output(&self) -> [usize; 3] {
+		self.input
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if input[2] != 1 {
+			return Err(format!(
+				"attention takes inputs with the shape [seq_len, d_model, 1] but got {:?}",
+				input
+			));
+		}
+		if self.heads == 0 || input[1] % self.heads != 0 {
+			return Err(format!(
+				"d_model ({}) must be a multiple of the number of heads ({})",
+				input[1], self.heads
+			));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"MultiHeadAttention"
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl LayerTrait for Attention {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let seq_len = self.info.sequence_length();
+		let size = seq_len * self.info.model_size();
+		let head_size = self.info.model_size() / self.info.heads;
+		let scale = 1.0 / (head_size as Float).sqrt();
+
+		let outputs = std::mem::take(&mut self.outputs);
+		let (queries, keys, values) = (
+			&outputs.projections[..size],
+			&outputs.projections[size..(2 * size)],
+			&outputs.projections[(2 * size)..],
+		);
+
+		let mut heads_gradient = vec![0.0; size];
+		self.projection_backward(
+			OUTPUT,
+			&outputs.heads,
+			output_gradient.as_slice(),
+			&mut heads_gradient,
+		);
+
+		let mut projection_gradients = vec![0.0; 3 * size];
+		let (query_gradients, rest) = projection_gradients.split_at_mut(size);
+		let (key_gradients, value_gradients) = rest.split_at_mut(size);
+		for head in 0..self.info.heads {
+			let columns = (head * seq_len * head_size)..((head + 1) * seq_len * head_size);
+			let weights =
+				&outputs.weights[(head * seq_len * seq_len)..((head + 1) * seq_len * seq_len)];
+
+			let mut weights_gradient = vec![0.0; seq_len * seq_len];
+			general_matrix_multiply(
+				[false, true],
+				[seq_len, seq_len, head_size],
+				1.0,
+				&heads_gradient[columns.clone()],
+				&values[columns.clone()],
+				0.0,
+				&mut weights_gradient,
+			);
+			general_matrix_multiply(
+				[true, false],
+				[seq_len, head_size, seq_len],
+				1.0,
+				weights,
+				&heads_gradient[columns.clone()],
+				0.0,
+				&mut value_gradients[columns.clone()],
+			);
+
+			// weights_gradient becomes the gradient with respect to the scores
+			softmax_rows_backward(weights, &mut weights_gradient, seq_len);
+			general_matrix_multiply(
+				[false, false],
+				[seq_len, head_size, seq_len],
+				scale,
+				&weights_gradient,
+				&keys[columns.clone()],
+				0.0,
+				&mut query_gradients[columns.clone()],
+			);
+			general_matrix_multiply(
+				[true, false],
+				[seq_len, head_size, seq_len],
+				scale,
+				&weights_gradient,
+				&queries[columns.clone()],
+				0.0,
+				&mut key_gradients[columns],
+			);
+		}
+
+		let mut input_gradient = vec![0.0; size];
+		for (&index, gradient) in [QUERY, KEY, VALUE]
+			.iter()
+			.zip(projection_gradients.chunks(size))
+		{
+			self.projection_backward(index, &outputs.input, gradient, &mut input_gradient);
+		}
+
+		self.outputs = outputs;
+		shaped(input_gradient, input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let input = input.into_vec();
+		let seq_len = self.info.sequence_length();
+		let size = seq_len * self.info.model_size();
+		let head_size = self.info.model_size() / self.info.heads;
+		let scale = 1.0 / (head_size as Float).sqrt();
+		assert_eq!(size, input.len());
+
+		let projections: Vec<Float> = [QUERY, KEY, VALUE]
+			.iter()
+			.flat_map(|&index| self.project(index, &input))
+			.collect();
+		let (queries, keys, values) = (
+			&projections[..size],
+			&projections[size..(2 * size)],
+			&projections[(2 * size)..],
+		);
+
+		// the columns of each head are next to each other as the matrices are column major
+		let mut weights = vec![0.0; self.info.heads * seq_len * seq_len];
+		let mut heads = vec![0.0; size];
+		for head in 0..self.info.heads {
+			let columns = (head * seq_len * head_size)..((head + 1) * seq_len * head_size);
+			let head_weights =
+				&mut weights[(head * seq_len * seq_len)..((head + 1) * seq_len * seq_len)];
+
+			general_matrix_multiply(
+				[false, true],
+				[seq_len, seq_len, head_size],
+				scale,
+				&queries[columns.clone()],
+				&keys[columns.clone()],
+				0.0,
+				head_weights,
+			);
+			softmax_rows(head_weights, seq_len);
+			general_matrix_multiply(
+				[false, false],
+				[seq_len, head_size, seq_len],
+				1.0,
+				head_weights,
+				&values[columns.clone()],
+				0.0,
+				&mut heads[columns],
+			);
+		}
+
+		let output = self.project(OUTPUT, &heads);
+		self.outputs = AttentionOutput {
+			input,
+			projections,
+			weights,
+			heads,
+			output,
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.output.clone(), &self.info.output())
+	}
+
+	// regularisation isn't supported for attention layers yet
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		plus_equals_matrix_multiplied(&mut self.weights, multiplier, &self.change.weights);
+		plus_equals_matrix_multiplied(&mut self.biases, multiplier, &self.change.biases);
+
+		self.change = ParameterChange::new(self.weights.len(), self.biases.len());
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.weights)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.biases)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.weights, &mut self.biases]
+	}
+}
+
+impl Attention {
+	pub fn new(info: AttentionInfo) -> Self {
+		let model_size = info.model_size();
+		let weights: Vec<Float> = (0..(4 * model_size * model_size))
+			.map(|_| info.init_type.generate_weight(model_size, model_size))
+			.collect();
+		let biases = vec![0.0; 4 * model_size];
+
+		Attention {
+			change: ParameterChange::new(weights.len(), biases.len()),
+			biases,
+			info,
+			outputs: AttentionOutput::default(),
+			weights,
+		}
+	}
+
+	// the attention weights of each head from the last forward pass,
+	// row i of a head's [seq_len, seq_len] matrix is how much timestep i attends to each timestep
+	pub fn attention_weights(&self) -> &[Float] {
+		&self.outputs.weights
+	}
+
+	// x * W + b for one of the projections
+	fn project(&self, index: usize, x: &[Float]) -> Vec<Float> {
+		let seq_len = self.info.sequence_length();
+		let model_size = self.info.model_size();
+		let size = model_size * model_size;
+
+		let mut result: Vec<Float> = self.biases[(index * model_size)..((index + 1) * model_size)]
+			.iter()
+			.flat_map(|&bias| std::iter::repeat(bias).take(seq_len))
+			.collect();
+		general_matrix_multiply(
+			[false, false],
+			[seq_len, model_size, model_size],
+			1.0,
+			x,
+			&self.weights[(index * size)..((index + 1) * size)],
+			1.0,
+			&mut result,
+		);
+		result
+	}
+
+	// accumulates the change of a projection and adds the gradient with respect to x to x_gradient
+	fn projection_backward(
+		&mut self,
+		index: usize,
+		x: &[Float],
+		gradient: &[Float],
+		x_gradient: &mut [Float],
+	) {
+		let seq_len = self.info.sequence_length();
+		let model_size = self.info.model_size();
+		let size = model_size * model_size;
+		let weights = (index * size)..((index + 1) * size);
+
+		general_matrix_multiply(
+			[true, false],
+			[model_size, model_size, seq_len],
+			1.0,
+			x,
+			gradient,
+			1.0,
+			&mut self.change.weights[weights.clone()],
+		);
+		for (j, bias) in self.change.biases[(index * model_size)..((index + 1) * model_size)]
+			.iter_mut()
+			.enumerate()
+		{
+			*bias += gradient[(j * seq_len)..((j + 1) * seq_len)]
+				.iter()
+				.sum::<Float>();
+		}
+		general_matrix_multiply(
+			[false, true],
+			[seq_len, model_size, model_size],
+			1.0,
+			gradient,
+			&self.weights[weights],
+			1.0,
+			x_gradient,
+		);
+	}
+}
+
+// softmax over each row of a column major [rows, rows] matrix
+fn softmax_rows(matrix: &mut [Float], rows: usize) {
+	for i in 0..rows {
+		let max = (0..rows)
+			.map(|j| matrix[i + rows * j])
+			.fold(Float::NEG_INFINITY, Float::max);
+		let mut sum = 0.0;
+		for j in 0..rows {
+			matrix[i + rows * j] = (matrix[i + rows * j] - max).exp();
+			sum += matrix[i + rows * j];
+		}
+		for j in 0..rows {
+			matrix[i + rows * j] /= sum;
+		}
+	}
+}
+
+// turns the gradient with respect to the softmax output into the gradient with respect to its input
+fn softmax_rows_backward(softmax: &[Float], gradient: &mut [Float], rows: usize) {
+	for i in 0..rows {
+		let dot: Float = (0..rows)
+			.map(|j| gradient[i + rows * j] * softmax[i + rows * j])
+			.sum();
+		for j in 0..rows {
+			gradient[i + rows * j] = softmax[i + rows * j] * (gradient[i + rows * j] - dot);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	#[test]
+	fn gradient_check() {
+		let mut info = AttentionInfo::new(2, InitType::NormalisedXavier);
+		info.set_input([3, 4, 1]).unwrap();
+		let mut layer = Attention::new(info);
+
+		let input: Vec<Float> = (0..12).map(|i| (i as Float * 1.3).cos()).collect();
+		layer.forward(vector(&input));
+
+		// the attention weights of every timestep sum to 1
+		for head in layer.attention_weights().chunks(9) {
+			for i in 0..3 {
+				let sum: Float = (0..3).map(|j| head[i + 3 * j]).sum();
+				assert!((sum - 1.0).abs() < 1e-5);
+			}
+		}
+
+		check_gradients(&mut layer, &[input], 5);
+	}
+
+	#[test]
+	fn invalid_heads() {
+		assert!(AttentionInfo::new(3, InitType::He)
+			.set_input([5, 4, 1])
+			.is_err());
+		assert!(AttentionInfo::new(2, InitType::He)
+			.set_input([5, 4, 2])
+			.is_err());
+		assert!(AttentionInfo::new(2, InitType::He)
+			.set_input([5, 4, 1])
+			.is_ok());
+	}
+}
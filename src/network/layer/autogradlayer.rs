@@ -0,0 +1,283 @@
+use crate::network::autograd::{Tape, Var};
+use crate::network::tensor::{Layout, ShapeError, Tensor};
+use crate::network::utility::plus_equals_matrix_multiplied;
+use crate::network::{Float, Regularisation};
+
+use super::{shaped, CustomLayerInfo, LayerInfoTrait, LayerTrait};
+
+// builds the output of the layer from the input (a [length, 1] column vector)
+// and the parameters, only the forward pass has to be written
+pub type Forward = fn(&mut Tape, Var, &[Var]) -> Result<Var, ShapeError>;
+
+// creates the initial parameters for an input shape
+pub type Parameters = fn([usize; 3]) -> Vec<Tensor>;
+
+// a layer differentiated with autograd, used through NetworkBuilder::custom
+#[derive(Clone)]
+pub struct AutogradLayerInfo {
+	forward: Forward,
+	input: [usize; 3],
+	name: &'static str,
+	output: [usize; 3],
+	parameters: Parameters,
+}
+
+pub struct AutogradLayer {
+	changes: Vec<Tensor>,
+	info: AutogradLayerInfo,
+	parameters: Vec<Tensor>,
+	// the tape of the last forward pass with the input, parameters and output recorded on it
+	tape: Tape,
+	vars: Option<(Var, Vec<Var>, Var)>,
+}
+
+impl AutogradLayerInfo {
+	pub fn new(name: &'static str, parameters: Parameters, forward: Forward) -> Self {
+		AutogradLayerInfo {
+			forward,
+			input: [0; 3],
+			name,
+			output: [0; 3],
+			parameters,
+		}
+	}
+}
+
+impl LayerInfoTrait for AutogradLayerInfo {
+	fn output(&self) -> [usize; 3] {
+		self.output
+	}
+
+	// runs the forward pass once so shape errors are found when the network is built
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		let mut layer = AutogradLayer::new(self.clone(), input);
+		layer
+			.record(vec![0.0; input.iter().product()])
+			.map_err(|error| error.to_string())?;
+
+		self.input = input;
+		self.output = [layer.output().len(), 1, 1];
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		self.name
+	}
+}
+
+impl CustomLayerInfo for AutogradLayerInfo {
+	fn create_layer(&self, input: [usize; 3]) -> Box<dyn LayerTrait> {
+		Box::new(AutogradLayer::new(self.clone(), input))
+	}
+}
+
+impl LayerTrait for AutogradLayer {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		if self.vars.is_none() {
+			self.forward(input.clone());
+		}
+		let (input_var, parameter_vars, output_var) = self.vars.as_ref().unwrap();
+
+		let output_shape = self.tape.value(*output_var).shape();
+		let output_gradient = output_gradient.clone().reshape(output_shape).unwrap();
+		let gradients = self.tape.backward(*output_var, output_gradient).unwrap();
+
+		for (change, var) in self.changes.iter_mut().zip(parameter_vars) {
+			if let Some(gradient) = gradients.get(*var) {
+				*change = change.add(gradient).unwrap();
+			}
+		}
+
+		match gradients.get(*input_var) {
+			Some(gradient) => shaped(
+				gradient.to_layout(Layout::ColumnMajor).into_vec(),
+				input.shape(),
+			),
+			None => Tensor::zeros(input.shape()),
+		}
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		self.record(input.into_vec())
+			.expect("shapes are checked when the network is built");
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output(), &self.info.output())
+	}
+
+	// regularisation isn't applied
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+		for (parameter, change) in self.parameters.iter_mut().zip(self.changes.iter_mut()) {
+			plus_equals_matrix_multiplied(parameter.as_mut_slice(), multiplier, change.as_slice());
+			*change = change.scale(0.0);
+		}
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn parameter_count(&self) -> usize {
+		self.parameters
+			.iter()
+			.map(|parameter| parameter.len())
+			.sum()
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		self.parameters
+			.iter_mut()
+			.map(|parameter| parameter.as_mut_slice())
+			.collect()
+	}
+}
+
+impl AutogradLayer {
+	pub fn new(info: AutogradLayerInfo, input: [usize; 3]) -> Self {
+		let parameters = (info.parameters)(input);
+		// changes have the layout of their parameter so they can be added directly
+		let changes = parameters
+			.iter()
+			.map(|parameter| {
+				let zeros = vec![0.0; parameter.len()];
+				Tensor::with_layout(zeros, parameter.shape(), parameter.layout()).unwrap()
+			})
+			.collect();
+
+		AutogradLayer {
+			changes,
+			info,
+			parameters,
+			tape: Tape::new(),
+			vars: None,
+		}
+	}
+
+	pub fn parameters(&self) -> &[Tensor] {
+		&self.parameters
+	}
+
+	// the output of the last forward pass in column major order, zeros before the first
+	fn output(&self) -> Vec<Float> {
+		match &self.vars {
+			Some((_, _, output)) => self
+				.tape
+				.value(*output)
+				.to_layout(Layout::ColumnMajor)
+				.into_vec(),
+			None => vec![0.0; self.info.output.iter().product()],
+		}
+	}
+
+	fn record(&mut self, input: Vec<Float>) -> Result<(), ShapeError> {
+		let length = input.len();
+		self.tape = Tape::new();
+		self.vars = None;
+
+		let input = self.tape.input(Tensor::new(input, &[length, 1])?);
+		let tape = &mut self.tape;
+		let parameters: Vec<Var> = self
+			.parameters
+			.iter()
+			.map(|parameter| tape.input(parameter.clone()))
+			.collect();
+		let output = (self.info.forward)(&mut self.tape, input, &parameters)?;
+
+		self.vars = Some((input, parameters, output));
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::{gradient_check, ActivationFunction, CostFunction, Network};
+
+	fn parameters(input: [usize; 3]) -> Vec<Tensor> {
+		let inputs = input.iter().product::<usize>();
+		let weights = (0..(3 * inputs))
+			.map(|i| (i as Float * 0.61).sin() * 0.5)
+			.collect();
+		vec![
+			Tensor::new(weights, &[3, inputs]).unwrap(),
+			Tensor::new(vec![0.1, -0.2, 0.3], &[3, 1]).unwrap(),
+		]
+	}
+
+	fn dense(tape: &mut Tape, input: Var, parameters: &[Var]) -> Result<Var, ShapeError> {
+		let z = tape.matmul(parameters[0], input)?;
+		let z = tape.add(z, parameters[1])?;
+		Ok(tape.activation(z, ActivationFunction::Tanh))
+	}
+
+	fn quadratic(tape: &mut Tape, output: Var, expected_output: Var) -> Result<Var, ShapeError> {
+		let error = tape.subtract(output, expected_output)?;
+		let squared = tape.multiply(error, error)?;
+		let sum = tape.sum(squared);
+		Ok(tape.scale(sum, 0.5))
+	}
+
+	#[test]
+	fn gradients() {
+		let mut network = Network::builder()
+			.input([4, 1, 1])
+			.custom(AutogradLayerInfo::new("Dense", parameters, dense))
+			.output(2)
+			.activation(ActivationFunction::Sigmoid)
+			.cost(CostFunction::Custom(quadratic))
+			.build()
+			.unwrap();
+		assert!(network.to_string().contains("1 (Dense)"));
+		assert_eq!(network.parameter_count(), 3 * 4 + 3 + 2 * 3 + 2);
+
+		let input = [0.5, -0.3, 0.8, 0.1];
+		for gradient in gradient_check(&mut network, &input, &[1.0, 0.0], 1e-3) {
+			assert!(gradient.error() < 2e-2, "{:?}", gradient);
+		}
+
+		// the custom cost is the same as the quadratic cost
+		let output = [0.3, 0.9];
+		let expected = [1.0, 0.0];
+		let custom = CostFunction::Custom(quadratic);
+		let builtin = CostFunction::Quadratic;
+		assert!(
+			(custom.evaluate(&output, &expected) - builtin.evaluate(&output, &expected)).abs()
+				< 1e-6
+		);
+		assert_eq!(
+			custom.gradient(&output, &expected),
+			builtin.gradient(&output, &expected)
+		);
+	}
+
+	#[test]
+	fn shape_error() {
+		fn wrong_size(_: [usize; 3]) -> Vec<Tensor> {
+			vec![Tensor::zeros(&[3, 5]), Tensor::zeros(&[3, 1])]
+		}
+
+		let result = Network::builder()
+			.input([4, 1, 1])
+			.custom(AutogradLayerInfo::new("Dense", wrong_size, dense))
+			.output(2)
+			.build();
+		assert!(result.is_err());
+
+		// a [2, 1] by [2, 1] product can't be taken
+		fn wrong_cost(tape: &mut Tape, output: Var, expected: Var) -> Result<Var, ShapeError> {
+			tape.matmul(output, expected)
+		}
+
+		let result = Network::builder()
+			.input([4, 1, 1])
+			.output(2)
+			.cost(CostFunction::Custom(wrong_cost))
+			.build();
+		assert!(result.is_err());
+	}
+}
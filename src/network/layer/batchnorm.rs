@@ -0,0 +1,357 @@
+use crate::network::change::NormalisationChange;
+use crate::network::{Float, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone)]
+pub struct BatchNormInfo {
+	input: [usize; 3],
+	pub epsilon: Float,
+	// how much of the previous running statistics is kept after each mini batch
+	pub momentum: Float,
+}
+
+pub struct BatchNorm {
+	beta: Vec<Float>,
+	change: NormalisationChange,
+	gamma: Vec<Float>,
+	info: BatchNormInfo,
+	// values from the last call to forward_batch, needed by backward_batch
+	inverse_std: Vec<Float>,
+	normalised: Vec<Vec<Float>>,
+	output: Vec<Float>,
+	// used instead of the mini batch statistics during evaluation
+	running_mean: Vec<Float>,
+	running_variance: Vec<Float>,
+	training: bool,
+}
+
+impl BatchNormInfo {
+	pub fn new() -> Self {
+		BatchNormInfo {
+			input: [0; 3],
+			epsilon: 1e-5,
+			momentum: 0.9,
+		}
+	}
+
+	// flat inputs ([n, 1, 1]) are normalised per element,
+	// otherwise each channel is normalised over every position in it
+	fn positions(&self) -> usize {
+		match self.input {
+			[_, 1, 1] => 1,
+			[width, height, _] => width * height,
+		}
+	}
+
+	fn features(&self) -> usize {
+		self.flattened_output() / self.positions()
+	}
+}
+
+impl Default for BatchNormInfo {
+	fn default() -> Self {
+		BatchNormInfo::new()
+	}
+}
+
+impl LayerInfoTrait for BatchNormInfo {
+	fn output(&self) -> [usize; 3] {
+		self.input
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn check_parameters(&self) -> Result<(), String> {
+		if !(0.0..=1.0).contains(&self.momentum) {
+			return Err(format!(
+				"batch norm momentum must be between 0 and 1 but is {}",
+				self.momentum
+			));
+		}
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"BatchNorm"
+	}
+}
+
+impl LayerTrait for BatchNorm {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		self.backward_batch(&[vec![input]], std::slice::from_ref(output_gradient))
+			.remove(0)
+			.remove(0)
+	}
+
+	fn backward_batch(
+		&mut self,
+		_: &[Vec<&Tensor>],
+		output_gradients: &[Tensor],
+	) -> Vec<Vec<Tensor>> {
+		let positions = self.info.positions();
+		let features = self.info.features();
+		let count = (output_gradients.len() * positions) as Float;
+
+		let mut gradient_sum = vec![0.0; features];
+		let mut weighted_sum = vec![0.0; features];
+		for (gradients, normalised) in output_gradients.iter().zip(self.normalised.iter()) {
+			for (i, (gradient, normalised)) in
+				gradients.as_slice().iter().zip(normalised).enumerate()
+			{
+				gradient_sum[i / positions] += gradient;
+				weighted_sum[i / positions] += gradient * normalised;
+			}
+		}
+
+		for feature in 0..features {
+			self.change.gamma[feature] += weighted_sum[feature];
+			self.change.beta[feature] += gradient_sum[feature];
+		}
+
+		output_gradients
+			.iter()
+			.zip(self.normalised.iter())
+			.map(|(gradients, normalised)| {
+				let input_gradient = gradients
+					.as_slice()
+					.iter()
+					.zip(normalised)
+					.enumerate()
+					.map(|(i, (gradient, normalised))| {
+						let feature = i / positions;
+						let scale = self.gamma[feature] * self.inverse_std[feature];
+						match self.training {
+							// the mean and variance depend on every sample in the mini batch
+							true => {
+								scale / count
+									* (count * gradient
+										- gradient_sum[feature] - normalised * weighted_sum[feature])
+							}
+							false => scale * gradient,
+						}
+					})
+					.collect();
+				vec![shaped(input_gradient, gradients.shape())]
+			})
+			.collect()
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		self.forward_batch(vec![vec![input]]);
+	}
+
+	fn forward_batch(&mut self, inputs: Vec<Vec<Tensor>>) -> Vec<Tensor> {
+		let inputs: Vec<Vec<Float>> = inputs
+			.into_iter()
+			.map(|mut input| input.remove(0).into_vec())
+			.collect();
+		let positions = self.info.positions();
+		let features = self.info.features();
+
+		let (mean, variance) = match self.training {
+			true => {
+				let count = (inputs.len() * positions) as Float;
+
+				let mut mean = vec![0.0; features];
+				for input in &inputs {
+					for (i, value) in input.iter().enumerate() {
+						mean[i / positions] += value / count;
+					}
+				}
+
+				let mut variance = vec![0.0; features];
+				for input in &inputs {
+					for (i, value) in input.iter().enumerate() {
+						variance[i / positions] += (value - mean[i / positions]).powi(2) / count;
+					}
+				}
+
+				// the running variance is an unbiased estimate of the population variance
+				let correction = match count > 1.0 {
+					true => count / (count - 1.0),
+					false => 1.0,
+				};
+				let momentum = self.info.momentum;
+				for feature in 0..features {
+					self.running_mean[feature] =
+						momentum * self.running_mean[feature] + (1.0 - momentum) * mean[feature];
+					self.running_variance[feature] = momentum * self.running_variance[feature]
+						+ (1.0 - momentum) * variance[feature] * correction;
+				}
+
+				(mean, variance)
+			}
+			false => (self.running_mean.clone(), self.running_variance.clone()),
+		};
+
+		self.inverse_std = variance
+			.iter()
+			.map(|variance| 1.0 / (variance + self.info.epsilon).sqrt())
+			.collect();
+
+		self.normalised = inputs
+			.iter()
+			.map(|input| {
+				input
+					.iter()
+					.enumerate()
+					.map(|(i, value)| {
+						(value - mean[i / positions]) * self.inverse_std[i / positions]
+					})
+					.collect()
+			})
+			.collect();
+
+		let outputs: Vec<Tensor> = self
+			.normalised
+			.iter()
+			.map(|normalised| {
+				let output = normalised
+					.iter()
+					.enumerate()
+					.map(|(i, value)| self.gamma[i / positions] * value + self.beta[i / positions])
+					.collect();
+				shaped(output, &self.info.output())
+			})
+			.collect();
+
+		if let Some(output) = outputs.last() {
+			self.output = output.as_slice().to_vec();
+		}
+		outputs
+	}
+
+	fn uses_batch_statistics(&self) -> bool {
+		true
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	// regularisation isn't applied to the scale and shift
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		for (gamma, change) in self.gamma.iter_mut().zip(self.change.gamma.iter()) {
+			*gamma += multiplier * change;
+		}
+		for (beta, change) in self.beta.iter_mut().zip(self.change.beta.iter()) {
+			*beta += multiplier * change;
+		}
+
+		self.change = NormalisationChange::new(self.info.features());
+	}
+
+	fn set_training(&mut self, training: bool) {
+		self.training = training;
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	// gamma (scale) and beta (shift) are reported as the weights and biases
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.gamma)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.beta)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.gamma, &mut self.beta]
+	}
+}
+
+impl BatchNorm {
+	pub fn new(info: BatchNormInfo) -> Self {
+		let features = info.features();
+		BatchNorm {
+			beta: vec![0.0; features],
+			change: NormalisationChange::new(features),
+			gamma: vec![1.0; features],
+			info,
+			inverse_std: Vec::new(),
+			normalised: Vec::new(),
+			output: Vec::new(),
+			running_mean: vec![0.0; features],
+			running_variance: vec![1.0; features],
+			training: false,
+		}
+	}
+
+	pub fn running_mean(&self) -> &[Float] {
+		&self.running_mean
+	}
+
+	pub fn running_variance(&self) -> &[Float] {
+		&self.running_variance
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn batch_norm(input: [usize; 3]) -> BatchNorm {
+		let mut info = BatchNormInfo::new();
+		info.set_input(input).unwrap();
+		let mut layer = BatchNorm::new(info);
+		layer.set_training(true);
+		layer
+	}
+
+	#[test]
+	fn normalises_channels() {
+		let mut layer = batch_norm([2, 1, 2]);
+		let outputs = layer.forward_batch(vec![
+			vec![vector(&[1.0, 2.0, 10.0, 20.0])],
+			vec![vector(&[3.0, 4.0, 30.0, 40.0])],
+		]);
+
+		for channel in 0..2 {
+			let values: Vec<Float> = outputs
+				.iter()
+				.flat_map(|output| output.as_slice()[(channel * 2)..(channel * 2 + 2)].to_vec())
+				.collect();
+			let mean = values.iter().sum::<Float>() / 4.0;
+			let variance = values
+				.iter()
+				.map(|value| (value - mean).powi(2))
+				.sum::<Float>()
+				/ 4.0;
+			assert!(mean.abs() < 1e-5);
+			assert!((variance - 1.0).abs() < 1e-3);
+		}
+
+		// evaluation uses the running statistics so single samples can be used
+		layer.set_training(false);
+		layer.forward(vector(&[2.5, 2.5, 25.0, 25.0]));
+		assert_eq!(layer.last_output().len(), 4);
+		assert!(layer.running_mean()[1] > layer.running_mean()[0]);
+	}
+
+	#[test]
+	fn gradient_check() {
+		let inputs = vec![
+			vec![0.3, -1.2, 0.8, 2.0, -0.5, 0.1],
+			vec![1.5, 0.4, -0.7, 0.9, 1.1, -2.0],
+			vec![-0.6, 0.2, 1.9, -1.3, 0.0, 0.7],
+		];
+		let mut layer = batch_norm([3, 1, 2]);
+		layer.gamma = vec![1.3, 0.6];
+		layer.beta = vec![0.2, -0.4];
+
+		check_gradients(&mut layer, &inputs, 1);
+	}
+}
@@ -0,0 +1,386 @@
+use crate::network::change::ParameterChange;
+use crate::network::utility::{general_matrix_multiply, plus_equals_matrix_multiplied};
+use crate::network::{ActivationFunction, Float, InitType, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Padding {
+	// no padding so the output is shorter than the input
+	Valid,
+	// zero padding on both sides so the output length is the input length / stride (rounded up)
+	Same,
+	// zero padding only before the input so outputs never depend on later inputs
+	Causal,
+}
+
+// takes inputs with the shape [length, channels, 1] where the value for channel c
+// at position t is at t + length * c, the output has the shape [output_length, filters, 1]
+#[derive(Copy, Clone)]
+pub struct Conv1DInfo {
+	pub activation_function: ActivationFunction,
+	pub bias: Float,
+	// gap between the input positions used by a kernel, 1 uses neighbouring positions
+	pub dilation: usize,
+	pub filters: usize,
+	pub init_type: InitType,
+	input: [usize; 3],
+	pub kernel: usize,
+	pub padding: Padding,
+	pub stride: usize,
+}
+
+pub struct Conv1DOutput {
+	after_activation: Vec<Float>,
+	before_activation: Vec<Float>,
+	// the input positions used by each output ([output_length, kernel * channels])
+	columns: Vec<Float>,
+}
+
+pub struct Conv1D {
+	biases: Vec<Float>,
+	change: ParameterChange,
+	info: Conv1DInfo,
+	outputs: Conv1DOutput,
+	// [filters, kernel * channels] where the weight for kernel position k
+	// and channel c of a filter is in column k + kernel * c
+	weights: Vec<Float>,
+}
+
+impl Conv1DInfo {
+	pub fn new(
+		activation_function: ActivationFunction,
+		init_type: InitType,
+		filters: usize,
+		kernel: usize,
+	) -> Self {
+		Conv1DInfo {
+			activation_function,
+			bias: 0.0,
+			dilation: 1,
+			filters,
+			init_type,
+			input: [0; 3],
+			kernel,
+			padding: Padding::Valid,
+			stride: 1,
+		}
+	}
+
+	fn effective_kernel(&self) -> usize {
+		self.dilation * (self.kernel - 1) + 1
+	}
+
+	fn output_length(&self) -> usize {
+		let length = self.input[0];
+		match self.padding {
+			Padding::Valid => match length >= self.effective_kernel() {
+				true => (length - self.effective_kernel()) / self.stride + 1,
+				false => 0,
+			},
+			Padding::Same | Padding::Causal => length.div_ceil(self.stride),
+		}
+	}
+
+	// padding before the first input position
+	fn left_padding(&self) -> usize {
+		match self.padding {
+			Padding::Valid => 0,
+			Padding::Same => {
+				let total = ((self.output_length() - 1) * self.stride + self.effective_kernel())
+					.saturating_sub(self.input[0]);
+				total / 2
+			}
+			Padding::Causal => self.effective_kernel() - 1,
+		}
+	}
+
+	fn channels(&self) -> usize {
+		self.input[1]
+	}
+
+	fn flattened_input(&self) -> usize {
+		self.input.iter().product()
+	}
+}
+
+impl LayerInfoTrait for Conv1DInfo {
+	fn output(&self) -> [usize; 3] {
+		[self.output_length(), self.filters, 1]
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if input[2] != 1 {
+			return Err(format!(
+				"1D convolution takes inputs with the shape [length, channels, 1] but got {:?}",
+				input
+			));
+		}
+		if self.kernel == 0 || self.stride == 0 || self.dilation == 0 {
+			return Err(String::from(
+				"kernel, stride and dilation must all be at least 1",
+			));
+		}
+		if self.padding == Padding::Valid && self.effective_kernel() > input[0] {
+			return Err(format!(
+				"the kernel covers {} positions but the input only has {}",
+				self.effective_kernel(),
+				input[0]
+			));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"Conv1D"
+	}
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		Some(self.activation_function)
+	}
+
+	fn supports_activation(&self, activation: ActivationFunction) -> bool {
+		activation != ActivationFunction::Softmax
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl LayerTrait for Conv1D {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let output_length = self.info.output_length();
+		let column_size = self.info.kernel * self.info.channels();
+		let filters = self.info.filters;
+
+		let errors: Vec<Float> = output_gradient
+			.as_slice()
+			.iter()
+			.zip(self.outputs.before_activation.iter())
+			.map(|(gradient, &z)| gradient * self.info.activation_function.derivative(z))
+			.collect();
+
+		general_matrix_multiply(
+			[true, false],
+			[filters, column_size, output_length],
+			1.0,
+			&errors,
+			&self.outputs.columns,
+			1.0,
+			&mut self.change.weights,
+		);
+		for (bias, errors) in self
+			.change
+			.biases
+			.iter_mut()
+			.zip(errors.chunks(output_length))
+		{
+			*bias += errors.iter().sum::<Float>();
+		}
+
+		let mut column_gradient = vec![0.0; output_length * column_size];
+		general_matrix_multiply(
+			[false, false],
+			[output_length, column_size, filters],
+			1.0,
+			&errors,
+			&self.weights,
+			0.0,
+			&mut column_gradient,
+		);
+
+		// positions used by more than one output get the sum of their gradients
+		let mut input_gradient = Tensor::zeros(input.shape());
+		let values = input_gradient.as_mut_slice();
+		self.for_each_position(|column, input| {
+			values[input] += column_gradient[column];
+		});
+		input_gradient
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let input = input.as_slice();
+		let output_length = self.info.output_length();
+		let column_size = self.info.kernel * self.info.channels();
+		assert_eq!(self.info.flattened_input(), input.len());
+
+		let mut columns = vec![0.0; output_length * column_size];
+		self.for_each_position(|column, position| {
+			columns[column] = input[position];
+		});
+
+		let mut before_activation: Vec<Float> = self
+			.biases
+			.iter()
+			.flat_map(|&bias| std::iter::repeat(bias).take(output_length))
+			.collect();
+		general_matrix_multiply(
+			[false, true],
+			[output_length, self.info.filters, column_size],
+			1.0,
+			&columns,
+			&self.weights,
+			1.0,
+			&mut before_activation,
+		);
+
+		self.outputs = Conv1DOutput {
+			after_activation: before_activation
+				.iter()
+				.map(|&z| self.info.activation_function.evaluate(z))
+				.collect(),
+			before_activation,
+			columns,
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.after_activation.clone(), &self.info.output())
+	}
+
+	fn last_z_values(&self) -> Tensor {
+		shaped(self.outputs.before_activation.clone(), &self.info.output())
+	}
+
+	// regularisation isn't supported for convolution layers yet
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		plus_equals_matrix_multiplied(&mut self.weights, multiplier, &self.change.weights);
+		plus_equals_matrix_multiplied(&mut self.biases, multiplier, &self.change.biases);
+
+		self.change = ParameterChange::new(self.weights.len(), self.biases.len());
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.weights)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.biases)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.weights, &mut self.biases]
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		Some([self.info.filters, self.info.kernel * self.info.channels()])
+	}
+}
+
+impl Conv1D {
+	pub fn new(info: Conv1DInfo) -> Self {
+		let column_size = info.kernel * info.channels();
+		let weights: Vec<Float> = (0..(info.filters * column_size))
+			.map(|_| info.init_type.generate_weight(column_size, info.filters))
+			.collect();
+		let biases = vec![info.bias; info.filters];
+
+		Conv1D {
+			change: ParameterChange::new(weights.len(), biases.len()),
+			biases,
+			info,
+			outputs: Conv1DOutput {
+				after_activation: Vec::new(),
+				before_activation: Vec::new(),
+				columns: Vec::new(),
+			},
+			weights,
+		}
+	}
+
+	// calls f with the index in the columns matrix and the index in the input for every
+	// position a kernel uses, positions in the padding are skipped
+	fn for_each_position<F: FnMut(usize, usize)>(&self, mut f: F) {
+		let length = self.info.input[0];
+		let output_length = self.info.output_length();
+		let left_padding = self.info.left_padding();
+
+		for c in 0..self.info.channels() {
+			for k in 0..self.info.kernel {
+				for t in 0..output_length {
+					let position = (t * self.info.stride + k * self.info.dilation)
+						.checked_sub(left_padding)
+						.filter(|&position| position < length);
+					if let Some(position) = position {
+						f(
+							t + output_length * (k + self.info.kernel * c),
+							position + length * c,
+						);
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn conv(padding: Padding, stride: usize, dilation: usize) -> Conv1D {
+		let mut info = Conv1DInfo::new(ActivationFunction::Tanh, InitType::NormalisedXavier, 3, 3);
+		info.padding = padding;
+		info.stride = stride;
+		info.dilation = dilation;
+		info.set_input([7, 2, 1]).unwrap();
+		Conv1D::new(info)
+	}
+
+	#[test]
+	fn output_shapes() {
+		assert_eq!(conv(Padding::Valid, 1, 1).info().output(), [5, 3, 1]);
+		assert_eq!(conv(Padding::Valid, 2, 2).info().output(), [2, 3, 1]);
+		assert_eq!(conv(Padding::Same, 2, 1).info().output(), [4, 3, 1]);
+		assert_eq!(conv(Padding::Causal, 1, 3).info().output(), [7, 3, 1]);
+
+		let mut info = Conv1DInfo::new(ActivationFunction::Tanh, InitType::He, 3, 4);
+		info.dilation = 3;
+		assert!(info.set_input([7, 2, 1]).is_err());
+	}
+
+	#[test]
+	fn causal() {
+		let mut layer = conv(Padding::Causal, 1, 2);
+		let input: Vec<Float> = (0..14).map(|i| (i as Float * 0.7).sin()).collect();
+		layer.forward(vector(&input));
+		let output = layer.last_output();
+
+		// changing the last position of each channel only changes the last output of each filter
+		let mut changed = input;
+		changed[6] += 1.0;
+		changed[13] += 1.0;
+		layer.forward(vector(&changed));
+		let changed = layer.last_output();
+		for (i, (before, after)) in output.as_slice().iter().zip(changed.as_slice()).enumerate() {
+			assert_eq!(before == after, i % 7 != 6);
+		}
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input: Vec<Float> = (0..14).map(|i| (i as Float * 0.7).sin()).collect();
+		for (padding, stride, dilation) in [
+			(Padding::Valid, 1, 1),
+			(Padding::Same, 2, 1),
+			(Padding::Causal, 2, 2),
+		] {
+			check_gradients(
+				&mut conv(padding, stride, dilation),
+				std::slice::from_ref(&input),
+				1,
+			);
+		}
+	}
+}
@@ -0,0 +1,341 @@
+use crate::network::change::ParameterChange;
+use crate::network::utility::{general_matrix_multiply, plus_equals_matrix_multiplied};
+use crate::network::{ActivationFunction, Float, InitType, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+// takes inputs with the shape [w, h, c] and spreads every input value over a kernel sized
+// area of the output, inputs stride pixels apart land stride pixels apart in the output
+// so the output has the shape [(w - 1) * stride + kernel_w - 2 * padding, .., filters]
+#[derive(Copy, Clone)]
+pub struct ConvTranspose2DInfo {
+	pub activation_function: ActivationFunction,
+	pub bias: Float,
+	pub filters: usize,
+	pub init_type: InitType,
+	input: [usize; 3],
+	pub kernel: [usize; 2],
+	// removed from each side of the output
+	pub padding: usize,
+	pub stride: usize,
+}
+
+pub struct ConvTranspose2DOutput {
+	after_activation: Vec<Float>,
+	before_activation: Vec<Float>,
+}
+
+pub struct ConvTranspose2D {
+	biases: Vec<Float>,
+	change: ParameterChange,
+	info: ConvTranspose2DInfo,
+	outputs: ConvTranspose2DOutput,
+	// [channels, kernel_w * kernel_h * filters] where the weight from channel c to
+	// kernel position (kx, ky) of filter f is in column kx + kernel_w * (ky + kernel_h * f)
+	weights: Vec<Float>,
+}
+
+impl ConvTranspose2DInfo {
+	pub fn new(
+		activation_function: ActivationFunction,
+		init_type: InitType,
+		filters: usize,
+		kernel: [usize; 2],
+	) -> Self {
+		ConvTranspose2DInfo {
+			activation_function,
+			bias: 0.0,
+			filters,
+			init_type,
+			input: [0; 3],
+			kernel,
+			padding: 0,
+			stride: 1,
+		}
+	}
+
+	fn output_size(&self, dimension: usize) -> usize {
+		((self.input[dimension] - 1) * self.stride + self.kernel[dimension])
+			.saturating_sub(2 * self.padding)
+	}
+
+	fn column_count(&self) -> usize {
+		self.kernel[0] * self.kernel[1] * self.filters
+	}
+}
+
+impl LayerInfoTrait for ConvTranspose2DInfo {
+	fn output(&self) -> [usize; 3] {
+		[self.output_size(0), self.output_size(1), self.filters]
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if self.kernel.contains(&0) || self.stride == 0 {
+			return Err(String::from("kernel and stride must be at least 1"));
+		}
+		self.input = input;
+		if self.output().contains(&0) {
+			return Err(format!(
+				"padding of {} removes the whole output of {:?}",
+				self.padding, input
+			));
+		}
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"ConvTranspose2D"
+	}
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		Some(self.activation_function)
+	}
+
+	fn supports_activation(&self, activation: ActivationFunction) -> bool {
+		activation != ActivationFunction::Softmax
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl LayerTrait for ConvTranspose2D {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let pixels = self.info.input[0] * self.info.input[1];
+		let channels = self.info.input[2];
+		let column_count = self.info.column_count();
+		let output_pixels = self.info.output_size(0) * self.info.output_size(1);
+
+		let errors: Vec<Float> = output_gradient
+			.as_slice()
+			.iter()
+			.zip(self.outputs.before_activation.iter())
+			.map(|(gradient, &z)| gradient * self.info.activation_function.derivative(z))
+			.collect();
+
+		for (bias, errors) in self
+			.change
+			.biases
+			.iter_mut()
+			.zip(errors.chunks(output_pixels))
+		{
+			*bias += errors.iter().sum::<Float>();
+		}
+
+		let mut column_gradient = vec![0.0; pixels * column_count];
+		self.for_each_position(|column, output| {
+			column_gradient[column] = errors[output];
+		});
+
+		general_matrix_multiply(
+			[true, false],
+			[channels, column_count, pixels],
+			1.0,
+			input.as_slice(),
+			&column_gradient,
+			1.0,
+			&mut self.change.weights,
+		);
+
+		let mut input_gradient = vec![0.0; pixels * channels];
+		general_matrix_multiply(
+			[false, true],
+			[pixels, channels, column_count],
+			1.0,
+			&column_gradient,
+			&self.weights,
+			0.0,
+			&mut input_gradient,
+		);
+		shaped(input_gradient, input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let pixels = self.info.input[0] * self.info.input[1];
+		let channels = self.info.input[2];
+		let column_count = self.info.column_count();
+		let output_pixels = self.info.output_size(0) * self.info.output_size(1);
+		assert_eq!(pixels * channels, input.len());
+
+		// the contribution of every input pixel to every kernel position of every filter
+		let mut columns = vec![0.0; pixels * column_count];
+		general_matrix_multiply(
+			[false, false],
+			[pixels, column_count, channels],
+			1.0,
+			input.as_slice(),
+			&self.weights,
+			0.0,
+			&mut columns,
+		);
+
+		let mut before_activation: Vec<Float> = self
+			.biases
+			.iter()
+			.flat_map(|&bias| std::iter::repeat(bias).take(output_pixels))
+			.collect();
+		self.for_each_position(|column, output| {
+			before_activation[output] += columns[column];
+		});
+
+		self.outputs = ConvTranspose2DOutput {
+			after_activation: before_activation
+				.iter()
+				.map(|&z| self.info.activation_function.evaluate(z))
+				.collect(),
+			before_activation,
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.after_activation.clone(), &self.info.output())
+	}
+
+	fn last_z_values(&self) -> Tensor {
+		shaped(self.outputs.before_activation.clone(), &self.info.output())
+	}
+
+	// regularisation isn't supported for convolution layers yet
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		plus_equals_matrix_multiplied(&mut self.weights, multiplier, &self.change.weights);
+		plus_equals_matrix_multiplied(&mut self.biases, multiplier, &self.change.biases);
+
+		self.change = ParameterChange::new(self.weights.len(), self.biases.len());
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.weights)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.biases)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.weights, &mut self.biases]
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		Some([self.info.input[2], self.info.column_count()])
+	}
+}
+
+impl ConvTranspose2D {
+	pub fn new(info: ConvTranspose2DInfo) -> Self {
+		let kernel_size = info.kernel[0] * info.kernel[1];
+		let weights: Vec<Float> = (0..(info.input[2] * info.column_count()))
+			.map(|_| {
+				info.init_type
+					.generate_weight(info.input[2] * kernel_size, info.filters)
+			})
+			.collect();
+		let biases = vec![info.bias; info.filters];
+
+		ConvTranspose2D {
+			change: ParameterChange::new(weights.len(), biases.len()),
+			biases,
+			info,
+			outputs: ConvTranspose2DOutput {
+				after_activation: Vec::new(),
+				before_activation: Vec::new(),
+			},
+			weights,
+		}
+	}
+
+	// calls f with the index in the columns matrix ([pixels, column_count]) and the index in
+	// the output for every kernel position of every input pixel that lands inside the output
+	fn for_each_position<F: FnMut(usize, usize)>(&self, mut f: F) {
+		let [width, height, _] = self.info.input;
+		let [kernel_width, kernel_height] = self.info.kernel;
+		let (output_width, output_height) = (self.info.output_size(0), self.info.output_size(1));
+		let pixels = width * height;
+		let position = |input: usize, k: usize, size: usize| {
+			(input * self.info.stride + k)
+				.checked_sub(self.info.padding)
+				.filter(|&position| position < size)
+		};
+
+		for filter in 0..self.info.filters {
+			for ky in 0..kernel_height {
+				for kx in 0..kernel_width {
+					let column = kx + kernel_width * (ky + kernel_height * filter);
+					for y in 0..height {
+						let oy = match position(y, ky, output_height) {
+							Some(oy) => oy,
+							None => continue,
+						};
+						for x in 0..width {
+							if let Some(ox) = position(x, kx, output_width) {
+								f(
+									x + width * y + pixels * column,
+									ox + output_width * (oy + output_height * filter),
+								);
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn conv(stride: usize, padding: usize) -> ConvTranspose2D {
+		let mut info = ConvTranspose2DInfo::new(
+			ActivationFunction::Tanh,
+			InitType::NormalisedXavier,
+			2,
+			[3, 2],
+		);
+		info.stride = stride;
+		info.padding = padding;
+		info.set_input([3, 2, 2]).unwrap();
+		ConvTranspose2D::new(info)
+	}
+
+	#[test]
+	fn output_shape() {
+		assert_eq!(conv(1, 0).info().output(), [5, 3, 2]);
+		assert_eq!(conv(2, 0).info().output(), [7, 4, 2]);
+		assert_eq!(conv(2, 1).info().output(), [5, 2, 2]);
+	}
+
+	#[test]
+	fn single_pixel() {
+		let mut info = ConvTranspose2DInfo::new(ActivationFunction::ReLU, InitType::He, 1, [2, 2]);
+		info.stride = 2;
+		info.set_input([2, 1, 1]).unwrap();
+		let mut layer = ConvTranspose2D::new(info);
+		layer.weights = vec![1.0, 2.0, 3.0, 4.0];
+
+		// each input is copied into its own 2x2 block of the output scaled by the kernel
+		layer.forward(vector(&[1.0, 2.0]));
+		assert_eq!(
+			layer.last_output().as_slice(),
+			[1.0, 2.0, 2.0, 4.0, 3.0, 4.0, 6.0, 8.0]
+		);
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input: Vec<Float> = (0..12).map(|i| (i as Float * 0.7).sin()).collect();
+		for (stride, padding) in [(1, 0), (2, 0), (2, 1)] {
+			check_gradients(&mut conv(stride, padding), std::slice::from_ref(&input), 1);
+		}
+	}
+}
@@ -0,0 +1,200 @@
+use crate::network::{Float, Tensor};
+
+use rand::{thread_rng, Rng};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone)]
+pub struct DropoutInfo {
+	input: [usize; 3],
+	pub rate: Float,
+}
+
+pub struct Dropout {
+	info: DropoutInfo,
+	// 0 for dropped activations and 1 / (1 - rate) for kept activations (inverted dropout)
+	// so the expected output is the same in training and evaluation
+	// there is a mask for each sample of the last forward pass
+	masks: Vec<Vec<Float>>,
+	output: Vec<Float>,
+	training: bool,
+}
+
+impl DropoutInfo {
+	pub fn new(rate: Float) -> Self {
+		DropoutInfo {
+			input: [0; 3],
+			rate,
+		}
+	}
+}
+
+impl LayerInfoTrait for DropoutInfo {
+	fn output(&self) -> [usize; 3] {
+		self.input
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		self.input = input;
+		Ok(())
+	}
+
+	fn check_parameters(&self) -> Result<(), String> {
+		if !(0.0..1.0).contains(&self.rate) {
+			return Err(format!(
+				"dropout rate must be at least 0 and less than 1 but is {}",
+				self.rate
+			));
+		}
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"Dropout"
+	}
+}
+
+impl LayerTrait for Dropout {
+	fn backward(&mut self, _: &Tensor, output_gradient: &Tensor) -> Tensor {
+		match (self.training, self.masks.last()) {
+			(true, Some(mask)) => shaped(
+				apply_mask(output_gradient.as_slice(), mask),
+				output_gradient.shape(),
+			),
+			_ => output_gradient.clone(),
+		}
+	}
+
+	fn backward_batch(
+		&mut self,
+		_: &[Vec<&Tensor>],
+		output_gradients: &[Tensor],
+	) -> Vec<Vec<Tensor>> {
+		if !self.training {
+			return output_gradients
+				.iter()
+				.map(|gradient| vec![gradient.clone()])
+				.collect();
+		}
+
+		output_gradients
+			.iter()
+			.zip(self.masks.iter())
+			.map(|(gradient, mask)| {
+				vec![shaped(
+					apply_mask(gradient.as_slice(), mask),
+					gradient.shape(),
+				)]
+			})
+			.collect()
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		self.forward_batch(vec![vec![input]]);
+	}
+
+	fn forward_batch(&mut self, inputs: Vec<Vec<Tensor>>) -> Vec<Tensor> {
+		let outputs: Vec<Tensor> = match self.training {
+			true => {
+				self.masks = inputs
+					.iter()
+					.map(|input| self.generate_mask(input[0].len()))
+					.collect();
+				inputs
+					.iter()
+					.zip(self.masks.iter())
+					.map(|(input, mask)| {
+						shaped(apply_mask(input[0].as_slice(), mask), input[0].shape())
+					})
+					.collect()
+			}
+			false => inputs
+				.into_iter()
+				.map(|mut input| input.remove(0))
+				.collect(),
+		};
+
+		if let Some(output) = outputs.last() {
+			self.output = output.as_slice().to_vec();
+		}
+		outputs
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	fn set_training(&mut self, training: bool) {
+		self.training = training;
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+}
+
+impl Dropout {
+	pub fn new(info: DropoutInfo) -> Self {
+		Dropout {
+			info,
+			masks: Vec::new(),
+			output: Vec::new(),
+			training: false,
+		}
+	}
+
+	fn generate_mask(&self, len: usize) -> Vec<Float> {
+		let mut rng = thread_rng();
+		let scale = 1.0 / (1.0 - self.info.rate);
+		(0..len)
+			.map(|_| match rng.gen::<Float>() < self.info.rate {
+				true => 0.0,
+				false => scale,
+			})
+			.collect()
+	}
+}
+
+fn apply_mask(values: &[Float], mask: &[Float]) -> Vec<Float> {
+	values
+		.iter()
+		.zip(mask.iter())
+		.map(|(value, mask)| value * mask)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::vector;
+
+	#[test]
+	fn dropout_modes() {
+		let mut info = DropoutInfo::new(0.5);
+		info.set_input([1000, 1, 1]).unwrap();
+		let mut layer = Dropout::new(info);
+		let input = vector(&[1.0; 1000]);
+
+		layer.forward(input.clone());
+		assert_eq!(layer.last_output(), input);
+
+		layer.set_training(true);
+		layer.forward(input.clone());
+		let output = layer.last_output().into_vec();
+		assert!(output.iter().all(|&value| value == 0.0 || value == 2.0));
+		assert!(output.contains(&0.0) && output.contains(&2.0));
+
+		// the same activations are dropped in backward
+		let gradient = layer.backward(&input, &vector(&[0.5; 1000]));
+		for (gradient, output) in gradient.as_slice().iter().zip(output.iter()) {
+			assert_eq!(*gradient, output * 0.5);
+		}
+	}
+
+	#[test]
+	fn invalid_rate() {
+		assert!(DropoutInfo::new(1.0).check_parameters().is_err());
+		assert!(DropoutInfo::new(-0.1).check_parameters().is_err());
+		assert!(DropoutInfo::new(0.0).check_parameters().is_ok());
+	}
+}
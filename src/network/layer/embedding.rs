@@ -0,0 +1,231 @@
+use crate::network::{Float, InitType, Regularisation, Tensor};
+
+use std::collections::BTreeMap;
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+// takes a sequence of token ids with the shape [seq_len, 1, 1] and outputs the embedding
+// of each token as a sequence with the shape [seq_len, dimensions, 1]
+#[derive(Copy, Clone)]
+pub struct EmbeddingInfo {
+	pub dimensions: usize,
+	pub init_type: InitType,
+	input: [usize; 3],
+	pub vocabulary: usize,
+}
+
+pub struct Embedding {
+	// the change of each row used since the last update, rows that weren't used aren't stored
+	change: BTreeMap<usize, Vec<Float>>,
+	info: EmbeddingInfo,
+	output: Vec<Float>,
+	// the embedding of token i is at i * dimensions, this is a column major
+	// [dimensions, vocabulary] matrix like the weights of other layers
+	table: Vec<Float>,
+	tokens: Vec<usize>,
+}
+
+impl EmbeddingInfo {
+	pub fn new(vocabulary: usize, dimensions: usize, init_type: InitType) -> Self {
+		EmbeddingInfo {
+			dimensions,
+			init_type,
+			input: [0; 3],
+			vocabulary,
+		}
+	}
+}
+
+impl LayerInfoTrait for EmbeddingInfo {
+	fn output(&self) -> [usize; 3] {
+		[self.input[0], self.dimensions, 1]
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if input[1] != 1 || input[2] != 1 {
+			return Err(format!(
+				"embedding takes token ids with the shape [seq_len, 1, 1] but got {:?}",
+				input
+			));
+		}
+		if self.vocabulary == 0 {
+			return Err(String::from("the vocabulary is empty"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"Embedding"
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl LayerTrait for Embedding {
+	// token ids can't be differentiated so the input gradient is always 0
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let seq_len = self.tokens.len();
+		let dimensions = self.info.dimensions;
+
+		for (t, &token) in self.tokens.iter().enumerate() {
+			let change = self
+				.change
+				.entry(token)
+				.or_insert_with(|| vec![0.0; dimensions]);
+			for (d, change) in change.iter_mut().enumerate() {
+				*change += output_gradient.as_slice()[t + seq_len * d];
+			}
+		}
+
+		Tensor::zeros(input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let dimensions = self.info.dimensions;
+		self.tokens = input
+			.as_slice()
+			.iter()
+			.map(|&id| {
+				let token = id as usize;
+				assert!(
+					id >= 0.0 && id.fract() == 0.0 && token < self.info.vocabulary,
+					"{} isn't a token id in a vocabulary of {}",
+					id,
+					self.info.vocabulary
+				);
+				token
+			})
+			.collect();
+
+		let (tokens, table) = (&self.tokens, &self.table);
+		self.output = (0..dimensions)
+			.flat_map(|d| {
+				tokens
+					.iter()
+					.map(move |&token| table[token * dimensions + d])
+			})
+			.collect();
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	// only the rows of tokens used since the last update are changed
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+		let dimensions = self.info.dimensions;
+
+		for (token, change) in std::mem::take(&mut self.change) {
+			for (value, change) in self.table[(token * dimensions)..((token + 1) * dimensions)]
+				.iter_mut()
+				.zip(change)
+			{
+				*value += multiplier * change;
+			}
+		}
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.table)
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		Some([self.info.dimensions, self.info.vocabulary])
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.table]
+	}
+}
+
+impl Embedding {
+	pub fn new(info: EmbeddingInfo) -> Self {
+		let table = (0..(info.vocabulary * info.dimensions))
+			.map(|_| {
+				info.init_type
+					.generate_weight(info.vocabulary, info.dimensions)
+			})
+			.collect();
+
+		Embedding {
+			change: BTreeMap::new(),
+			info,
+			output: Vec::new(),
+			table,
+			tokens: Vec::new(),
+		}
+	}
+
+	pub fn vocabulary(&self) -> usize {
+		self.info.vocabulary
+	}
+
+	pub fn embedding(&self, token: usize) -> &[Float] {
+		let dimensions = self.info.dimensions;
+		&self.table[(token * dimensions)..((token + 1) * dimensions)]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::vector;
+
+	fn embedding() -> Embedding {
+		let mut info = EmbeddingInfo::new(5, 2, InitType::NormalisedXavier);
+		info.set_input([3, 1, 1]).unwrap();
+		Embedding::new(info)
+	}
+
+	#[test]
+	fn lookup() {
+		let mut layer = embedding();
+		layer.forward(vector(&[4.0, 0.0, 4.0]));
+
+		let (first, second) = (layer.embedding(4).to_vec(), layer.embedding(0).to_vec());
+		assert_eq!(
+			layer.last_output().as_slice(),
+			[first[0], second[0], first[0], first[1], second[1], first[1]]
+		);
+	}
+
+	#[test]
+	fn sparse_update() {
+		let mut layer = embedding();
+		let table = layer.table.clone();
+
+		let input = vector(&[1.0, 3.0, 1.0]);
+		layer.forward(input.clone());
+		let gradient = layer.backward(&input, &vector(&[1.0, 2.0, 3.0, 0.5, 0.5, 0.5]));
+		assert_eq!(gradient, Tensor::zeros(&[3, 1, 1]));
+		assert_eq!(layer.change.len(), 2);
+
+		layer.update(1.0, 1, &Regularisation::None);
+		assert!(layer.change.is_empty());
+
+		// rows of tokens that weren't used are untouched
+		for token in [0, 2, 4] {
+			assert_eq!(layer.embedding(token), &table[(token * 2)..(token * 2 + 2)]);
+		}
+		// both uses of token 1 are added together
+		assert_eq!(layer.embedding(1), &[table[2] - 4.0, table[3] - 1.0]);
+		assert_eq!(layer.embedding(3), &[table[6] - 2.0, table[7] - 0.5]);
+	}
+
+	#[test]
+	#[should_panic]
+	fn invalid_token() {
+		embedding().forward(vector(&[1.0, 5.0, 0.0]));
+	}
+}
@@ -1,26 +1,31 @@
-use crate::network::{ActivationFunction, Float, InitType, Regularisation};
+use crate::network::{ActivationFunction, Float, InitType, Regularisation, Tensor};
 
 use crate::network::change::FeedForwardChange;
 
+use crate::network::simd::{add_assign, l2_update};
+use crate::network::sparse::{
+	sparse_matrix_vec_multiply_add, sparse_outer_product_add, SparseVector,
+};
 use crate::network::utility::{
-	hadamard_product, matrix_vec_multiply_add, outer_product_add, plus_equals_matrix_multiplied,
-	scale_elements, transpose_matrix_multiply_vec,
+	matrix_vec_multiply_add, outer_product_add, plus_equals_matrix_multiplied, scale_elements,
+	transpose_matrix_multiply_vec,
 };
 
-extern crate openblas_src;
-
-use super::{LayerInfoTrait, LayerTrait};
+use super::{shaped, LayerInfoTrait, LayerTrait};
 
 pub struct FeedForwardData {
 	biases: Vec<Float>,
+	// weights that are false have been pruned and are kept at zero
+	mask: Option<Vec<bool>>,
 	weights: Vec<Float>,
 	weight_dimensions: [usize; 2],
 }
 
 #[derive(Copy, Clone)]
 pub struct FeedForwardInfo {
-	activation_function: ActivationFunction,
-	init_type: InitType,
+	pub activation_function: ActivationFunction,
+	pub bias: Float,
+	pub init_type: InitType,
 	pub length: usize,
 }
 
@@ -37,8 +42,24 @@ pub struct FeedForward {
 }
 
 impl FeedForwardData {
-	pub fn new(init_type: InitType, weight_dimensions: [usize; 2]) -> Self {
-		let biases = vec![0.0; weight_dimensions[0]];
+	pub fn biases(&self) -> &[Float] {
+		&self.biases
+	}
+
+	pub fn weights(&self) -> &[Float] {
+		&self.weights
+	}
+
+	pub fn mask(&self) -> Option<&[bool]> {
+		self.mask.as_deref()
+	}
+
+	pub fn weight_dimensions(&self) -> [usize; 2] {
+		self.weight_dimensions
+	}
+
+	pub fn new(init_type: InitType, bias: Float, weight_dimensions: [usize; 2]) -> Self {
+		let biases = vec![bias; weight_dimensions[0]];
 		let mut weights = Vec::new();
 		for _ in 0..(weight_dimensions[0] * weight_dimensions[1]) {
 			weights.push(init_type.generate_weight(weight_dimensions[1], weight_dimensions[0]));
@@ -46,10 +67,21 @@ impl FeedForwardData {
 
 		FeedForwardData {
 			biases,
+			mask: None,
 			weights,
 			weight_dimensions,
 		}
 	}
+
+	fn apply_mask(&mut self) {
+		if let Some(mask) = &self.mask {
+			for (weight, &keep) in self.weights.iter_mut().zip(mask) {
+				if !keep {
+					*weight = 0.0;
+				}
+			}
+		}
+	}
 }
 
 impl FeedForwardOutput {
@@ -61,6 +93,12 @@ impl FeedForwardOutput {
 	}
 }
 
+impl Default for FeedForwardOutput {
+	fn default() -> Self {
+		FeedForwardOutput::new()
+	}
+}
+
 impl FeedForwardInfo {
 	pub fn new(
 		activation_function: ActivationFunction,
@@ -69,6 +107,7 @@ impl FeedForwardInfo {
 	) -> Self {
 		FeedForwardInfo {
 			activation_function,
+			bias: 0.0,
 			init_type,
 			length,
 		}
@@ -79,74 +118,64 @@ impl LayerInfoTrait for FeedForwardInfo {
 	fn output(&self) -> [usize; 3] {
 		[self.length, 1, 1]
 	}
-}
-
-impl LayerTrait for FeedForward {
-	fn backward(
-		&mut self,
-		a: &[Float],
-		error_input: &[Float],
-		weights: (Vec<Float>, [usize; 3]),
-	) -> (Vec<Float>, Vec<Float>, [usize; 3]) {
-		let mut c_da = Vec::new();
-
-		assert_eq!(weights.1[2], 1);
 
-		let weights = (weights.0, [weights.1[0], weights.1[1]]);
+	fn name(&self) -> &str {
+		"FeedForward"
+	}
 
-		transpose_matrix_multiply_vec(&weights.0, error_input, weights.1, &mut c_da);
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		Some(self.activation_function)
+	}
 
-		let a_dz: Vec<Float> = self
-			.outputs
-			.before_activation
-			.iter()
-			.map(|&z| self.info.activation_function.derivative(z))
-			.collect();
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
 
-		let errors = hadamard_product(&c_da, &a_dz);
+impl LayerTrait for FeedForward {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let errors = self.info.activation_function.backward_all(
+			&self.outputs.before_activation,
+			&self.outputs.after_activation,
+			output_gradient.as_slice(),
+		);
 
-		self.update_change(&errors, a);
+		self.update_change(&errors, input.as_slice());
 
-		(
-			errors,
-			self.data.weights.clone(),
-			[
-				self.data.weight_dimensions[0],
-				self.data.weight_dimensions[1],
-				1,
-			],
-		)
+		let mut input_gradient = Vec::new();
+		transpose_matrix_multiply_vec(
+			&self.data.weights,
+			&errors,
+			self.data.weight_dimensions,
+			&mut input_gradient,
+		);
+		shaped(input_gradient, input.shape())
 	}
 
-	fn forward(&mut self, input: Vec<Float>) {
+	fn forward(&mut self, input: Tensor) {
 		assert_eq!(self.data.weight_dimensions[1], input.len());
 
-		self.outputs.before_activation = self.data.biases.clone();
+		self.outputs.before_activation = vec![0.0; self.data.biases.len()];
 		matrix_vec_multiply_add(
 			&self.data.weights,
-			&input,
+			input.as_slice(),
 			&mut self.outputs.before_activation,
 			&self.data.weight_dimensions,
 		);
+		add_assign(&mut self.outputs.before_activation, &self.data.biases);
 
-		// is this optimal??
-		// perhaps have activation_function(z_values: &[]) -> Vec<>
-		let output = self
-			.outputs
-			.before_activation
-			.iter()
-			.map(|&z| self.info.activation_function.evaluate(z))
-			.collect();
-
-		self.outputs.after_activation = output;
+		self.outputs.after_activation = self
+			.info
+			.activation_function
+			.evaluate_all(&self.outputs.before_activation);
 	}
 
-	fn last_output(&self) -> Vec<Float> {
-		self.outputs.after_activation.clone()
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.after_activation.clone(), &self.info.output())
 	}
 
-	fn last_z_values(&self) -> Vec<Float> {
-		self.outputs.before_activation.clone()
+	fn last_z_values(&self) -> Tensor {
+		shaped(self.outputs.before_activation.clone(), &self.info.output())
 	}
 
 	fn update(
@@ -155,8 +184,8 @@ impl LayerTrait for FeedForward {
 		mini_batch_size: usize,
 		regularisation: &Regularisation,
 	) {
-		match &self.change {
-			Some(change) => match regularisation {
+		if let Some(change) = &self.change {
+			match regularisation {
 				Regularisation::L1(lambda) => {
 					let multiplier = -1.0 / mini_batch_size as Float;
 
@@ -174,11 +203,7 @@ impl LayerTrait for FeedForward {
 				Regularisation::L2(lambda) => {
 					let multiplier = -learning_rate / mini_batch_size as Float;
 
-					// is there a more optimal way to do this?
-					for (weight, change) in self.data.weights.iter_mut().zip(change.weights.iter())
-					{
-						*weight += multiplier * (change + lambda * weight.signum());
-					}
+					l2_update(&mut self.data.weights, &change.weights, multiplier, *lambda);
 				}
 				Regularisation::None => {
 					let multiplier = -learning_rate / mini_batch_size as Float;
@@ -195,9 +220,9 @@ impl LayerTrait for FeedForward {
 						&change.biases,
 					);
 				}
-			},
-			None => {}
+			}
 		}
+		self.data.apply_mask();
 		self.change = Some(FeedForward::empty_layer_change(
 			&self.data.weight_dimensions,
 		));
@@ -205,25 +230,61 @@ impl LayerTrait for FeedForward {
 
 	fn update_change(&mut self, errors: &[Float], a: &[Float]) {
 		assert_eq!(self.data.weight_dimensions[0], errors.len());
-		// On entry to SGER   parameter number  9 had an illegal value
 		// prob need to write unit tests for backwards
 		let change = self.change.as_mut().unwrap();
 		let biases = &mut change.biases;
 		let weights = &mut change.weights;
 
-		for (bias, error) in biases.iter_mut().zip(errors) {
-			*bias += error;
-		}
+		add_assign(biases, errors);
+
+		outer_product_add(errors, a, weights);
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(self.data.weights())
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(self.data.biases())
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.data.weights, &mut self.data.biases]
+	}
 
-		outer_product_add(&errors, &a, weights);
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		Some(self.data.weight_dimensions())
 	}
 }
 
 impl FeedForward {
+	pub fn data(&self) -> &FeedForwardData {
+		&self.data
+	}
+
+	// pruned weights are set to zero and stay zero when the layer is updated,
+	// None removes the mask so every weight can change again
+	pub fn set_mask(&mut self, mask: Option<Vec<bool>>) {
+		if let Some(mask) = &mask {
+			assert_eq!(mask.len(), self.data.weights.len());
+		}
+		self.data.mask = mask;
+		self.data.apply_mask();
+	}
+
+	// sets pruned weights back to zero after the weights are changed directly
+	pub fn apply_mask(&mut self) {
+		self.data.apply_mask();
+	}
+
 	pub fn new(info: FeedForwardInfo, input_size: usize) -> Self {
 		let weight_dimensions = [info.length, input_size];
 
-		let data = FeedForwardData::new(info.init_type, weight_dimensions);
+		let data = FeedForwardData::new(info.init_type, info.bias, weight_dimensions);
 
 		FeedForward {
 			change: Some(FeedForward::empty_layer_change(&weight_dimensions)),
@@ -233,6 +294,45 @@ impl FeedForward {
 		}
 	}
 
+	// the same as forward for an input that is mostly zeros, only the columns of the weights
+	// for the nonzero inputs are used
+	pub fn forward_sparse(&mut self, input: &SparseVector) {
+		self.outputs.before_activation = self.data.biases.clone();
+		sparse_matrix_vec_multiply_add(
+			&self.data.weights,
+			input,
+			&mut self.outputs.before_activation,
+			&self.data.weight_dimensions,
+		);
+
+		self.outputs.after_activation = self
+			.info
+			.activation_function
+			.evaluate_all(&self.outputs.before_activation);
+	}
+
+	// the same as backward without the gradient with respect to the input,
+	// sparse input only comes from the input layer which doesn't need it
+	pub fn backward_sparse(&mut self, input: &SparseVector, output_gradient: &[Float]) {
+		let errors = self.info.activation_function.backward_all(
+			&self.outputs.before_activation,
+			&self.outputs.after_activation,
+			output_gradient,
+		);
+
+		self.update_change_sparse(&errors, input);
+	}
+
+	// only the columns of the weight change for nonzero inputs change
+	fn update_change_sparse(&mut self, errors: &[Float], a: &SparseVector) {
+		assert_eq!(self.data.weight_dimensions[0], errors.len());
+		let change = self.change.as_mut().unwrap();
+
+		add_assign(&mut change.biases, errors);
+
+		sparse_outer_product_add(errors, a, &mut change.weights);
+	}
+
 	fn empty_layer_change(weight_dim: &[usize; 2]) -> FeedForwardChange {
 		FeedForwardChange::new(weight_dim)
 	}
@@ -241,12 +341,10 @@ impl FeedForward {
 #[macro_export]
 macro_rules! feedforward {
 	($activation_function:expr, $init_type:expr, $length:expr) => {
-		neural_network::layer::LayerInfo::FeedForward(
-			neural_network::layer::feedforward::FeedForwardInfo::new(
-				$activation_function,
-				$init_type,
-				$length,
-			),
-		)
+		$crate::layer::LayerInfo::FeedForward($crate::layer::feedforward::FeedForwardInfo::new(
+			$activation_function,
+			$init_type,
+			$length,
+		))
 	};
 }
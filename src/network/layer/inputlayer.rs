@@ -1,6 +1,6 @@
-use crate::network::{Float, Regularisation};
+use crate::network::{Float, Tensor};
 
-use super::{LayerInfoTrait, LayerTrait};
+use super::{shaped, LayerInfoTrait, LayerTrait};
 
 #[derive(Copy, Clone)]
 pub struct InputLayerInfo {
@@ -22,35 +22,30 @@ impl LayerInfoTrait for InputLayerInfo {
 	fn output(&self) -> [usize; 3] {
 		self.sizes
 	}
+
+	fn name(&self) -> &str {
+		"Input"
+	}
 }
 
 impl LayerTrait for InputLayer {
-	fn backward(
-		&mut self,
-		_: &[Float],
-		error_input: &[Float],
-		weights: (Vec<Float>, [usize; 3]),
-	) -> (Vec<Float>, Vec<Float>, [usize; 3]) {
-		(error_input.to_vec(), weights.0, weights.1)
+	fn backward(&mut self, _: &Tensor, output_gradient: &Tensor) -> Tensor {
+		output_gradient.clone()
 	}
 
-	fn forward(&mut self, input: Vec<Float>) {
+	fn forward(&mut self, input: Tensor) {
 		assert_eq!(self.info.flattened_output(), input.len());
 
-		self.output = input;
+		self.output = input.into_vec();
 	}
 
-	fn last_output(&self) -> Vec<Float> {
-		self.output.clone()
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
 	}
 
-	fn last_z_values(&self) -> Vec<Float> {
-		self.output.clone()
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
 	}
-
-	fn update(&mut self, _: Float, _: usize, _: &Regularisation) {}
-
-	fn update_change(&mut self, _: &[Float], _: &[Float]) {}
 }
 
 impl InputLayer {
@@ -65,8 +60,8 @@ impl InputLayer {
 #[macro_export]
 macro_rules! input {
 	($length:expr) => {
-		neural_network::layer::LayerInfo::InputLayer(
-			neural_network::layer::inputlayer::InputLayerInfo::new([$length, 1, 1]),
-		)
+		$crate::layer::LayerInfo::InputLayer($crate::layer::inputlayer::InputLayerInfo::new([
+			$length, 1, 1,
+		]))
 	};
 }
@@ -0,0 +1,253 @@
+use crate::network::change::NormalisationChange;
+use crate::network::{Float, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NormType {
+	// subtracts the mean and divides by the standard deviation
+	Layer,
+	// only divides by the root mean square, there is no centring
+	RMS,
+}
+
+#[derive(Copy, Clone)]
+pub struct LayerNormInfo {
+	input: [usize; 3],
+	pub epsilon: Float,
+	pub norm_type: NormType,
+}
+
+// unlike batch norm every sample is normalised on its own
+// so the same calculation is used in training and evaluation
+pub struct LayerNorm {
+	beta: Vec<Float>,
+	change: NormalisationChange,
+	gamma: Vec<Float>,
+	info: LayerNormInfo,
+	inverse_std: Float,
+	normalised: Vec<Float>,
+	output: Vec<Float>,
+}
+
+impl LayerNormInfo {
+	pub fn new(norm_type: NormType) -> Self {
+		LayerNormInfo {
+			input: [0; 3],
+			epsilon: 1e-5,
+			norm_type,
+		}
+	}
+}
+
+impl LayerInfoTrait for LayerNormInfo {
+	fn output(&self) -> [usize; 3] {
+		self.input
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.norm_type {
+			NormType::Layer => "LayerNorm",
+			NormType::RMS => "RMSNorm",
+		}
+	}
+}
+
+impl LayerTrait for LayerNorm {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let normalised = std::mem::take(&mut self.normalised);
+		self.update_change(output_gradient.as_slice(), &normalised);
+
+		let normalised_gradient: Vec<Float> = output_gradient
+			.as_slice()
+			.iter()
+			.zip(self.gamma.iter())
+			.map(|(gradient, gamma)| gradient * gamma)
+			.collect();
+		let input_gradient = normalise_backward(
+			&normalised_gradient,
+			&normalised,
+			self.inverse_std,
+			self.info.norm_type,
+		);
+
+		self.normalised = normalised;
+		shaped(input_gradient, input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let (normalised, inverse_std) =
+			normalise(input.as_slice(), self.info.epsilon, self.info.norm_type);
+
+		self.output = normalised
+			.iter()
+			.zip(self.gamma.iter().zip(self.beta.iter()))
+			.map(|(value, (gamma, beta))| gamma * value + beta)
+			.collect();
+		self.normalised = normalised;
+		self.inverse_std = inverse_std;
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	// regularisation isn't applied to the scale and shift
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		for (gamma, change) in self.gamma.iter_mut().zip(self.change.gamma.iter()) {
+			*gamma += multiplier * change;
+		}
+		for (beta, change) in self.beta.iter_mut().zip(self.change.beta.iter()) {
+			*beta += multiplier * change;
+		}
+
+		self.change = NormalisationChange::new(self.gamma.len());
+	}
+
+	// errors are the gradient with respect to the output and a is the normalised input
+	fn update_change(&mut self, errors: &[Float], a: &[Float]) {
+		for ((gamma, beta), (error, a)) in self
+			.change
+			.gamma
+			.iter_mut()
+			.zip(self.change.beta.iter_mut())
+			.zip(errors.iter().zip(a))
+		{
+			*gamma += error * a;
+			*beta += error;
+		}
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	// gamma (scale) and beta (shift) are reported as the weights and biases
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.gamma)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.beta)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.gamma, &mut self.beta]
+	}
+}
+
+impl LayerNorm {
+	pub fn new(info: LayerNormInfo) -> Self {
+		let length = info.flattened_output();
+		LayerNorm {
+			beta: vec![0.0; length],
+			change: NormalisationChange::new(length),
+			gamma: vec![1.0; length],
+			info,
+			inverse_std: 1.0,
+			normalised: Vec::new(),
+			output: Vec::new(),
+		}
+	}
+}
+
+// returns the normalised values and 1 / the standard deviation (or root mean square)
+pub(crate) fn normalise(
+	input: &[Float],
+	epsilon: Float,
+	norm_type: NormType,
+) -> (Vec<Float>, Float) {
+	let len = input.len() as Float;
+	let mean = match norm_type {
+		NormType::Layer => input.iter().sum::<Float>() / len,
+		NormType::RMS => 0.0,
+	};
+	let variance = input
+		.iter()
+		.map(|value| (value - mean).powi(2))
+		.sum::<Float>()
+		/ len;
+	let inverse_std = 1.0 / (variance + epsilon).sqrt();
+
+	let normalised = input
+		.iter()
+		.map(|value| (value - mean) * inverse_std)
+		.collect();
+	(normalised, inverse_std)
+}
+
+// takes the gradient with respect to the normalised values and returns the gradient
+// with respect to the input of normalise
+pub(crate) fn normalise_backward(
+	normalised_gradient: &[Float],
+	normalised: &[Float],
+	inverse_std: Float,
+	norm_type: NormType,
+) -> Vec<Float> {
+	let len = normalised.len() as Float;
+	let mean_gradient = match norm_type {
+		NormType::Layer => normalised_gradient.iter().sum::<Float>() / len,
+		NormType::RMS => 0.0,
+	};
+	let mean_weighted_gradient = normalised_gradient
+		.iter()
+		.zip(normalised)
+		.map(|(gradient, value)| gradient * value)
+		.sum::<Float>()
+		/ len;
+
+	normalised_gradient
+		.iter()
+		.zip(normalised)
+		.map(|(gradient, value)| {
+			inverse_std * (gradient - mean_gradient - value * mean_weighted_gradient)
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::check_gradients;
+
+	fn layer_norm(norm_type: NormType, input: [usize; 3]) -> LayerNorm {
+		let mut info = LayerNormInfo::new(norm_type);
+		info.set_input(input).unwrap();
+		let mut layer = LayerNorm::new(info);
+		layer.gamma = vec![1.3, 0.6, -0.8, 1.1];
+		layer.beta = vec![0.2, -0.4, 0.1, 0.0];
+		layer
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input = vec![0.3, -1.2, 0.8, 2.0];
+		for norm_type in [NormType::Layer, NormType::RMS] {
+			check_gradients(
+				&mut layer_norm(norm_type, [2, 2, 1]),
+				std::slice::from_ref(&input),
+				1,
+			);
+		}
+	}
+
+	#[test]
+	fn rms_norm_is_not_centred() {
+		let (normalised, _) = normalise(&[1.0, 2.0, 3.0, 4.0], 0.0, NormType::Layer);
+		assert!(normalised.iter().sum::<Float>().abs() < 1e-5);
+
+		let (normalised, inverse_std) = normalise(&[1.0, 2.0, 3.0, 4.0], 0.0, NormType::RMS);
+		assert!((inverse_std - 1.0 / (7.5 as Float).sqrt()).abs() < 1e-6);
+		assert!(normalised.iter().all(|&value| value > 0.0));
+	}
+}
@@ -0,0 +1,241 @@
+use crate::network::{Float, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MergeType {
+	Add,
+	// flat inputs ([n, 1, 1]) are joined end to end, otherwise inputs are joined along the channels
+	Concatenate,
+	Multiply,
+}
+
+#[derive(Clone)]
+pub struct MergeInfo {
+	pub merge_type: MergeType,
+	output: [usize; 3],
+}
+
+pub struct Merge {
+	info: MergeInfo,
+	output: Vec<Float>,
+}
+
+impl MergeInfo {
+	pub fn new(merge_type: MergeType) -> Self {
+		MergeInfo {
+			merge_type,
+			output: [0; 3],
+		}
+	}
+}
+
+impl LayerInfoTrait for MergeInfo {
+	fn output(&self) -> [usize; 3] {
+		self.output
+	}
+
+	fn set_inputs(&mut self, inputs: &[[usize; 3]]) -> Result<(), String> {
+		if inputs.len() < 2 {
+			return Err(format!(
+				"merge layers need at least 2 inputs but {} were given",
+				inputs.len()
+			));
+		}
+		if inputs.iter().any(|input| input.contains(&0)) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+
+		let first = inputs[0];
+		self.output = match self.merge_type {
+			MergeType::Add | MergeType::Multiply => {
+				if let Some(input) = inputs.iter().find(|&&input| input != first) {
+					return Err(format!(
+						"all inputs of a {:?} merge must have the same shape but got {:?} and {:?}",
+						self.merge_type, first, input
+					));
+				}
+				first
+			}
+			MergeType::Concatenate => {
+				if inputs.iter().all(|input| input[1] == 1 && input[2] == 1) {
+					[inputs.iter().map(|input| input[0]).sum(), 1, 1]
+				} else {
+					if let Some(input) = inputs
+						.iter()
+						.find(|input| input[0] != first[0] || input[1] != first[1])
+					{
+						return Err(format!(
+							"inputs of a concatenate merge must have the same width and height but got {:?} and {:?}, flatten the inputs to join them end to end",
+							first, input
+						));
+					}
+					[
+						first[0],
+						first[1],
+						inputs.iter().map(|input| input[2]).sum(),
+					]
+				}
+			}
+		};
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.merge_type {
+			MergeType::Add => "Add",
+			MergeType::Concatenate => "Concatenate",
+			MergeType::Multiply => "Multiply",
+		}
+	}
+}
+
+impl LayerTrait for Merge {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		self.backward_many(&[input], output_gradient).remove(0)
+	}
+
+	fn backward_many(&mut self, inputs: &[&Tensor], output_gradient: &Tensor) -> Vec<Tensor> {
+		let output_gradient = output_gradient.as_slice();
+		match self.info.merge_type {
+			MergeType::Add => inputs
+				.iter()
+				.map(|input| shaped(output_gradient.to_vec(), input.shape()))
+				.collect(),
+			MergeType::Concatenate => {
+				let mut start = 0;
+				inputs
+					.iter()
+					.map(|input| {
+						start += input.len();
+						shaped(
+							output_gradient[(start - input.len())..start].to_vec(),
+							input.shape(),
+						)
+					})
+					.collect()
+			}
+			MergeType::Multiply => (0..inputs.len())
+				.map(|i| {
+					let mut gradient = output_gradient.to_vec();
+					for (j, input) in inputs.iter().enumerate() {
+						if i != j {
+							for (gradient, value) in gradient.iter_mut().zip(input.as_slice()) {
+								*gradient *= value;
+							}
+						}
+					}
+					shaped(gradient, inputs[i].shape())
+				})
+				.collect(),
+		}
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		self.forward_many(vec![input]);
+	}
+
+	fn forward_many(&mut self, inputs: Vec<Tensor>) {
+		let inputs: Vec<Vec<Float>> = inputs.into_iter().map(Vec::from).collect();
+		self.output = match self.info.merge_type {
+			MergeType::Add => {
+				let mut output = inputs[0].clone();
+				for input in &inputs[1..] {
+					for (output, value) in output.iter_mut().zip(input) {
+						*output += value;
+					}
+				}
+				output
+			}
+			MergeType::Concatenate => inputs.concat(),
+			MergeType::Multiply => {
+				let mut output = inputs[0].clone();
+				for input in &inputs[1..] {
+					for (output, value) in output.iter_mut().zip(input) {
+						*output *= value;
+					}
+				}
+				output
+			}
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+}
+
+impl Merge {
+	pub fn new(info: MergeInfo) -> Self {
+		Merge {
+			info,
+			output: Vec::new(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::vector;
+
+	fn merge(merge_type: MergeType, inputs: &[[usize; 3]]) -> Merge {
+		let mut info = MergeInfo::new(merge_type);
+		info.set_inputs(inputs).unwrap();
+		Merge::new(info)
+	}
+
+	#[test]
+	fn concatenate_shapes() {
+		let mut info = MergeInfo::new(MergeType::Concatenate);
+		info.set_inputs(&[[3, 1, 1], [2, 1, 1]]).unwrap();
+		assert_eq!(info.output(), [5, 1, 1]);
+
+		info.set_inputs(&[[4, 4, 3], [4, 4, 2]]).unwrap();
+		assert_eq!(info.output(), [4, 4, 5]);
+
+		assert!(info.set_inputs(&[[4, 4, 3], [4, 2, 3]]).is_err());
+		assert!(MergeInfo::new(MergeType::Add)
+			.set_inputs(&[[3, 1, 1], [2, 1, 1]])
+			.is_err());
+	}
+
+	#[test]
+	fn multiply_backward() {
+		let mut layer = merge(MergeType::Multiply, &[[2, 1, 1]; 3]);
+		let inputs = vec![
+			vector(&[1.0, 2.0]),
+			vector(&[3.0, -1.0]),
+			vector(&[0.5, 4.0]),
+		];
+		layer.forward_many(inputs.clone());
+		assert_eq!(layer.last_output(), vector(&[1.5, -8.0]));
+
+		let input_refs: Vec<&Tensor> = inputs.iter().collect();
+		let gradients = layer.backward_many(&input_refs, &vector(&[1.0, 0.5]));
+		assert_eq!(
+			gradients,
+			vec![
+				vector(&[1.5, -2.0]),
+				vector(&[0.5, 4.0]),
+				vector(&[3.0, -1.0])
+			]
+		);
+	}
+
+	#[test]
+	fn concatenate_backward() {
+		let mut layer = merge(MergeType::Concatenate, &[[1, 1, 1], [2, 1, 1]]);
+		let inputs = [vector(&[1.0]), vector(&[2.0, 3.0])];
+		layer.forward_many(inputs.to_vec());
+		assert_eq!(layer.last_output(), vector(&[1.0, 2.0, 3.0]));
+
+		let gradients = layer.backward_many(&[&inputs[0], &inputs[1]], &vector(&[0.1, 0.2, 0.3]));
+		assert_eq!(gradients, vec![vector(&[0.1]), vector(&[0.2, 0.3])]);
+	}
+}
@@ -1,73 +1,455 @@
+pub mod attention;
+pub mod autogradlayer;
+pub mod batchnorm;
+pub mod conv1d;
+pub mod convtranspose2d;
+pub mod dropout;
+pub mod embedding;
 pub mod feedforward;
 pub mod inputlayer;
+pub mod layernorm;
+pub mod merge;
 pub mod outputlayer;
+pub mod pooling;
+pub mod recurrent;
+pub mod reshape;
+pub mod transformer;
+pub mod upsample;
 
-use crate::network::{Float, Regularisation};
+use crate::network::{
+	ActivationFunction, Float, InitType, Layout, NetworkError, Regularisation, Tensor,
+};
 
 use {
+	attention::{Attention, AttentionInfo},
+	batchnorm::{BatchNorm, BatchNormInfo},
+	conv1d::{Conv1D, Conv1DInfo},
+	convtranspose2d::{ConvTranspose2D, ConvTranspose2DInfo},
+	dropout::{Dropout, DropoutInfo},
+	embedding::{Embedding, EmbeddingInfo},
 	feedforward::{FeedForward, FeedForwardInfo},
 	inputlayer::{InputLayer, InputLayerInfo},
+	layernorm::{LayerNorm, LayerNormInfo},
+	merge::{Merge, MergeInfo},
 	outputlayer::{OutputLayer, OutputLayerInfo},
+	pooling::{GlobalPool, GlobalPoolInfo},
+	recurrent::{Recurrent, RecurrentInfo},
+	reshape::{Reshape, ReshapeInfo},
+	transformer::{TransformerEncoder, TransformerEncoderInfo},
+	upsample::{Upsample, UpsampleInfo},
 };
 
+// wraps values computed by a layer in a tensor, values of the wrong length are a bug in the layer
+pub(crate) fn shaped(values: Vec<Float>, shape: &[usize]) -> Tensor {
+	Tensor::new(values, shape).unwrap_or_else(|error| panic!("{}", error))
+}
+
+// a column vector for the tests of single layers, which only use the shape of their input as
+// the shape of its gradient
+#[cfg(test)]
+pub(crate) fn vector(values: &[Float]) -> Tensor {
+	shaped(values.to_vec(), &[values.len(), 1, 1])
+}
+
+// checks the gradients from backward_batch and update against central differences of a weighted
+// sum of the outputs of the mini batch, every step'th parameter is checked
+#[cfg(test)]
+pub(crate) fn check_gradients(layer: &mut dyn LayerTrait, mini_batch: &[Vec<Float>], step: usize) {
+	use crate::network::gradient_check::{parameter_mut, relative_error, set_parameters};
+
+	let epsilon = 1e-2;
+	let weight = |i: usize| (i as Float * 0.37 + 0.5).sin();
+	let cost = |layer: &mut dyn LayerTrait, mini_batch: &[Vec<Float>]| -> Float {
+		layer
+			.forward_batch(mini_batch.iter().map(|input| vec![vector(input)]).collect())
+			.iter()
+			.flat_map(|output| output.as_slice().to_vec())
+			.enumerate()
+			.map(|(i, output)| output * weight(i))
+			.sum()
+	};
+
+	let inputs: Vec<Tensor> = mini_batch.iter().map(|input| vector(input)).collect();
+	let outputs = layer.forward_batch(inputs.iter().map(|input| vec![input.clone()]).collect());
+	let mut offset = 0;
+	let output_gradients: Vec<Tensor> = outputs
+		.iter()
+		.map(|output| {
+			let values = (offset..offset + output.len()).map(weight).collect();
+			offset += output.len();
+			shaped(values, output.shape())
+		})
+		.collect();
+	let input_refs: Vec<Vec<&Tensor>> = inputs.iter().map(|input| vec![input]).collect();
+	let input_gradients = layer.backward_batch(&input_refs, &output_gradients);
+
+	// with a learning rate of 1 and a mini batch size of 1 update subtracts the gradient
+	let original = layer.parameters_mut().concat();
+	layer.update(1.0, 1, &Regularisation::None);
+	let updated = layer.parameters_mut().concat();
+	set_parameters(layer, &original);
+
+	for (sample, input) in mini_batch.iter().enumerate() {
+		for i in 0..input.len() {
+			let mut plus = mini_batch.to_vec();
+			plus[sample][i] += epsilon;
+			let mut minus = mini_batch.to_vec();
+			minus[sample][i] -= epsilon;
+			let numerical = (cost(layer, &plus) - cost(layer, &minus)) / (2.0 * epsilon);
+			let gradient = input_gradients[sample][0].as_slice()[i];
+			assert!(
+				relative_error(gradient, numerical) < 2e-2,
+				"input {} of sample {}: {} != {}",
+				i,
+				sample,
+				gradient,
+				numerical
+			);
+		}
+	}
+
+	for index in (0..original.len()).step_by(step) {
+		let parameter = original[index];
+		*parameter_mut(layer, index) = parameter + epsilon;
+		let plus = cost(layer, mini_batch);
+		*parameter_mut(layer, index) = parameter - epsilon;
+		let minus = cost(layer, mini_batch);
+		*parameter_mut(layer, index) = parameter;
+		let numerical = (plus - minus) / (2.0 * epsilon);
+		let gradient = parameter - updated[index];
+		assert!(
+			relative_error(gradient, numerical) < 2e-2,
+			"parameter {}: {} != {}",
+			index,
+			gradient,
+			numerical
+		);
+	}
+}
+
 pub enum LayerInfo {
+	Attention(AttentionInfo),
+	BatchNorm(BatchNormInfo),
+	Conv1D(Conv1DInfo),
+	ConvTranspose2D(ConvTranspose2DInfo),
+	Custom(Box<dyn CustomLayerInfo>),
+	Dropout(DropoutInfo),
+	Embedding(EmbeddingInfo),
 	FeedForward(FeedForwardInfo),
+	GlobalPool(GlobalPoolInfo),
 	InputLayer(InputLayerInfo),
+	LayerNorm(LayerNormInfo),
+	Merge(MergeInfo),
 	OutputLayer(OutputLayerInfo),
+	Recurrent(RecurrentInfo),
+	Reshape(ReshapeInfo),
+	TransformerEncoder(TransformerEncoderInfo),
+	Upsample(UpsampleInfo),
 }
 
+// layers are only created when a network is built so the size difference between variants doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Layer {
+	Attention(Attention),
+	BatchNorm(BatchNorm),
+	Conv1D(Conv1D),
+	ConvTranspose2D(ConvTranspose2D),
+	Custom(Box<dyn LayerTrait>),
+	Dropout(Dropout),
+	Embedding(Embedding),
 	FeedForward(FeedForward),
+	GlobalPool(GlobalPool),
 	InputLayer(InputLayer),
+	LayerNorm(LayerNorm),
+	Merge(Merge),
 	OutputLayer(OutputLayer),
+	Recurrent(Recurrent),
+	Reshape(Reshape),
+	TransformerEncoder(TransformerEncoder),
+	Upsample(Upsample),
 }
 
-impl LayerInfoTrait for LayerInfo {
-	fn output(&self) -> [usize; 3] {
+impl LayerInfo {
+	fn inner(&self) -> &dyn LayerInfoTrait {
 		match self {
-			LayerInfo::FeedForward(info) => info.output(),
-			LayerInfo::InputLayer(info) => info.output(),
-			LayerInfo::OutputLayer(info) => info.output(),
+			LayerInfo::Attention(info) => info,
+			LayerInfo::BatchNorm(info) => info,
+			LayerInfo::Conv1D(info) => info,
+			LayerInfo::ConvTranspose2D(info) => info,
+			LayerInfo::Custom(info) => info.as_ref(),
+			LayerInfo::Dropout(info) => info,
+			LayerInfo::Embedding(info) => info,
+			LayerInfo::FeedForward(info) => info,
+			LayerInfo::GlobalPool(info) => info,
+			LayerInfo::InputLayer(info) => info,
+			LayerInfo::LayerNorm(info) => info,
+			LayerInfo::Merge(info) => info,
+			LayerInfo::OutputLayer(info) => info,
+			LayerInfo::Recurrent(info) => info,
+			LayerInfo::Reshape(info) => info,
+			LayerInfo::TransformerEncoder(info) => info,
+			LayerInfo::Upsample(info) => info,
 		}
 	}
-}
 
-impl LayerTrait for Layer {
-	fn backward(
-		&mut self,
-		a: &[Float],
-		error_input: &[Float],
-		weights: (Vec<Float>, [usize; 3]),
-	) -> (Vec<Float>, Vec<Float>, [usize; 3]) {
+	fn inner_mut(&mut self) -> &mut dyn LayerInfoTrait {
 		match self {
-			Layer::InputLayer(layer) => layer.backward(a, error_input, weights),
-			Layer::FeedForward(layer) => layer.backward(a, error_input, weights),
-			Layer::OutputLayer(layer) => layer.backward(a, error_input, weights),
+			LayerInfo::Attention(info) => info,
+			LayerInfo::BatchNorm(info) => info,
+			LayerInfo::Conv1D(info) => info,
+			LayerInfo::ConvTranspose2D(info) => info,
+			LayerInfo::Custom(info) => info.as_mut(),
+			LayerInfo::Dropout(info) => info,
+			LayerInfo::Embedding(info) => info,
+			LayerInfo::FeedForward(info) => info,
+			LayerInfo::GlobalPool(info) => info,
+			LayerInfo::InputLayer(info) => info,
+			LayerInfo::LayerNorm(info) => info,
+			LayerInfo::Merge(info) => info,
+			LayerInfo::OutputLayer(info) => info,
+			LayerInfo::Recurrent(info) => info,
+			LayerInfo::Reshape(info) => info,
+			LayerInfo::TransformerEncoder(info) => info,
+			LayerInfo::Upsample(info) => info,
 		}
 	}
 
-	fn forward(&mut self, input: Vec<Float>) {
+	// input_shapes must have been passed to set_inputs first
+	pub fn create_layer(&self, input_shapes: &[[usize; 3]]) -> Layer {
+		let input_shape = input_shapes.first().copied().unwrap_or([0; 3]);
+		let input_size = input_shape.iter().product();
+
 		match self {
-			Layer::FeedForward(layer) => layer.forward(input),
-			Layer::InputLayer(layer) => layer.forward(input),
-			Layer::OutputLayer(layer) => layer.forward(input),
+			LayerInfo::Attention(info) => Layer::Attention(Attention::new(*info)),
+			LayerInfo::BatchNorm(info) => Layer::BatchNorm(BatchNorm::new(*info)),
+			LayerInfo::Conv1D(info) => Layer::Conv1D(Conv1D::new(*info)),
+			LayerInfo::ConvTranspose2D(info) => Layer::ConvTranspose2D(ConvTranspose2D::new(*info)),
+			LayerInfo::Custom(info) => Layer::Custom(info.create_layer(input_shape)),
+			LayerInfo::Dropout(info) => Layer::Dropout(Dropout::new(*info)),
+			LayerInfo::Embedding(info) => Layer::Embedding(Embedding::new(*info)),
+			LayerInfo::FeedForward(info) => Layer::FeedForward(FeedForward::new(*info, input_size)),
+			LayerInfo::GlobalPool(info) => Layer::GlobalPool(GlobalPool::new(*info)),
+			LayerInfo::InputLayer(info) => Layer::InputLayer(InputLayer::new(*info)),
+			LayerInfo::LayerNorm(info) => Layer::LayerNorm(LayerNorm::new(*info)),
+			LayerInfo::Merge(info) => Layer::Merge(Merge::new(info.clone())),
+			LayerInfo::OutputLayer(info) => Layer::OutputLayer(OutputLayer::new(*info, input_size)),
+			LayerInfo::Recurrent(info) => Layer::Recurrent(Recurrent::new(*info)),
+			LayerInfo::Reshape(info) => Layer::Reshape(Reshape::new(*info)),
+			LayerInfo::TransformerEncoder(info) => {
+				Layer::TransformerEncoder(TransformerEncoder::new(*info))
+			}
+			LayerInfo::Upsample(info) => Layer::Upsample(Upsample::new(*info)),
 		}
 	}
+}
+
+impl LayerInfoTrait for LayerInfo {
+	fn output(&self) -> [usize; 3] {
+		self.inner().output()
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		self.inner_mut().set_input(input)
+	}
+
+	fn set_inputs(&mut self, inputs: &[[usize; 3]]) -> Result<(), String> {
+		self.inner_mut().set_inputs(inputs)
+	}
+
+	fn check_parameters(&self) -> Result<(), String> {
+		self.inner().check_parameters()
+	}
+
+	fn name(&self) -> &str {
+		self.inner().name()
+	}
 
-	fn last_output(&self) -> Vec<Float> {
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		self.inner().activation_function()
+	}
+
+	fn supports_activation(&self, activation: ActivationFunction) -> bool {
+		self.inner().supports_activation(activation)
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		self.inner().init_type()
+	}
+}
+
+impl Layer {
+	fn inner(&self) -> &dyn LayerTrait {
 		match self {
-			Layer::FeedForward(layer) => layer.last_output(),
-			Layer::InputLayer(layer) => layer.last_output(),
-			Layer::OutputLayer(layer) => layer.last_output(),
+			Layer::Attention(layer) => layer,
+			Layer::BatchNorm(layer) => layer,
+			Layer::Conv1D(layer) => layer,
+			Layer::ConvTranspose2D(layer) => layer,
+			Layer::Custom(layer) => layer.as_ref(),
+			Layer::Dropout(layer) => layer,
+			Layer::Embedding(layer) => layer,
+			Layer::FeedForward(layer) => layer,
+			Layer::GlobalPool(layer) => layer,
+			Layer::InputLayer(layer) => layer,
+			Layer::LayerNorm(layer) => layer,
+			Layer::Merge(layer) => layer,
+			Layer::OutputLayer(layer) => layer,
+			Layer::Recurrent(layer) => layer,
+			Layer::Reshape(layer) => layer,
+			Layer::TransformerEncoder(layer) => layer,
+			Layer::Upsample(layer) => layer,
 		}
 	}
 
-	fn last_z_values(&self) -> Vec<Float> {
+	fn inner_mut(&mut self) -> &mut dyn LayerTrait {
 		match self {
-			Layer::FeedForward(layer) => layer.last_z_values(),
-			Layer::InputLayer(layer) => layer.last_z_values(),
-			Layer::OutputLayer(layer) => layer.last_z_values(),
+			Layer::Attention(layer) => layer,
+			Layer::BatchNorm(layer) => layer,
+			Layer::Conv1D(layer) => layer,
+			Layer::ConvTranspose2D(layer) => layer,
+			Layer::Custom(layer) => layer.as_mut(),
+			Layer::Dropout(layer) => layer,
+			Layer::Embedding(layer) => layer,
+			Layer::FeedForward(layer) => layer,
+			Layer::GlobalPool(layer) => layer,
+			Layer::InputLayer(layer) => layer,
+			Layer::LayerNorm(layer) => layer,
+			Layer::Merge(layer) => layer,
+			Layer::OutputLayer(layer) => layer,
+			Layer::Recurrent(layer) => layer,
+			Layer::Reshape(layer) => layer,
+			Layer::TransformerEncoder(layer) => layer,
+			Layer::Upsample(layer) => layer,
+		}
+	}
+}
+
+impl Layer {
+	// tensors passed between layers are checked here so a layer given or returning the wrong
+	// shape fails with the name of the layer rather than somewhere inside it (or inside BLAS),
+	// tensors in another layout are converted to column major
+	fn check_shape(
+		&self,
+		tensor: &'static str,
+		shape: &[usize],
+		expected: &[usize],
+	) -> Result<(), NetworkError> {
+		match shape == expected {
+			true => Ok(()),
+			false => Err(NetworkError::TensorShape {
+				layer: self.info().name().to_string(),
+				tensor,
+				shape: shape.to_vec(),
+				expected: expected.to_vec(),
+			}),
+		}
+	}
+
+	fn check_input_gradients(
+		&self,
+		inputs: &[&Tensor],
+		input_gradients: &[Tensor],
+	) -> Result<(), NetworkError> {
+		assert_eq!(
+			inputs.len(),
+			input_gradients.len(),
+			"a {} layer returned a gradient for {} inputs but has {}",
+			self.info().name(),
+			input_gradients.len(),
+			inputs.len()
+		);
+		for (input, input_gradient) in inputs.iter().zip(input_gradients) {
+			self.check_shape("input gradient", input_gradient.shape(), input.shape())?;
+		}
+		Ok(())
+	}
+
+	// the output of the last forward pass, the network returns the error from predict and
+	// forward_tokens, LayerTrait::last_output panics with it
+	pub(crate) fn try_last_output(&self) -> Result<Tensor, NetworkError> {
+		let output = self.inner().last_output().into_layout(Layout::ColumnMajor);
+		self.check_shape("output", output.shape(), &self.info().output())?;
+		Ok(output)
+	}
+}
+
+fn column_major(tensors: Vec<Tensor>) -> Vec<Tensor> {
+	tensors
+		.into_iter()
+		.map(|tensor| tensor.into_layout(Layout::ColumnMajor))
+		.collect()
+}
+
+fn or_panic<T>(result: Result<T, NetworkError>) -> T {
+	result.unwrap_or_else(|error| panic!("{}", error))
+}
+
+impl LayerTrait for Layer {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		self.backward_many(&[input], output_gradient).remove(0)
+	}
+
+	fn backward_many(&mut self, inputs: &[&Tensor], output_gradient: &Tensor) -> Vec<Tensor> {
+		or_panic(self.check_shape(
+			"output gradient",
+			output_gradient.shape(),
+			&self.info().output(),
+		));
+		let input_gradients = column_major(self.inner_mut().backward_many(inputs, output_gradient));
+		or_panic(self.check_input_gradients(inputs, &input_gradients));
+		input_gradients
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		self.inner_mut()
+			.forward(input.into_layout(Layout::ColumnMajor))
+	}
+
+	fn forward_many(&mut self, inputs: Vec<Tensor>) {
+		self.inner_mut().forward_many(column_major(inputs))
+	}
+
+	fn backward_batch(
+		&mut self,
+		inputs: &[Vec<&Tensor>],
+		output_gradients: &[Tensor],
+	) -> Vec<Vec<Tensor>> {
+		let output = self.info().output();
+		for output_gradient in output_gradients {
+			or_panic(self.check_shape("output gradient", output_gradient.shape(), &output));
+		}
+		let input_gradients: Vec<Vec<Tensor>> = self
+			.inner_mut()
+			.backward_batch(inputs, output_gradients)
+			.into_iter()
+			.map(column_major)
+			.collect();
+		for (inputs, input_gradients) in inputs.iter().zip(&input_gradients) {
+			or_panic(self.check_input_gradients(inputs, input_gradients));
 		}
+		input_gradients
+	}
+
+	fn forward_batch(&mut self, inputs: Vec<Vec<Tensor>>) -> Vec<Tensor> {
+		let inputs = inputs.into_iter().map(column_major).collect();
+		let outputs = column_major(self.inner_mut().forward_batch(inputs));
+		for output in &outputs {
+			or_panic(self.check_shape("output", output.shape(), &self.info().output()));
+		}
+		outputs
+	}
+
+	fn uses_batch_statistics(&self) -> bool {
+		self.inner().uses_batch_statistics()
+	}
+
+	fn last_output(&self) -> Tensor {
+		or_panic(self.try_last_output())
+	}
+
+	fn last_z_values(&self) -> Tensor {
+		self.inner()
+			.last_z_values()
+			.into_layout(Layout::ColumnMajor)
 	}
 
 	fn update(
@@ -76,25 +458,40 @@ impl LayerTrait for Layer {
 		mini_batch_size: usize,
 		regularisation: &Regularisation,
 	) {
-		match self {
-			Layer::FeedForward(layer) => {
-				(*layer).update(learning_rate, mini_batch_size, regularisation)
-			}
-			Layer::InputLayer(layer) => {
-				(*layer).update(learning_rate, mini_batch_size, regularisation)
-			}
-			Layer::OutputLayer(layer) => {
-				(*layer).update(learning_rate, mini_batch_size, regularisation)
-			}
-		}
+		self.inner_mut()
+			.update(learning_rate, mini_batch_size, regularisation)
 	}
 
 	fn update_change(&mut self, errors: &[Float], a: &[Float]) {
-		match self {
-			Layer::FeedForward(layer) => layer.update_change(errors, a),
-			Layer::InputLayer(layer) => layer.update_change(errors, a),
-			Layer::OutputLayer(layer) => layer.update_change(errors, a),
-		}
+		self.inner_mut().update_change(errors, a)
+	}
+
+	fn set_training(&mut self, training: bool) {
+		self.inner_mut().set_training(training)
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		self.inner().info()
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		self.inner().weights()
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		self.inner().biases()
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		self.inner().weight_dimensions()
+	}
+
+	fn parameter_count(&self) -> usize {
+		self.inner().parameter_count()
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		self.inner_mut().parameters_mut()
 	}
 }
 
@@ -105,24 +502,145 @@ pub trait LayerInfoTrait {
 	}
 
 	fn output(&self) -> [usize; 3];
+
+	// called with the output shape of the previous layer before output() is used
+	// so layers can infer their shape, Err contains the reason the input can't be used
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		Ok(())
+	}
+
+	// checks settings that are invalid whatever the input is, such as a dropout rate,
+	// called before set_inputs, Err contains the reason
+	fn check_parameters(&self) -> Result<(), String> {
+		Ok(())
+	}
+
+	// layers with more than one input (merges) get the shapes of all their inputs
+	fn set_inputs(&mut self, inputs: &[[usize; 3]]) -> Result<(), String> {
+		if inputs.len() != 1 {
+			return Err(format!(
+				"layer takes 1 input but {} were given",
+				inputs.len()
+			));
+		}
+		self.set_input(inputs[0])
+	}
+
+	// used when printing a network summary
+	fn name(&self) -> &str;
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		None
+	}
+
+	// softmax depends on every value of a layer so layers that apply their activation function
+	// to one value at a time can't use it
+	fn supports_activation(&self, _: ActivationFunction) -> bool {
+		true
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		None
+	}
+}
+
+/// Allows layers defined outside of this crate to be used in a `Network`
+/// through `LayerInfo::Custom`.
+pub trait CustomLayerInfo: LayerInfoTrait {
+	// input is the shape of the first input, layers with more than one input
+	// receive every input shape through set_inputs
+	fn create_layer(&self, input: [usize; 3]) -> Box<dyn LayerTrait>;
 }
 
 pub trait LayerTrait {
-	fn backward(
-		&mut self,
-		a: &[Float],
-		error_input: &[Float],
-		weights: (Vec<Float>, [usize; 3]),
-		// errors, weights, dimensions_weights
-	) -> (Vec<Float>, Vec<Float>, [usize; 3]);
-	fn forward(&mut self, input: Vec<Float>);
-	fn last_output(&self) -> Vec<Float>;
-	fn last_z_values(&self) -> Vec<Float>;
-	fn update(
+	// takes the gradient of the cost with respect to the output of the layer,
+	// accumulates the layer's change and returns the gradient with respect to the input
+	//
+	// tensors are column major, outputs and output gradients have the shape info().output() and
+	// input gradients have the shape of their input, in a network Layer converts tensors in other
+	// layouts and checks the shapes
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor;
+	// used for layers with multiple inputs, returns the gradient with respect to each input
+	fn backward_many(&mut self, inputs: &[&Tensor], output_gradient: &Tensor) -> Vec<Tensor> {
+		assert_eq!(inputs.len(), 1);
+		vec![self.backward(inputs[0], output_gradient)]
+	}
+	fn forward(&mut self, input: Tensor);
+	fn forward_many(&mut self, mut inputs: Vec<Tensor>) {
+		assert_eq!(inputs.len(), 1);
+		self.forward(inputs.remove(0))
+	}
+	// processes a mini batch at once, inputs[sample][input]
+	// returns the output for every sample
+	fn forward_batch(&mut self, inputs: Vec<Vec<Tensor>>) -> Vec<Tensor> {
+		inputs
+			.into_iter()
+			.map(|inputs| {
+				self.forward_many(inputs);
+				self.last_output()
+			})
+			.collect()
+	}
+	// must be called after forward_batch with the same inputs
+	// returns the gradient with respect to each input for every sample
+	fn backward_batch(
 		&mut self,
-		learning_rate: Float,
-		mini_batch_size: usize,
-		regularisation: &Regularisation,
-	);
-	fn update_change(&mut self, errors: &[Float], a: &[Float]);
+		inputs: &[Vec<&Tensor>],
+		output_gradients: &[Tensor],
+	) -> Vec<Vec<Tensor>> {
+		let batch_size = inputs.len();
+		inputs
+			.iter()
+			.zip(output_gradients)
+			.map(|(inputs, output_gradient)| {
+				// layers only keep the values from their last forward pass
+				// so they have to be recalculated for each sample
+				if batch_size > 1 {
+					self.forward_many(inputs.iter().map(|&input| input.clone()).collect());
+				}
+				self.backward_many(inputs, output_gradient)
+			})
+			.collect()
+	}
+	// layers that normalise over a mini batch need the network to use forward_batch
+	// and backward_batch with the whole mini batch rather than one sample at a time
+	fn uses_batch_statistics(&self) -> bool {
+		false
+	}
+	fn last_output(&self) -> Tensor;
+	fn last_z_values(&self) -> Tensor {
+		self.last_output()
+	}
+	fn update(&mut self, _learning_rate: Float, _mini_batch_size: usize, _: &Regularisation) {}
+	fn update_change(&mut self, _errors: &[Float], _a: &[Float]) {}
+	// switches between training and evaluation behaviour e.g. dropout is only used in training
+	fn set_training(&mut self, _training: bool) {}
+
+	fn info(&self) -> &dyn LayerInfoTrait;
+
+	// weights are column major with dimensions [outputs, inputs]
+	fn weights(&self) -> Option<&[Float]> {
+		None
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		None
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		None
+	}
+
+	fn parameter_count(&self) -> usize {
+		self.weights().map_or(0, |weights| weights.len())
+			+ self.biases().map_or(0, |biases| biases.len())
+	}
+
+	// every value changed by update, used by gradient_check
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		Vec::new()
+	}
 }
@@ -1,12 +1,12 @@
 use crate::network::change::OutputLayerChange;
+use crate::network::simd::{add_assign, l2_update};
 use crate::network::utility::{
 	matrix_multiply_sum, outer_product_add, plus_equals_matrix_multiplied, scale_elements,
+	transpose_matrix_multiply_vec,
 };
-use crate::network::{ActivationFunction, CostFunction, Float, InitType, Regularisation};
+use crate::network::{ActivationFunction, CostFunction, Float, InitType, Regularisation, Tensor};
 
-extern crate openblas_src;
-
-use super::{LayerInfoTrait, LayerTrait};
+use super::{shaped, LayerInfoTrait, LayerTrait};
 
 pub struct OutputLayerData {
 	biases: Vec<Float>,
@@ -17,6 +17,7 @@ pub struct OutputLayerData {
 #[derive(Copy, Clone)]
 pub struct OutputLayerInfo {
 	pub activation_function: ActivationFunction,
+	pub bias: Float,
 	pub cost_function: CostFunction,
 	pub init_type: InitType,
 	pub length: usize,
@@ -35,8 +36,20 @@ pub struct OutputLayer {
 }
 
 impl OutputLayerData {
-	pub fn new(init_type: InitType, weight_dimensions: [usize; 2]) -> Self {
-		let biases = vec![0.0; weight_dimensions[0]];
+	pub fn biases(&self) -> &[Float] {
+		&self.biases
+	}
+
+	pub fn weights(&self) -> &[Float] {
+		&self.weights
+	}
+
+	pub fn weight_dimensions(&self) -> [usize; 2] {
+		self.weight_dimensions
+	}
+
+	pub fn new(init_type: InitType, bias: Float, weight_dimensions: [usize; 2]) -> Self {
+		let biases = vec![bias; weight_dimensions[0]];
 		let mut weights = Vec::new();
 		for _ in 0..(weight_dimensions[0] * weight_dimensions[1]) {
 			weights.push(init_type.generate_weight(weight_dimensions[1], weight_dimensions[0]));
@@ -59,6 +72,12 @@ impl OutputLayerOutput {
 	}
 }
 
+impl Default for OutputLayerOutput {
+	fn default() -> Self {
+		OutputLayerOutput::new()
+	}
+}
+
 impl OutputLayerInfo {
 	pub fn new(
 		activation_function: ActivationFunction,
@@ -68,6 +87,7 @@ impl OutputLayerInfo {
 	) -> Self {
 		OutputLayerInfo {
 			activation_function,
+			bias: 0.0,
 			cost_function,
 			init_type,
 			length,
@@ -79,74 +99,67 @@ impl LayerInfoTrait for OutputLayerInfo {
 	fn output(&self) -> [usize; 3] {
 		[self.length, 1, 1]
 	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		self.cost_function
+			.check(self.length)
+			.map_err(|error| format!("the custom cost failed: {}", error))
+	}
+
+	fn name(&self) -> &str {
+		"Output"
+	}
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		Some(self.activation_function)
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
 }
 
 impl LayerTrait for OutputLayer {
-	fn backward(
-		&mut self,
-		a: &[Float],
-		output: &[Float],
-		expected_output: (Vec<Float>, [usize; 3]),
-	) -> (Vec<Float>, Vec<Float>, [usize; 3]) {
-		let expected_output = &expected_output.0;
-
-		let errors: Vec<Float> = output
-			.iter()
-			.zip(expected_output.iter())
-			.zip(self.outputs.before_activation.iter())
-			.map(|((output, expected_output), z)| {
-				self.info.cost_function.c_dz(
-					&self.info.activation_function,
-					output,
-					expected_output,
-					z,
-				)
-			})
-			.collect();
-
-		self.update_change(&errors, a);
-
-		(
-			errors,
-			self.data.weights.clone(),
-			[
-				self.data.weight_dimensions[0],
-				self.data.weight_dimensions[1],
-				1,
-			],
-		)
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let errors = self.info.activation_function.backward_all(
+			&self.outputs.before_activation,
+			&self.outputs.after_activation,
+			output_gradient.as_slice(),
+		);
+
+		self.update_change(&errors, input.as_slice());
+
+		self.input_gradient(&errors, input)
 	}
 
-	fn forward(&mut self, input: Vec<Float>) {
+	fn forward(&mut self, input: Tensor) {
 		assert_eq!(self.data.weight_dimensions[1], input.len());
-		self.outputs.before_activation = self.data.biases.clone();
+		self.outputs.before_activation = vec![0.0; self.data.biases.len()];
 
 		// change to matrix vector operation?
 		matrix_multiply_sum(
 			&self.data.weights,
-			&input,
+			input.as_slice(),
 			self.data.weight_dimensions,
 			&mut self.outputs.before_activation,
 		);
+		add_assign(&mut self.outputs.before_activation, &self.data.biases);
 
-		// is this optimal??
-		// perhaps have activation_function(outputs.before_activation: &[]) -> Vec<>
-		let output = self
-			.outputs
-			.before_activation
-			.iter()
-			.map(|&z| self.info.activation_function.evaluate(z))
-			.collect();
-
-		self.outputs.after_activation = output;
+		self.outputs.after_activation = self
+			.info
+			.activation_function
+			.evaluate_all(&self.outputs.before_activation);
 	}
 
-	fn last_output(&self) -> Vec<Float> {
-		self.outputs.after_activation.clone()
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.after_activation.clone(), &self.info.output())
 	}
 
-	fn last_z_values(&self) -> Vec<Float> {
-		self.outputs.before_activation.clone()
+	fn last_z_values(&self) -> Tensor {
+		shaped(self.outputs.before_activation.clone(), &self.info.output())
 	}
 
 	fn update(
@@ -155,8 +168,8 @@ impl LayerTrait for OutputLayer {
 		mini_batch_size: usize,
 		regularisation: &Regularisation,
 	) {
-		match &self.change {
-			Some(change) => match regularisation {
+		if let Some(change) = &self.change {
+			match regularisation {
 				Regularisation::L1(lambda) => {
 					let multiplier = -1.0 / mini_batch_size as Float;
 
@@ -174,11 +187,7 @@ impl LayerTrait for OutputLayer {
 				Regularisation::L2(lambda) => {
 					let multiplier = -learning_rate / mini_batch_size as Float;
 
-					// is there a more optimal way to do this?
-					for (weight, change) in self.data.weights.iter_mut().zip(change.weights.iter())
-					{
-						*weight += multiplier * (change + lambda * weight.signum());
-					}
+					l2_update(&mut self.data.weights, &change.weights, multiplier, *lambda);
 				}
 				Regularisation::None => {
 					let multiplier = -learning_rate / mini_batch_size as Float;
@@ -195,8 +204,7 @@ impl LayerTrait for OutputLayer {
 						&change.biases,
 					);
 				}
-			},
-			None => {}
+			}
 		}
 		self.change = Some(OutputLayer::empty_layer_change(
 			&self.data.weight_dimensions,
@@ -208,19 +216,45 @@ impl LayerTrait for OutputLayer {
 		let biases = &mut change.biases;
 		let weights = &mut change.weights;
 
-		for (bias, error) in biases.iter_mut().zip(errors) {
-			*bias += error;
-		}
+		add_assign(biases, errors);
 
-		outer_product_add(&errors, &a, weights);
+		outer_product_add(errors, a, weights);
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(self.data.weights())
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(self.data.biases())
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.data.weights, &mut self.data.biases]
+	}
+
+	fn weight_dimensions(&self) -> Option<[usize; 2]> {
+		Some(self.data.weight_dimensions())
 	}
 }
 
 impl OutputLayer {
+	pub fn cost_function(&self) -> CostFunction {
+		self.info.cost_function
+	}
+
+	pub fn data(&self) -> &OutputLayerData {
+		&self.data
+	}
+
 	pub fn new(info: OutputLayerInfo, input_size: usize) -> Self {
 		let weight_dimensions = [info.length, input_size];
 
-		let data = OutputLayerData::new(info.init_type, weight_dimensions);
+		let data = OutputLayerData::new(info.init_type, info.bias, weight_dimensions);
 
 		OutputLayer {
 			change: Some(OutputLayer::empty_layer_change(&weight_dimensions)),
@@ -229,6 +263,38 @@ impl OutputLayer {
 			outputs: OutputLayerOutput::new(),
 		}
 	}
+	// starts backpropagation using the cost function rather than a gradient from the next layer
+	pub fn cost_backward(&mut self, input: &Tensor, expected_output: &[Float]) -> Tensor {
+		let errors = self.info.cost_function.errors(
+			&self.info.activation_function,
+			&self.outputs.before_activation,
+			&self.outputs.after_activation,
+			expected_output,
+		);
+
+		self.update_change(&errors, input.as_slice());
+
+		self.input_gradient(&errors, input)
+	}
+
+	// the cost of the output from the last forward pass
+	pub fn cost(&self, expected_output: &[Float]) -> Float {
+		self.info
+			.cost_function
+			.evaluate(&self.outputs.after_activation, expected_output)
+	}
+
+	fn input_gradient(&self, errors: &[Float], input: &Tensor) -> Tensor {
+		let mut input_gradient = Vec::new();
+		transpose_matrix_multiply_vec(
+			&self.data.weights,
+			errors,
+			self.data.weight_dimensions,
+			&mut input_gradient,
+		);
+		shaped(input_gradient, input.shape())
+	}
+
 	fn empty_layer_change(weight_dim: &[usize; 2]) -> OutputLayerChange {
 		OutputLayerChange::new(weight_dim)
 	}
@@ -237,13 +303,11 @@ impl OutputLayer {
 #[macro_export]
 macro_rules! output {
 	($activation_function:expr, $cost_function:expr, $init_type:expr, $length:expr) => {
-		neural_network::layer::LayerInfo::OutputLayer(
-			neural_network::layer::outputlayer::OutputLayerInfo::new(
-				$activation_function,
-				$cost_function,
-				$init_type,
-				$length,
-			),
-		)
+		$crate::layer::LayerInfo::OutputLayer($crate::layer::outputlayer::OutputLayerInfo::new(
+			$activation_function,
+			$cost_function,
+			$init_type,
+			$length,
+		))
 	};
 }
@@ -0,0 +1,237 @@
+use crate::network::{Float, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PoolType {
+	Average,
+	Max,
+	// softmax over the positions of each channel, outputs the expected x and y coordinate
+	// (from -1 to 1) of each channel as [x_0, y_0, x_1, y_1, ..]
+	SpatialSoftmax,
+}
+
+// reduces [w, h, c] inputs to [c, 1, 1] ([2c, 1, 1] for spatial softmax)
+#[derive(Copy, Clone)]
+pub struct GlobalPoolInfo {
+	input: [usize; 3],
+	pub pool_type: PoolType,
+}
+
+pub struct GlobalPool {
+	info: GlobalPoolInfo,
+	// the position of the maximum of each channel
+	max_positions: Vec<usize>,
+	output: Vec<Float>,
+	// the softmax of each channel
+	probabilities: Vec<Float>,
+}
+
+impl GlobalPoolInfo {
+	pub fn new(pool_type: PoolType) -> Self {
+		GlobalPoolInfo {
+			input: [0; 3],
+			pool_type,
+		}
+	}
+
+	fn positions(&self) -> usize {
+		self.input[0] * self.input[1]
+	}
+
+	// the x and y coordinates of a position scaled to -1 to 1
+	fn coordinates(&self, position: usize) -> [Float; 2] {
+		let scale = |value: usize, size: usize| match size {
+			1 => 0.0,
+			_ => 2.0 * value as Float / (size - 1) as Float - 1.0,
+		};
+		[
+			scale(position % self.input[0], self.input[0]),
+			scale(position / self.input[0], self.input[1]),
+		]
+	}
+}
+
+impl LayerInfoTrait for GlobalPoolInfo {
+	fn output(&self) -> [usize; 3] {
+		match self.pool_type {
+			PoolType::Average | PoolType::Max => [self.input[2], 1, 1],
+			PoolType::SpatialSoftmax => [2 * self.input[2], 1, 1],
+		}
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.pool_type {
+			PoolType::Average => "GlobalAveragePool",
+			PoolType::Max => "GlobalMaxPool",
+			PoolType::SpatialSoftmax => "SpatialSoftmax",
+		}
+	}
+}
+
+impl LayerTrait for GlobalPool {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let output_gradient = output_gradient.as_slice();
+		let positions = self.info.positions();
+		let mut input_gradient = vec![0.0; input.len()];
+
+		match self.info.pool_type {
+			PoolType::Average => {
+				for (gradient, output_gradient) in
+					input_gradient.chunks_mut(positions).zip(output_gradient)
+				{
+					gradient.fill(output_gradient / positions as Float);
+				}
+			}
+			PoolType::Max => {
+				for (c, &position) in self.max_positions.iter().enumerate() {
+					input_gradient[c * positions + position] = output_gradient[c];
+				}
+			}
+			PoolType::SpatialSoftmax => {
+				for (c, (gradient, probabilities)) in input_gradient
+					.chunks_mut(positions)
+					.zip(self.probabilities.chunks(positions))
+					.enumerate()
+				{
+					let expected = [self.output[2 * c], self.output[2 * c + 1]];
+					let output_gradient = [output_gradient[2 * c], output_gradient[2 * c + 1]];
+					for (position, (gradient, probability)) in
+						gradient.iter_mut().zip(probabilities).enumerate()
+					{
+						let [x, y] = self.info.coordinates(position);
+						*gradient = probability
+							* (output_gradient[0] * (x - expected[0])
+								+ output_gradient[1] * (y - expected[1]));
+					}
+				}
+			}
+		}
+		shaped(input_gradient, input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let input = input.as_slice();
+		let positions = self.info.positions();
+		assert_eq!(positions * self.info.input[2], input.len());
+		let channels = input.chunks(positions);
+
+		self.output = match self.info.pool_type {
+			PoolType::Average => channels
+				.map(|channel| channel.iter().sum::<Float>() / positions as Float)
+				.collect(),
+			PoolType::Max => {
+				self.max_positions = channels
+					.map(|channel| {
+						(0..positions)
+							.reduce(|max, i| if channel[i] > channel[max] { i } else { max })
+							.unwrap()
+					})
+					.collect();
+				self.max_positions
+					.iter()
+					.enumerate()
+					.map(|(c, &position)| input[c * positions + position])
+					.collect()
+			}
+			PoolType::SpatialSoftmax => {
+				self.probabilities = channels
+					.flat_map(|channel| {
+						let max = channel.iter().copied().fold(Float::MIN, Float::max);
+						let exponentials: Vec<Float> =
+							channel.iter().map(|value| (value - max).exp()).collect();
+						let sum: Float = exponentials.iter().sum();
+						exponentials.into_iter().map(move |value| value / sum)
+					})
+					.collect();
+				self.probabilities
+					.chunks(positions)
+					.flat_map(|probabilities| {
+						let mut expected = [0.0; 2];
+						for (position, probability) in probabilities.iter().enumerate() {
+							let [x, y] = self.info.coordinates(position);
+							expected[0] += probability * x;
+							expected[1] += probability * y;
+						}
+						expected
+					})
+					.collect()
+			}
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+}
+
+impl GlobalPool {
+	pub fn new(info: GlobalPoolInfo) -> Self {
+		GlobalPool {
+			info,
+			max_positions: Vec::new(),
+			output: Vec::new(),
+			probabilities: Vec::new(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn pool(pool_type: PoolType) -> GlobalPool {
+		let mut info = GlobalPoolInfo::new(pool_type);
+		info.set_input([3, 2, 2]).unwrap();
+		GlobalPool::new(info)
+	}
+
+	#[test]
+	fn pooling() {
+		let input = vec![
+			1.0, 5.0, 3.0, 0.0, 2.0, 1.0, -1.0, -2.0, -6.0, -3.0, -4.0, -5.0,
+		];
+
+		let mut average = pool(PoolType::Average);
+		average.forward(vector(&input));
+		assert_eq!(average.last_output().as_slice(), [2.0, -3.5]);
+
+		let mut max = pool(PoolType::Max);
+		max.forward(vector(&input));
+		assert_eq!(max.last_output().as_slice(), [5.0, -1.0]);
+		assert_eq!(
+			max.backward(&vector(&input), &vector(&[1.0, 2.0])),
+			vector(&[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0])
+		);
+
+		// a large value at the bottom right moves the expected position there
+		let mut spatial = pool(PoolType::SpatialSoftmax);
+		spatial.forward(vector(&[
+			0.0, 0.0, 0.0, 0.0, 0.0, 50.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+		]));
+		let output = spatial.last_output().into_vec();
+		assert!((output[0] - 1.0).abs() < 1e-5 && (output[1] - 1.0).abs() < 1e-5);
+		assert!(output[2].abs() < 1e-6 && output[3].abs() < 1e-6);
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input: Vec<Float> = (0..12).map(|i| (i as Float * 0.7).sin()).collect();
+		for pool_type in [PoolType::Average, PoolType::Max, PoolType::SpatialSoftmax] {
+			check_gradients(&mut pool(pool_type), std::slice::from_ref(&input), 1);
+		}
+	}
+}
@@ -0,0 +1,586 @@
+use crate::network::change::ParameterChange;
+use crate::network::utility::{
+	matrix_vec_multiply_add, outer_product_add, plus_equals_matrix_multiplied,
+	transpose_matrix_multiply_vec,
+};
+use crate::network::{ActivationFunction, Float, InitType, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CellType {
+	// h = activation(W x + U h + b)
+	RNN,
+	// input, forget, cell and output gates with a separate cell state
+	LSTM,
+	// update, reset and candidate gates
+	GRU,
+}
+
+impl CellType {
+	fn gates(&self) -> usize {
+		match self {
+			CellType::RNN => 1,
+			CellType::LSTM => 4,
+			CellType::GRU => 3,
+		}
+	}
+}
+
+// takes a sequence with the shape [seq_len, features, 1] where the value for
+// feature d at timestep t is at t + seq_len * d
+#[derive(Copy, Clone)]
+pub struct RecurrentInfo {
+	// only used by RNN cells, LSTM and GRU gates always use sigmoid and tanh
+	pub activation_function: ActivationFunction,
+	pub cell_type: CellType,
+	pub init_type: InitType,
+	input: [usize; 3],
+	pub length: usize,
+	// outputs the hidden state of every timestep ([seq_len, length, 1])
+	// rather than only the hidden state of the last timestep ([length, 1, 1])
+	pub return_sequences: bool,
+	// gradients only flow back through this many timesteps (truncated backpropagation through time)
+	pub truncation: Option<usize>,
+}
+
+// values from a timestep of the last forward pass that are needed by backward
+struct TimeStep {
+	input: Vec<Float>,
+	// the z values for RNN cells and the gate activations for LSTM and GRU cells
+	gates: Vec<Float>,
+	// LSTM cell state
+	cell: Vec<Float>,
+	// reset gate * previous hidden state for GRU cells
+	reset_hidden: Vec<Float>,
+	hidden: Vec<Float>,
+}
+
+pub struct Recurrent {
+	biases: Vec<Float>,
+	change: ParameterChange,
+	info: RecurrentInfo,
+	output: Vec<Float>,
+	steps: Vec<TimeStep>,
+	// the input weights of every gate ([length, features] each)
+	// followed by the hidden weights of every gate ([length, length] each)
+	weights: Vec<Float>,
+}
+
+impl RecurrentInfo {
+	pub fn new(
+		cell_type: CellType,
+		activation_function: ActivationFunction,
+		init_type: InitType,
+		length: usize,
+	) -> Self {
+		RecurrentInfo {
+			activation_function,
+			cell_type,
+			init_type,
+			input: [0; 3],
+			length,
+			return_sequences: false,
+			truncation: None,
+		}
+	}
+
+	fn sequence_length(&self) -> usize {
+		self.input[0]
+	}
+
+	fn features(&self) -> usize {
+		self.input[1] * self.input[2]
+	}
+}
+
+impl LayerInfoTrait for RecurrentInfo {
+	fn output(&self) -> [usize; 3] {
+		match self.return_sequences {
+			true => [self.input[0], self.length, 1],
+			false => [self.length, 1, 1],
+		}
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if self.truncation == Some(0) {
+			return Err(String::from("truncation must be at least 1 timestep"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.cell_type {
+			CellType::RNN => "RNN",
+			CellType::LSTM => "LSTM",
+			CellType::GRU => "GRU",
+		}
+	}
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		match self.cell_type {
+			CellType::RNN => Some(self.activation_function),
+			_ => None,
+		}
+	}
+
+	// only rnn cells use the activation function, activation_function is None for the others
+	fn supports_activation(&self, activation: ActivationFunction) -> bool {
+		activation != ActivationFunction::Softmax
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl LayerTrait for Recurrent {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let output_gradient = output_gradient.as_slice();
+		let seq_len = self.info.sequence_length();
+		let features = self.info.features();
+		let length = self.info.length;
+
+		let mut input_gradient = vec![0.0; seq_len * features];
+		// gradients with respect to the hidden and cell state passed back from the next timestep
+		let mut hidden_gradient = vec![0.0; length];
+		let mut cell_gradient = vec![0.0; length];
+		let zeros = vec![0.0; length];
+
+		let steps = std::mem::take(&mut self.steps);
+		for t in (0..seq_len).rev() {
+			let step = &steps[t];
+			let (previous_hidden, previous_cell) = match t {
+				0 => (&zeros, &zeros),
+				_ => (&steps[t - 1].hidden, &steps[t - 1].cell),
+			};
+
+			for (k, gradient) in hidden_gradient.iter_mut().enumerate() {
+				*gradient += match self.info.return_sequences {
+					true => output_gradient[t + seq_len * k],
+					false if t == seq_len - 1 => output_gradient[k],
+					false => 0.0,
+				};
+			}
+
+			let mut step_input_gradient = vec![0.0; features];
+			let mut previous_hidden_gradient = vec![0.0; length];
+			match self.info.cell_type {
+				CellType::RNN => {
+					let errors: Vec<Float> = hidden_gradient
+						.iter()
+						.zip(step.gates.iter())
+						.map(|(gradient, &z)| {
+							gradient * self.info.activation_function.derivative(z)
+						})
+						.collect();
+					let (input, hidden) =
+						self.gate_backward(0, &errors, &step.input, previous_hidden);
+					add(&mut step_input_gradient, &input);
+					add(&mut previous_hidden_gradient, &hidden);
+				}
+				CellType::LSTM => {
+					let gates = &step.gates;
+					let mut errors = vec![0.0; 4 * length];
+					for k in 0..length {
+						let (i, f, g, o) = (
+							gates[k],
+							gates[length + k],
+							gates[2 * length + k],
+							gates[3 * length + k],
+						);
+						let tanh_cell = step.cell[k].tanh();
+						let cell =
+							hidden_gradient[k] * o * (1.0 - tanh_cell.powi(2)) + cell_gradient[k];
+
+						errors[k] = cell * g * i * (1.0 - i);
+						errors[length + k] = cell * previous_cell[k] * f * (1.0 - f);
+						errors[2 * length + k] = cell * i * (1.0 - g.powi(2));
+						errors[3 * length + k] = hidden_gradient[k] * tanh_cell * o * (1.0 - o);
+						cell_gradient[k] = cell * f;
+					}
+
+					for gate in 0..4 {
+						let (input, hidden) = self.gate_backward(
+							gate,
+							&errors[(gate * length)..((gate + 1) * length)],
+							&step.input,
+							previous_hidden,
+						);
+						add(&mut step_input_gradient, &input);
+						add(&mut previous_hidden_gradient, &hidden);
+					}
+				}
+				CellType::GRU => {
+					let gates = &step.gates;
+					let mut errors = vec![0.0; 3 * length];
+					for k in 0..length {
+						let (z, n) = (gates[k], gates[2 * length + k]);
+						errors[k] = hidden_gradient[k] * (previous_hidden[k] - n) * z * (1.0 - z);
+						errors[2 * length + k] = hidden_gradient[k] * (1.0 - z) * (1.0 - n.powi(2));
+						previous_hidden_gradient[k] = hidden_gradient[k] * z;
+					}
+
+					// the candidate gate uses the reset hidden state so the reset gate's error depends on it
+					let (input, reset_hidden_gradient) = self.gate_backward(
+						2,
+						&errors[(2 * length)..],
+						&step.input,
+						&step.reset_hidden,
+					);
+					add(&mut step_input_gradient, &input);
+					for k in 0..length {
+						let r = gates[length + k];
+						errors[length + k] =
+							reset_hidden_gradient[k] * previous_hidden[k] * r * (1.0 - r);
+						previous_hidden_gradient[k] += reset_hidden_gradient[k] * r;
+					}
+
+					for gate in 0..2 {
+						let (input, hidden) = self.gate_backward(
+							gate,
+							&errors[(gate * length)..((gate + 1) * length)],
+							&step.input,
+							previous_hidden,
+						);
+						add(&mut step_input_gradient, &input);
+						add(&mut previous_hidden_gradient, &hidden);
+					}
+				}
+			}
+
+			for (d, gradient) in step_input_gradient.into_iter().enumerate() {
+				input_gradient[t + seq_len * d] = gradient;
+			}
+
+			hidden_gradient = previous_hidden_gradient;
+			// blocks are counted back from the last timestep so it always reaches truncation timesteps
+			if let Some(truncation) = self.info.truncation {
+				if (seq_len - t) % truncation == 0 {
+					hidden_gradient = vec![0.0; length];
+					cell_gradient = vec![0.0; length];
+				}
+			}
+		}
+		self.steps = steps;
+
+		shaped(input_gradient, input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let input = input.as_slice();
+		let seq_len = self.info.sequence_length();
+		let features = self.info.features();
+		let length = self.info.length;
+		assert_eq!(seq_len * features, input.len());
+
+		let sigmoid = |z: Float| ActivationFunction::Sigmoid.evaluate(z);
+
+		let mut hidden = vec![0.0; length];
+		let mut cell = Vec::new();
+		let mut steps = Vec::with_capacity(seq_len);
+		for t in 0..seq_len {
+			let step_input: Vec<Float> = (0..features).map(|d| input[t + seq_len * d]).collect();
+			let mut reset_hidden = Vec::new();
+
+			let gates = match self.info.cell_type {
+				CellType::RNN => {
+					let z = self.gate_input(0, &step_input, &hidden);
+					hidden = z
+						.iter()
+						.map(|&z| self.info.activation_function.evaluate(z))
+						.collect();
+					z
+				}
+				CellType::LSTM => {
+					let mut gates = Vec::with_capacity(4 * length);
+					for gate in 0..4 {
+						let z = self.gate_input(gate, &step_input, &hidden);
+						match gate {
+							2 => gates.extend(z.iter().map(|z| z.tanh())),
+							_ => gates.extend(z.into_iter().map(sigmoid)),
+						}
+					}
+
+					cell = (0..length)
+						.map(|k| {
+							let previous = cell.get(k).copied().unwrap_or(0.0);
+							gates[length + k] * previous + gates[k] * gates[2 * length + k]
+						})
+						.collect();
+					hidden = (0..length)
+						.map(|k| gates[3 * length + k] * cell[k].tanh())
+						.collect();
+					gates
+				}
+				CellType::GRU => {
+					let mut gates = Vec::with_capacity(3 * length);
+					for gate in 0..2 {
+						let z = self.gate_input(gate, &step_input, &hidden);
+						gates.extend(z.into_iter().map(sigmoid));
+					}
+
+					reset_hidden = (0..length).map(|k| gates[length + k] * hidden[k]).collect();
+					let candidate = self.gate_input(2, &step_input, &reset_hidden);
+					gates.extend(candidate.iter().map(|z| z.tanh()));
+
+					hidden = (0..length)
+						.map(|k| {
+							let z = gates[k];
+							(1.0 - z) * gates[2 * length + k] + z * hidden[k]
+						})
+						.collect();
+					gates
+				}
+			};
+
+			steps.push(TimeStep {
+				input: step_input,
+				gates,
+				cell: cell.clone(),
+				reset_hidden,
+				hidden: hidden.clone(),
+			});
+		}
+
+		self.output = match self.info.return_sequences {
+			true => (0..length)
+				.flat_map(|k| steps.iter().map(move |step| step.hidden[k]))
+				.collect(),
+			false => hidden,
+		};
+		self.steps = steps;
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	// regularisation isn't supported for recurrent layers yet
+	fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		plus_equals_matrix_multiplied(&mut self.weights, multiplier, &self.change.weights);
+		plus_equals_matrix_multiplied(&mut self.biases, multiplier, &self.change.biases);
+
+		self.change = ParameterChange::new(self.weights.len(), self.biases.len());
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	fn weights(&self) -> Option<&[Float]> {
+		Some(&self.weights)
+	}
+
+	fn biases(&self) -> Option<&[Float]> {
+		Some(&self.biases)
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		vec![&mut self.weights, &mut self.biases]
+	}
+}
+
+impl Recurrent {
+	pub fn new(info: RecurrentInfo) -> Self {
+		let gates = info.cell_type.gates();
+		let features = info.features();
+		let length = info.length;
+
+		let weights: Vec<Float> = (0..(gates * length * features))
+			.map(|_| info.init_type.generate_weight(features, length))
+			.chain(
+				(0..(gates * length * length))
+					.map(|_| info.init_type.generate_weight(length, length)),
+			)
+			.collect();
+
+		let mut biases = vec![0.0; gates * length];
+		// a forget gate bias of 1 keeps the cell state early in training
+		if info.cell_type == CellType::LSTM {
+			for bias in &mut biases[length..(2 * length)] {
+				*bias = 1.0;
+			}
+		}
+
+		Recurrent {
+			change: ParameterChange::new(weights.len(), biases.len()),
+			biases,
+			info,
+			output: Vec::new(),
+			steps: Vec::new(),
+			weights,
+		}
+	}
+
+	// ranges of the input weights and hidden weights of a gate in weights
+	fn weight_ranges(&self, gate: usize) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+		let length = self.info.length;
+		let input_size = length * self.info.features();
+		let hidden_start = self.info.cell_type.gates() * input_size + gate * length * length;
+		(
+			(gate * input_size)..((gate + 1) * input_size),
+			hidden_start..(hidden_start + length * length),
+		)
+	}
+
+	// z = W x + U h + b for a gate
+	fn gate_input(&self, gate: usize, input: &[Float], hidden: &[Float]) -> Vec<Float> {
+		let length = self.info.length;
+		let (input_range, hidden_range) = self.weight_ranges(gate);
+
+		let mut z = self.biases[(gate * length)..((gate + 1) * length)].to_vec();
+		matrix_vec_multiply_add(
+			&self.weights[input_range],
+			input,
+			&mut z,
+			&[length, input.len()],
+		);
+		matrix_vec_multiply_add(
+			&self.weights[hidden_range],
+			hidden,
+			&mut z,
+			&[length, length],
+		);
+		z
+	}
+
+	// accumulates the change of a gate from its errors (gradient with respect to z)
+	// and returns the gradients with respect to the input and hidden state
+	fn gate_backward(
+		&mut self,
+		gate: usize,
+		errors: &[Float],
+		input: &[Float],
+		hidden: &[Float],
+	) -> (Vec<Float>, Vec<Float>) {
+		let length = self.info.length;
+		let (input_range, hidden_range) = self.weight_ranges(gate);
+
+		for (bias, error) in self.change.biases[(gate * length)..((gate + 1) * length)]
+			.iter_mut()
+			.zip(errors)
+		{
+			*bias += error;
+		}
+		outer_product_add(errors, input, &mut self.change.weights[input_range.clone()]);
+		outer_product_add(
+			errors,
+			hidden,
+			&mut self.change.weights[hidden_range.clone()],
+		);
+
+		let mut input_gradient = Vec::new();
+		transpose_matrix_multiply_vec(
+			&self.weights[input_range],
+			errors,
+			[length, input.len()],
+			&mut input_gradient,
+		);
+		let mut hidden_gradient = Vec::new();
+		transpose_matrix_multiply_vec(
+			&self.weights[hidden_range],
+			errors,
+			[length, length],
+			&mut hidden_gradient,
+		);
+		(input_gradient, hidden_gradient)
+	}
+}
+
+fn add(total: &mut [Float], values: &[Float]) {
+	for (total, value) in total.iter_mut().zip(values) {
+		*total += value;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn recurrent(
+		cell_type: CellType,
+		return_sequences: bool,
+		truncation: Option<usize>,
+	) -> Recurrent {
+		recurrent_with_length(cell_type, return_sequences, truncation, 4)
+	}
+
+	fn recurrent_with_length(
+		cell_type: CellType,
+		return_sequences: bool,
+		truncation: Option<usize>,
+		seq_len: usize,
+	) -> Recurrent {
+		let mut info = RecurrentInfo::new(
+			cell_type,
+			ActivationFunction::Tanh,
+			InitType::NormalisedXavier,
+			3,
+		);
+		info.return_sequences = return_sequences;
+		info.truncation = truncation;
+		info.set_input([seq_len, 2, 1]).unwrap();
+		Recurrent::new(info)
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input = vec![0.3, -1.2, 0.8, 0.5, -0.5, 0.1, 1.1, -0.7];
+		for cell_type in [CellType::RNN, CellType::LSTM, CellType::GRU] {
+			for return_sequences in [false, true] {
+				let mut layer = recurrent(cell_type, return_sequences, None);
+				assert_eq!(
+					layer.info().flattened_output(),
+					if return_sequences { 12 } else { 3 }
+				);
+				check_gradients(&mut layer, std::slice::from_ref(&input), 1);
+			}
+		}
+	}
+
+	#[test]
+	fn truncation() {
+		let input = vec![0.3, -1.2, 0.8, 0.5, -0.5, 0.1, 1.1, -0.7];
+		let mut layer = recurrent(CellType::LSTM, false, Some(2));
+		layer.forward(vector(&input));
+		let gradient = layer
+			.backward(&vector(&input), &vector(&[1.0, 1.0, 1.0]))
+			.into_vec();
+
+		// only the last 2 timesteps are reached from the final hidden state
+		for t in 0..4 {
+			let reached = gradient[t] != 0.0 || gradient[t + 4] != 0.0;
+			assert_eq!(reached, t >= 2);
+		}
+
+		// with a sequence that isn't a multiple of truncation the gradient of the last
+		// timestep still reaches the last 2 timesteps
+		let input: Vec<Float> = (0..10).map(|i| (i as Float * 0.7).sin()).collect();
+		for return_sequences in [false, true] {
+			let mut layer = recurrent_with_length(CellType::GRU, return_sequences, Some(2), 5);
+			layer.forward(vector(&input));
+			let output_gradient: Vec<Float> = match return_sequences {
+				true => (0..15)
+					.map(|i| if i % 5 == 4 { 1.0 } else { 0.0 })
+					.collect(),
+				false => vec![1.0; 3],
+			};
+			let gradient = layer
+				.backward(&vector(&input), &vector(&output_gradient))
+				.into_vec();
+
+			for t in 0..5 {
+				let reached = gradient[t] != 0.0 || gradient[t + 5] != 0.0;
+				assert_eq!(reached, t >= 3, "timestep {}", t);
+			}
+		}
+	}
+}
@@ -0,0 +1,111 @@
+use crate::network::{Float, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+// changes the shape of the input without moving any values, None flattens the input to [n, 1, 1]
+#[derive(Copy, Clone)]
+pub struct ReshapeInfo {
+	input: [usize; 3],
+	pub target: Option<[usize; 3]>,
+}
+
+pub struct Reshape {
+	info: ReshapeInfo,
+	output: Vec<Float>,
+}
+
+impl ReshapeInfo {
+	pub fn new(target: [usize; 3]) -> Self {
+		ReshapeInfo {
+			input: [0; 3],
+			target: Some(target),
+		}
+	}
+
+	pub fn flatten() -> Self {
+		ReshapeInfo {
+			input: [0; 3],
+			target: None,
+		}
+	}
+}
+
+impl LayerInfoTrait for ReshapeInfo {
+	fn output(&self) -> [usize; 3] {
+		self.target.unwrap_or([self.input.iter().product(), 1, 1])
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if let Some(target) = self.target {
+			let (input_size, target_size) = (
+				input.iter().product::<usize>(),
+				target.iter().product::<usize>(),
+			);
+			if input_size != target_size {
+				return Err(format!(
+					"can't reshape {:?} ({} values) to {:?} ({} values)",
+					input, input_size, target, target_size
+				));
+			}
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.target {
+			Some(_) => "Reshape",
+			None => "Flatten",
+		}
+	}
+}
+
+impl LayerTrait for Reshape {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		shaped(output_gradient.as_slice().to_vec(), input.shape())
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		assert_eq!(self.info.flattened_output(), input.len());
+
+		self.output = input.into_vec();
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+}
+
+impl Reshape {
+	pub fn new(info: ReshapeInfo) -> Self {
+		Reshape {
+			info,
+			output: Vec::new(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shapes() {
+		let mut flatten = ReshapeInfo::flatten();
+		flatten.set_input([4, 3, 2]).unwrap();
+		assert_eq!(flatten.output(), [24, 1, 1]);
+		assert_eq!(flatten.name(), "Flatten");
+
+		let mut reshape = ReshapeInfo::new([6, 2, 2]);
+		reshape.set_input([4, 3, 2]).unwrap();
+		assert_eq!(reshape.output(), [6, 2, 2]);
+		assert!(reshape.set_input([4, 3, 3]).is_err());
+	}
+}
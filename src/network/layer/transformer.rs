@@ -0,0 +1,439 @@
+use crate::network::change::{NormalisationChange, ParameterChange};
+use crate::network::utility::{general_matrix_multiply, plus_equals_matrix_multiplied};
+use crate::network::{ActivationFunction, Float, InitType, Regularisation, Tensor};
+
+use super::{
+	attention::{Attention, AttentionInfo},
+	layernorm::{normalise, normalise_backward, NormType},
+	shaped, LayerInfoTrait, LayerTrait,
+};
+
+// multi-head self-attention followed by a position-wise feed-forward network,
+// each with a residual connection and layer normalisation (post-norm)
+#[derive(Copy, Clone)]
+pub struct TransformerEncoderInfo {
+	// used by the hidden layer of the feed-forward network
+	pub activation_function: ActivationFunction,
+	pub epsilon: Float,
+	pub heads: usize,
+	// size of the hidden layer of the feed-forward network
+	pub hidden: usize,
+	pub init_type: InitType,
+	input: [usize; 3],
+}
+
+// layer normalisation applied to each timestep of a [seq_len, d_model] matrix,
+// every timestep shares the same scale and shift
+struct SequenceNorm {
+	beta: Vec<Float>,
+	change: NormalisationChange,
+	epsilon: Float,
+	gamma: Vec<Float>,
+	inverse_std: Vec<Float>,
+	normalised: Vec<Float>,
+}
+
+// values from the last forward pass that are needed by backward
+#[derive(Default)]
+struct TransformerEncoderOutput {
+	// output of the attention sublayer after normalisation
+	attention: Vec<Float>,
+	hidden_z: Vec<Float>,
+	hidden: Vec<Float>,
+	output: Vec<Float>,
+}
+
+pub struct TransformerEncoder {
+	attention: Attention,
+	// the first weights are [d_model, hidden] and the second are [hidden, d_model]
+	biases: Vec<Float>,
+	change: ParameterChange,
+	first_norm: SequenceNorm,
+	info: TransformerEncoderInfo,
+	outputs: TransformerEncoderOutput,
+	second_norm: SequenceNorm,
+	weights: Vec<Float>,
+}
+
+impl TransformerEncoderInfo {
+	pub fn new(
+		heads: usize,
+		hidden: usize,
+		activation_function: ActivationFunction,
+		init_type: InitType,
+	) -> Self {
+		TransformerEncoderInfo {
+			activation_function,
+			epsilon: 1e-5,
+			heads,
+			hidden,
+			init_type,
+			input: [0; 3],
+		}
+	}
+
+	fn attention_info(&self) -> AttentionInfo {
+		AttentionInfo::new(self.heads, self.init_type)
+	}
+}
+
+impl LayerInfoTrait for TransformerEncoderInfo {
+	fn output(&self) -> [usize; 3] {
+		self.input
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		self.attention_info().set_input(input)?;
+		if self.hidden == 0 {
+			return Err(String::from(
+				"the feed-forward hidden layer has a size of 0",
+			));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		"TransformerEncoder"
+	}
+
+	fn activation_function(&self) -> Option<ActivationFunction> {
+		Some(self.activation_function)
+	}
+
+	fn supports_activation(&self, activation: ActivationFunction) -> bool {
+		activation != ActivationFunction::Softmax
+	}
+
+	fn init_type(&self) -> Option<InitType> {
+		Some(self.init_type)
+	}
+}
+
+impl SequenceNorm {
+	fn new(model_size: usize, epsilon: Float) -> Self {
+		SequenceNorm {
+			beta: vec![0.0; model_size],
+			change: NormalisationChange::new(model_size),
+			epsilon,
+			gamma: vec![1.0; model_size],
+			inverse_std: Vec::new(),
+			normalised: Vec::new(),
+		}
+	}
+
+	fn forward(&mut self, input: &[Float]) -> Vec<Float> {
+		let seq_len = input.len() / self.gamma.len();
+
+		self.normalised = vec![0.0; input.len()];
+		self.inverse_std = (0..seq_len)
+			.map(|t| {
+				let step: Vec<Float> = (0..self.gamma.len())
+					.map(|j| input[t + seq_len * j])
+					.collect();
+				let (normalised, inverse_std) = normalise(&step, self.epsilon, NormType::Layer);
+				for (j, value) in normalised.into_iter().enumerate() {
+					self.normalised[t + seq_len * j] = value;
+				}
+				inverse_std
+			})
+			.collect();
+
+		self.normalised
+			.iter()
+			.enumerate()
+			.map(|(i, value)| self.gamma[i / seq_len] * value + self.beta[i / seq_len])
+			.collect()
+	}
+
+	fn backward(&mut self, output_gradient: &[Float]) -> Vec<Float> {
+		let seq_len = output_gradient.len() / self.gamma.len();
+
+		for (i, (gradient, value)) in output_gradient.iter().zip(&self.normalised).enumerate() {
+			self.change.gamma[i / seq_len] += gradient * value;
+			self.change.beta[i / seq_len] += gradient;
+		}
+
+		let mut input_gradient = vec![0.0; output_gradient.len()];
+		for t in 0..seq_len {
+			let (normalised_gradient, normalised): (Vec<Float>, Vec<Float>) = (0..self.gamma.len())
+				.map(|j| {
+					(
+						output_gradient[t + seq_len * j] * self.gamma[j],
+						self.normalised[t + seq_len * j],
+					)
+				})
+				.unzip();
+			let gradient = normalise_backward(
+				&normalised_gradient,
+				&normalised,
+				self.inverse_std[t],
+				NormType::Layer,
+			);
+			for (j, value) in gradient.into_iter().enumerate() {
+				input_gradient[t + seq_len * j] = value;
+			}
+		}
+		input_gradient
+	}
+
+	fn update(&mut self, multiplier: Float) {
+		plus_equals_matrix_multiplied(&mut self.gamma, multiplier, &self.change.gamma);
+		plus_equals_matrix_multiplied(&mut self.beta, multiplier, &self.change.beta);
+		self.change = NormalisationChange::new(self.gamma.len());
+	}
+}
+
+impl LayerTrait for TransformerEncoder {
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let seq_len = self.info.input[0];
+		let model_size = self.info.input[1];
+		let hidden = self.info.hidden;
+		let (first_weights, second_weights) = self.weights.split_at(model_size * hidden);
+
+		let outputs = std::mem::take(&mut self.outputs);
+		let feed_forward_gradient = self.second_norm.backward(output_gradient.as_slice());
+
+		// second layer of the feed-forward network
+		general_matrix_multiply(
+			[true, false],
+			[hidden, model_size, seq_len],
+			1.0,
+			&outputs.hidden,
+			&feed_forward_gradient,
+			1.0,
+			&mut self.change.weights[(model_size * hidden)..],
+		);
+		add_column_sums(
+			&feed_forward_gradient,
+			seq_len,
+			&mut self.change.biases[hidden..],
+		);
+
+		let mut hidden_gradient = vec![0.0; seq_len * hidden];
+		general_matrix_multiply(
+			[false, true],
+			[seq_len, hidden, model_size],
+			1.0,
+			&feed_forward_gradient,
+			second_weights,
+			0.0,
+			&mut hidden_gradient,
+		);
+		for (gradient, &z) in hidden_gradient.iter_mut().zip(&outputs.hidden_z) {
+			*gradient *= self.info.activation_function.derivative(z);
+		}
+
+		// first layer of the feed-forward network
+		general_matrix_multiply(
+			[true, false],
+			[model_size, hidden, seq_len],
+			1.0,
+			&outputs.attention,
+			&hidden_gradient,
+			1.0,
+			&mut self.change.weights[..(model_size * hidden)],
+		);
+		add_column_sums(&hidden_gradient, seq_len, &mut self.change.biases[..hidden]);
+
+		// the residual connection passes the gradient straight through
+		let mut attention_gradient = feed_forward_gradient;
+		general_matrix_multiply(
+			[false, true],
+			[seq_len, model_size, hidden],
+			1.0,
+			&hidden_gradient,
+			first_weights,
+			1.0,
+			&mut attention_gradient,
+		);
+
+		let attention_gradient =
+			shaped(self.first_norm.backward(&attention_gradient), input.shape());
+		let mut input_gradient = self.attention.backward(input, &attention_gradient);
+		for (gradient, residual) in input_gradient
+			.as_mut_slice()
+			.iter_mut()
+			.zip(attention_gradient.as_slice())
+		{
+			*gradient += residual;
+		}
+
+		self.outputs = outputs;
+		input_gradient
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let seq_len = self.info.input[0];
+		let model_size = self.info.input[1];
+		let hidden = self.info.hidden;
+		let (first_weights, second_weights) = self.weights.split_at(model_size * hidden);
+
+		self.attention.forward(input.clone());
+		let residual: Vec<Float> = self
+			.attention
+			.last_output()
+			.as_slice()
+			.iter()
+			.zip(input.as_slice())
+			.map(|(output, input)| output + input)
+			.collect();
+		let attention = self.first_norm.forward(&residual);
+
+		// the feed-forward network is applied to each timestep (row) separately
+		let mut hidden_z = repeat_columns(&self.biases[..hidden], seq_len);
+		general_matrix_multiply(
+			[false, false],
+			[seq_len, hidden, model_size],
+			1.0,
+			&attention,
+			first_weights,
+			1.0,
+			&mut hidden_z,
+		);
+		let hidden_output: Vec<Float> = hidden_z
+			.iter()
+			.map(|&z| self.info.activation_function.evaluate(z))
+			.collect();
+
+		let mut residual = repeat_columns(&self.biases[hidden..], seq_len);
+		general_matrix_multiply(
+			[false, false],
+			[seq_len, model_size, hidden],
+			1.0,
+			&hidden_output,
+			second_weights,
+			1.0,
+			&mut residual,
+		);
+		for (value, attention) in residual.iter_mut().zip(&attention) {
+			*value += attention;
+		}
+		let output = self.second_norm.forward(&residual);
+
+		self.outputs = TransformerEncoderOutput {
+			attention,
+			hidden_z,
+			hidden: hidden_output,
+			output,
+		};
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.outputs.output.clone(), &self.info.output())
+	}
+
+	fn update(
+		&mut self,
+		learning_rate: Float,
+		mini_batch_size: usize,
+		regularisation: &Regularisation,
+	) {
+		let multiplier = -learning_rate / mini_batch_size as Float;
+
+		self.attention
+			.update(learning_rate, mini_batch_size, regularisation);
+		self.first_norm.update(multiplier);
+		self.second_norm.update(multiplier);
+
+		plus_equals_matrix_multiplied(&mut self.weights, multiplier, &self.change.weights);
+		plus_equals_matrix_multiplied(&mut self.biases, multiplier, &self.change.biases);
+		self.change = ParameterChange::new(self.weights.len(), self.biases.len());
+	}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+
+	// the weights and biases are split between the sublayers so only the total is reported
+	fn parameter_count(&self) -> usize {
+		self.attention.parameter_count()
+			+ self.weights.len()
+			+ self.biases.len()
+			+ 4 * self.info.input[1]
+	}
+
+	fn parameters_mut(&mut self) -> Vec<&mut [Float]> {
+		let mut parameters = self.attention.parameters_mut();
+		parameters.extend([
+			&mut self.weights[..],
+			&mut self.biases,
+			&mut self.first_norm.gamma,
+			&mut self.first_norm.beta,
+			&mut self.second_norm.gamma,
+			&mut self.second_norm.beta,
+		]);
+		parameters
+	}
+}
+
+impl TransformerEncoder {
+	pub fn new(info: TransformerEncoderInfo) -> Self {
+		let mut attention_info = info.attention_info();
+		attention_info
+			.set_input(info.input)
+			.expect("input shape is checked by set_input");
+
+		let model_size = info.input[1];
+		let hidden = info.hidden;
+		let weights: Vec<Float> = (0..(model_size * hidden))
+			.map(|_| info.init_type.generate_weight(model_size, hidden))
+			.chain(
+				(0..(hidden * model_size))
+					.map(|_| info.init_type.generate_weight(hidden, model_size)),
+			)
+			.collect();
+		let biases = vec![0.0; hidden + model_size];
+
+		TransformerEncoder {
+			attention: Attention::new(attention_info),
+			change: ParameterChange::new(weights.len(), biases.len()),
+			biases,
+			first_norm: SequenceNorm::new(model_size, info.epsilon),
+			info,
+			outputs: TransformerEncoderOutput::default(),
+			second_norm: SequenceNorm::new(model_size, info.epsilon),
+			weights,
+		}
+	}
+
+	pub fn attention(&self) -> &Attention {
+		&self.attention
+	}
+}
+
+// a column major [rows, values.len()] matrix where every row is values
+fn repeat_columns(values: &[Float], rows: usize) -> Vec<Float> {
+	values
+		.iter()
+		.flat_map(|&value| std::iter::repeat(value).take(rows))
+		.collect()
+}
+
+// adds the sum of each column of a column major matrix with the given number of rows
+fn add_column_sums(matrix: &[Float], rows: usize, sums: &mut [Float]) {
+	for (sum, column) in sums.iter_mut().zip(matrix.chunks(rows)) {
+		*sum += column.iter().sum::<Float>();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::check_gradients;
+
+	#[test]
+	fn gradient_check() {
+		let mut info =
+			TransformerEncoderInfo::new(2, 5, ActivationFunction::Tanh, InitType::NormalisedXavier);
+		info.set_input([3, 4, 1]).unwrap();
+		let mut layer = TransformerEncoder::new(info);
+		assert_eq!(
+			layer.parameter_count(),
+			4 * (4 * 4 + 4) + (4 * 5 + 5) + (5 * 4 + 4) + 4 * 4
+		);
+
+		let input: Vec<Float> = (0..12).map(|i| (i as Float * 1.3).cos()).collect();
+		check_gradients(&mut layer, &[input], 3);
+	}
+}
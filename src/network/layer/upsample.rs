@@ -0,0 +1,184 @@
+use crate::network::{Float, Regularisation, Tensor};
+
+use super::{shaped, LayerInfoTrait, LayerTrait};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UpsampleType {
+	// every output copies the closest input
+	Nearest,
+	// every output is interpolated from the 4 closest inputs, the corners of the
+	// input and output are aligned at the edges of the pixels rather than their centres
+	Bilinear,
+}
+
+// scales the width and height of [w, h, c] inputs by scale, the channels are unchanged
+#[derive(Copy, Clone)]
+pub struct UpsampleInfo {
+	input: [usize; 3],
+	pub scale: usize,
+	pub upsample_type: UpsampleType,
+}
+
+pub struct Upsample {
+	info: UpsampleInfo,
+	output: Vec<Float>,
+	// the 2 inputs along each axis used by every output position and their weights
+	sources: [Vec<[(usize, Float); 2]>; 2],
+}
+
+impl UpsampleInfo {
+	pub fn new(upsample_type: UpsampleType, scale: usize) -> Self {
+		UpsampleInfo {
+			input: [0; 3],
+			scale,
+			upsample_type,
+		}
+	}
+
+	fn sources(&self, dimension: usize) -> Vec<[(usize, Float); 2]> {
+		let size = self.input[dimension];
+		(0..(size * self.scale))
+			.map(|position| match self.upsample_type {
+				UpsampleType::Nearest => [(position / self.scale, 1.0), (0, 0.0)],
+				UpsampleType::Bilinear => {
+					let source = ((position as Float + 0.5) / self.scale as Float - 0.5).max(0.0);
+					let first = (source as usize).min(size - 1);
+					let second = (first + 1).min(size - 1);
+					let weight = source - first as Float;
+					[(first, 1.0 - weight), (second, weight)]
+				}
+			})
+			.collect()
+	}
+}
+
+impl LayerInfoTrait for UpsampleInfo {
+	fn output(&self) -> [usize; 3] {
+		[
+			self.input[0] * self.scale,
+			self.input[1] * self.scale,
+			self.input[2],
+		]
+	}
+
+	fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+		if input.contains(&0) {
+			return Err(String::from("input has a zero sized dimension"));
+		}
+		if self.scale == 0 {
+			return Err(String::from("scale must be at least 1"));
+		}
+		self.input = input;
+		Ok(())
+	}
+
+	fn name(&self) -> &str {
+		match self.upsample_type {
+			UpsampleType::Nearest => "NearestUpsample",
+			UpsampleType::Bilinear => "BilinearUpsample",
+		}
+	}
+}
+
+impl LayerTrait for Upsample {
+	// each input gets the gradient of every output it contributed to scaled by its weight
+	fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+		let mut input_gradient = Tensor::zeros(input.shape());
+		let values = input_gradient.as_mut_slice();
+		self.for_each_source(|output, input, weight| {
+			values[input] += weight * output_gradient.as_slice()[output];
+		});
+		input_gradient
+	}
+
+	fn forward(&mut self, input: Tensor) {
+		let input = input.as_slice();
+		let mut output = vec![0.0; self.info.output().iter().product()];
+		self.for_each_source(|output_index, input_index, weight| {
+			output[output_index] += weight * input[input_index];
+		});
+		self.output = output;
+	}
+
+	fn last_output(&self) -> Tensor {
+		shaped(self.output.clone(), &self.info.output())
+	}
+
+	fn update(&mut self, _: Float, _: usize, _: &Regularisation) {}
+
+	fn info(&self) -> &dyn LayerInfoTrait {
+		&self.info
+	}
+}
+
+impl Upsample {
+	pub fn new(info: UpsampleInfo) -> Self {
+		Upsample {
+			info,
+			output: Vec::new(),
+			sources: [info.sources(0), info.sources(1)],
+		}
+	}
+
+	// calls f with the output index, input index and weight of every input used by every output
+	fn for_each_source<F: FnMut(usize, usize, Float)>(&self, mut f: F) {
+		let [width, height, channels] = self.info.input;
+		let (output_width, output_height) = (self.sources[0].len(), self.sources[1].len());
+
+		for c in 0..channels {
+			for (oy, y_sources) in self.sources[1].iter().enumerate() {
+				for (ox, x_sources) in self.sources[0].iter().enumerate() {
+					let output = ox + output_width * (oy + output_height * c);
+					for &(y, y_weight) in y_sources {
+						for &(x, x_weight) in x_sources {
+							if y_weight * x_weight != 0.0 {
+								f(output, x + width * (y + height * c), y_weight * x_weight);
+							}
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::layer::{check_gradients, vector};
+
+	fn upsample(upsample_type: UpsampleType) -> Upsample {
+		let mut info = UpsampleInfo::new(upsample_type, 2);
+		info.set_input([2, 2, 1]).unwrap();
+		Upsample::new(info)
+	}
+
+	#[test]
+	fn nearest() {
+		let mut layer = upsample(UpsampleType::Nearest);
+		layer.forward(vector(&[1.0, 2.0, 3.0, 4.0]));
+		assert_eq!(
+			layer.last_output().as_slice(),
+			[1.0, 1.0, 2.0, 2.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 4.0, 3.0, 3.0, 4.0, 4.0]
+		);
+	}
+
+	#[test]
+	fn bilinear() {
+		let mut layer = upsample(UpsampleType::Bilinear);
+		layer.forward(vector(&[0.0, 4.0, 8.0, 12.0]));
+		let output = layer.last_output();
+		assert_eq!(output.as_slice()[0..4], [0.0, 1.0, 3.0, 4.0]);
+		assert_eq!(output.as_slice()[4..8], [2.0, 3.0, 5.0, 6.0]);
+	}
+
+	#[test]
+	fn gradient_check() {
+		let input: Vec<Float> = (0..18).map(|i| (i as Float * 0.7).sin()).collect();
+		for upsample_type in [UpsampleType::Nearest, UpsampleType::Bilinear] {
+			let mut info = UpsampleInfo::new(upsample_type, 3);
+			info.set_input([3, 3, 2]).unwrap();
+			check_gradients(&mut Upsample::new(info), std::slice::from_ref(&input), 1);
+		}
+	}
+}
@@ -0,0 +1,521 @@
+// mixed precision training of dense networks, the weights, biases and activations are stored in
+// 16 bits and converted to Float at each matrix multiplication, which accumulates in Float, while
+// the network keeps Float master weights that the updates are applied to
+//
+// the gradients of each sample are multiplied by a loss scale and rounded to 16 bits before being
+// accumulated so small gradients don't underflow to 0 (mostly a problem for f16), when a scaled
+// gradient overflows the step is skipped and the loss scale halved, after enough good steps it is
+// doubled again
+
+use crate::network::half::HalfType;
+use crate::network::layer::{Layer, LayerTrait};
+use crate::network::utility::{
+	count_correct, matrix_vec_multiply_add, outer_product_add, transpose_matrix_multiply_vec,
+};
+use crate::network::{
+	ActivationFunction, CostFunction, Float, Network, NetworkData, SequentialError,
+};
+
+use rand::prelude::SliceRandom;
+use rand::thread_rng;
+
+use std::fmt;
+
+// a dense layer with its weights and biases stored in 16 bits
+struct HalfLayer {
+	activation_function: ActivationFunction,
+	biases: Vec<u16>,
+	// the index of the layer in the network
+	index: usize,
+	// column major with dimensions [outputs, inputs]
+	weights: Vec<u16>,
+	weight_dimensions: [usize; 2],
+}
+
+// a network of dense layers with its parameters and activations stored in 16 bits
+pub struct HalfNetwork {
+	cost_function: CostFunction,
+	half_type: HalfType,
+	layers: Vec<HalfLayer>,
+}
+
+pub struct MixedPrecision {
+	good_steps: usize,
+	// the number of good steps before the loss scale is doubled
+	pub growth_interval: usize,
+	// kept between steps and refreshed from the master weights
+	half_network: Option<HalfNetwork>,
+	pub half_type: HalfType,
+	pub loss_scale: Float,
+	skipped_steps: usize,
+}
+
+// accuracies are the fraction of test data classified correctly and sizes are in bytes
+#[derive(Copy, Clone, Debug)]
+pub struct PrecisionReport {
+	pub accuracy: f64,
+	pub half_accuracy: f64,
+	pub half_size: usize,
+	pub half_type: HalfType,
+	pub loss_scale: Float,
+	pub size: usize,
+	pub skipped_steps: usize,
+}
+
+impl HalfLayer {
+	// returns the z values and the output stored in 16 bits
+	fn forward(&self, input: &[u16], half_type: HalfType) -> (Vec<Float>, Vec<u16>) {
+		let mut z = to_float(half_type, &self.biases);
+		matrix_vec_multiply_add(
+			&to_float(half_type, &self.weights),
+			&to_float(half_type, input),
+			&mut z,
+			&self.weight_dimensions,
+		);
+		let output = to_half(half_type, &self.activation_function.evaluate_all(&z));
+		(z, output)
+	}
+}
+
+impl HalfNetwork {
+	pub fn new(network: &Network, half_type: HalfType) -> Result<Self, SequentialError> {
+		let layers = network
+			.dense_layers()?
+			.into_iter()
+			.map(|layer| HalfLayer {
+				activation_function: layer.activation_function,
+				biases: to_half(half_type, layer.biases),
+				index: layer.index,
+				weights: to_half(half_type, layer.weights),
+				weight_dimensions: layer.weight_dimensions,
+			})
+			.collect();
+		let cost_function = match network.layers.last() {
+			Some(Layer::OutputLayer(layer)) => layer.cost_function(),
+			_ => unreachable!("the last layer of a network is always an output layer"),
+		};
+
+		Ok(HalfNetwork {
+			cost_function,
+			half_type,
+			layers,
+		})
+	}
+
+	// copies the master weights of network into the 16 bit parameters, returns false if network
+	// doesn't have the same dense layers as the one this was made from
+	fn refresh(&mut self, network: &Network, half_type: HalfType) -> Result<bool, SequentialError> {
+		let layers = network.dense_layers()?;
+		let matches = half_type == self.half_type
+			&& layers.len() == self.layers.len()
+			&& layers.iter().zip(&self.layers).all(|(layer, half)| {
+				layer.index == half.index
+					&& layer.weight_dimensions == half.weight_dimensions
+					&& layer.activation_function == half.activation_function
+			});
+		if !matches {
+			return Ok(false);
+		}
+
+		for (layer, half) in layers.iter().zip(&mut self.layers) {
+			set_half(half_type, layer.weights, &mut half.weights);
+			set_half(half_type, layer.biases, &mut half.biases);
+		}
+		Ok(true)
+	}
+
+	pub fn forward(&self, input: &[Float]) -> Vec<Float> {
+		let output = self
+			.layers
+			.iter()
+			.fold(to_half(self.half_type, input), |input, layer| {
+				layer.forward(&input, self.half_type).1
+			});
+		to_float(self.half_type, &output)
+	}
+
+	// the same as Network::evaluate
+	pub fn evaluate(&self, test_data: &NetworkData) -> usize {
+		count_correct(test_data, |input| self.forward(input))
+	}
+
+	// the size of the weights and biases in bytes, half the size of f32 parameters
+	pub fn size(&self) -> usize {
+		self.layers
+			.iter()
+			.map(|layer| (layer.weights.len() + layer.biases.len()) * std::mem::size_of::<u16>())
+			.sum()
+	}
+
+	// adds the gradients of the cost for one sample multiplied by loss_scale to gradients,
+	// which has the weight and bias gradients of each layer, every gradient of the sample is
+	// rounded to 16 bits first so gradients too small for the format are lost
+	fn backpropagate(
+		&self,
+		input: &[Float],
+		expected: &[Float],
+		loss_scale: Float,
+		gradients: &mut [(Vec<Float>, Vec<Float>)],
+	) {
+		let half_type = self.half_type;
+		let round = |values: &[Float]| to_float(half_type, &to_half(half_type, values));
+
+		// inputs[i] is the input to layer i and the last is the output of the network
+		let mut inputs = vec![to_half(half_type, input)];
+		let mut z_values = Vec::new();
+		for layer in &self.layers {
+			let (z, output) = layer.forward(inputs.last().unwrap(), half_type);
+			z_values.push(z);
+			inputs.push(output);
+		}
+
+		let last = self.layers.len() - 1;
+		let errors = self.cost_function.errors(
+			&self.layers[last].activation_function,
+			&z_values[last],
+			&to_float(half_type, &inputs[last + 1]),
+			expected,
+		);
+		let mut errors: Vec<Float> = round(
+			&errors
+				.iter()
+				.map(|error| error * loss_scale)
+				.collect::<Vec<Float>>(),
+		);
+
+		for (index, layer) in self.layers.iter().enumerate().rev() {
+			let input = to_float(half_type, &inputs[index]);
+			let (weight_gradients, bias_gradients) = &mut gradients[index];
+
+			let mut weight_gradient = vec![0.0; weight_gradients.len()];
+			outer_product_add(&errors, &input, &mut weight_gradient);
+			for (gradient, sample) in weight_gradients.iter_mut().zip(round(&weight_gradient)) {
+				*gradient += sample;
+			}
+			for (gradient, error) in bias_gradients.iter_mut().zip(&errors) {
+				*gradient += error;
+			}
+
+			if index > 0 {
+				let mut input_gradient = Vec::new();
+				transpose_matrix_multiply_vec(
+					&to_float(half_type, &layer.weights),
+					&errors,
+					layer.weight_dimensions,
+					&mut input_gradient,
+				);
+				errors = round(&self.layers[index - 1].activation_function.backward_all(
+					&z_values[index - 1],
+					&input,
+					&input_gradient,
+				));
+			}
+		}
+	}
+}
+
+impl MixedPrecision {
+	pub fn new(half_type: HalfType) -> Self {
+		MixedPrecision {
+			good_steps: 0,
+			growth_interval: 1000,
+			half_network: None,
+			half_type,
+			// bf16 has the range of f32 so doesn't need scaling
+			loss_scale: match half_type {
+				HalfType::F16 => 65536.0,
+				HalfType::BF16 => 1.0,
+			},
+			skipped_steps: 0,
+		}
+	}
+
+	// the number of steps skipped because a scaled gradient overflowed
+	pub fn skipped_steps(&self) -> usize {
+		self.skipped_steps
+	}
+
+	// the same as Network::sgd without test data
+	pub fn sgd(
+		&mut self,
+		network: &mut Network,
+		mut training_data: NetworkData,
+		epochs: usize,
+		mini_batch_size: usize,
+		learning_rate: Float,
+	) -> Result<(), SequentialError> {
+		for i in 0..epochs {
+			training_data.shuffle(&mut thread_rng());
+
+			for mini_batch in training_data.chunks(mini_batch_size) {
+				self.step(network, mini_batch, learning_rate)?;
+			}
+
+			println!(
+				"Epoch {} complete. Loss scale: {}, skipped steps: {}",
+				i + 1,
+				self.loss_scale,
+				self.skipped_steps
+			);
+		}
+		Ok(())
+	}
+
+	// trains network on one mini batch with the network's parameters stored in 16 bits,
+	// network must be made of dense layers and dropout layers are skipped
+	pub fn step(
+		&mut self,
+		network: &mut Network,
+		mini_batch: &[(Vec<Float>, Vec<Float>)],
+		learning_rate: Float,
+	) -> Result<(), SequentialError> {
+		let half_network = match self.half_network.take() {
+			Some(mut half_network) => match half_network.refresh(network, self.half_type)? {
+				true => half_network,
+				false => HalfNetwork::new(network, self.half_type)?,
+			},
+			None => HalfNetwork::new(network, self.half_type)?,
+		};
+		let mut gradients: Vec<(Vec<Float>, Vec<Float>)> = half_network
+			.layers
+			.iter()
+			.map(|layer| {
+				(
+					vec![0.0; layer.weights.len()],
+					vec![0.0; layer.biases.len()],
+				)
+			})
+			.collect();
+		for (input, expected) in mini_batch {
+			half_network.backpropagate(input, expected, self.loss_scale, &mut gradients);
+		}
+		let indices: Vec<usize> = half_network
+			.layers
+			.iter()
+			.map(|layer| layer.index)
+			.collect();
+		self.half_network = Some(half_network);
+
+		let overflow = gradients
+			.iter()
+			.flat_map(|(weights, biases)| weights.iter().chain(biases))
+			.any(|gradient| !gradient.is_finite());
+		if overflow {
+			self.loss_scale = (self.loss_scale / 2.0).max(1.0);
+			self.good_steps = 0;
+			self.skipped_steps += 1;
+			return Ok(());
+		}
+
+		let multiplier = -learning_rate / (mini_batch.len() as Float * self.loss_scale);
+		for (index, gradients) in indices.into_iter().zip(gradients) {
+			let parameters = network.layers[index].parameters_mut();
+			for (parameters, gradients) in parameters.into_iter().zip([gradients.0, gradients.1]) {
+				for (parameter, gradient) in parameters.iter_mut().zip(gradients) {
+					*parameter += multiplier * gradient;
+				}
+			}
+		}
+		network.apply_masks();
+
+		self.good_steps += 1;
+		if self.good_steps % self.growth_interval == 0 && self.half_type == HalfType::F16 {
+			self.loss_scale *= 2.0;
+		}
+		Ok(())
+	}
+
+	// compares network_half (trained with this) stored and run in 16 bits against network
+	// trained as usual
+	pub fn report(
+		&self,
+		network: &mut Network,
+		network_half: &Network,
+		test_data: &NetworkData,
+	) -> Result<PrecisionReport, SequentialError> {
+		let half_network = HalfNetwork::new(network_half, self.half_type)?;
+		let training = network.training();
+		network.set_training(false);
+		let accuracy = network.evaluate(test_data) as f64 / test_data.len() as f64;
+		network.set_training(training);
+
+		Ok(PrecisionReport {
+			accuracy,
+			half_accuracy: half_network.evaluate(test_data) as f64 / test_data.len() as f64,
+			half_size: half_network.size(),
+			half_type: self.half_type,
+			loss_scale: self.loss_scale,
+			size: network.parameter_count() * std::mem::size_of::<Float>(),
+			skipped_steps: self.skipped_steps,
+		})
+	}
+}
+
+impl fmt::Display for PrecisionReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Mixed precision ({:?})", self.half_type)?;
+		writeln!(
+			f,
+			"Accuracy: {:.2}% -> {:.2}% ({:+.2}%)",
+			self.accuracy * 100.0,
+			self.half_accuracy * 100.0,
+			(self.half_accuracy - self.accuracy) * 100.0
+		)?;
+		writeln!(
+			f,
+			"Parameters: {} bytes -> {} bytes",
+			self.size, self.half_size
+		)?;
+		write!(
+			f,
+			"Final loss scale: {}, skipped steps: {}",
+			self.loss_scale, self.skipped_steps
+		)
+	}
+}
+
+fn to_half(half_type: HalfType, values: &[Float]) -> Vec<u16> {
+	let mut half = vec![0; values.len()];
+	set_half(half_type, values, &mut half);
+	half
+}
+
+// the casts do nothing while Float is f32
+#[allow(clippy::unnecessary_cast)]
+fn set_half(half_type: HalfType, values: &[Float], half: &mut [u16]) {
+	for (half, &value) in half.iter_mut().zip(values) {
+		*half = half_type.from_f32(value as f32);
+	}
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn to_float(half_type: HalfType, values: &[u16]) -> Vec<Float> {
+	values
+		.iter()
+		.map(|&value| half_type.to_f32(value) as Float)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::fixtures::{data, fix_parameters, parameters};
+
+	fn network() -> Network {
+		let mut network = Network::builder()
+			.input([6, 1, 1])
+			.dense(16)
+			.activation(ActivationFunction::Tanh)
+			.output(2)
+			.activation(ActivationFunction::Sigmoid)
+			.build()
+			.unwrap();
+		fix_parameters(&mut network);
+		network
+	}
+
+	#[test]
+	fn half_network() {
+		let mut network = network();
+		let half_network = HalfNetwork::new(&network, HalfType::F16).unwrap();
+		assert_eq!(half_network.size(), network.parameter_count() * 2);
+
+		for (input, _) in data(20) {
+			let output = network.forward(input.clone());
+			for (value, half) in output.iter().zip(half_network.forward(&input)) {
+				assert!((value - half).abs() < 1e-2);
+			}
+		}
+	}
+
+	#[test]
+	fn loss_scaling() {
+		// gradients of about 1e-8 are below the smallest f16 subnormal
+		let tiny: NetworkData = data(1)
+			.into_iter()
+			.map(|(input, expected)| (input.iter().map(|x| x * 1e-7).collect(), expected))
+			.collect();
+
+		let mut unscaled = network();
+		let mut mixed = MixedPrecision::new(HalfType::F16);
+		mixed.loss_scale = 1.0;
+		mixed.step(&mut unscaled, &tiny, 1.0).unwrap();
+
+		let mut scaled = network();
+		let mut mixed = MixedPrecision::new(HalfType::F16);
+		mixed.loss_scale = 1024.0;
+		mixed.step(&mut scaled, &tiny, 1.0).unwrap();
+		assert_eq!(mixed.skipped_steps(), 0);
+
+		// only the weights of the first layer see the tiny input, the biases of every layer
+		// and the output layer are updated either way
+		let original = parameters(&mut network());
+		let weights = 6 * 16;
+		assert_eq!(parameters(&mut unscaled)[..weights], original[..weights]);
+		assert!(parameters(&mut scaled)[..weights] != original[..weights]);
+		assert!(parameters(&mut unscaled)[weights..] != original[weights..]);
+
+		// a loss scale this large overflows so the step is skipped
+		let mut overflow = network();
+		let mut mixed = MixedPrecision::new(HalfType::F16);
+		mixed.loss_scale = 1e30;
+		mixed.step(&mut overflow, &data(1), 1.0).unwrap();
+		assert_eq!(parameters(&mut overflow), original);
+		assert_eq!(mixed.skipped_steps(), 1);
+		assert_eq!(mixed.loss_scale, 5e29);
+	}
+
+	#[test]
+	fn refreshes_half_network() {
+		let mut reused = network();
+		let mut fresh = network();
+		let mut mixed = MixedPrecision::new(HalfType::BF16);
+		for mini_batch in data(30).chunks(10) {
+			mixed.step(&mut reused, mini_batch, 0.5).unwrap();
+			// a new MixedPrecision makes its HalfNetwork from the network
+			MixedPrecision::new(HalfType::BF16)
+				.step(&mut fresh, mini_batch, 0.5)
+				.unwrap();
+		}
+		assert_eq!(parameters(&mut reused), parameters(&mut fresh));
+	}
+
+	#[test]
+	fn accuracy_parity() {
+		let mut full = network();
+		full.sgd(data(400), None, 5, 10, 0.5, None);
+
+		for half_type in [HalfType::F16, HalfType::BF16] {
+			let mut half = network();
+			let mut mixed = MixedPrecision::new(half_type);
+			mixed.sgd(&mut half, data(400), 5, 10, 0.5).unwrap();
+
+			full.set_training(true);
+			let report = mixed.report(&mut full, &half, &data(200)).unwrap();
+			assert!(full.training());
+			assert!(report.accuracy > 0.8);
+			assert!((report.half_accuracy - report.accuracy).abs() < 0.1);
+			assert_eq!(report.half_size, full.parameter_count() * 2);
+		}
+	}
+
+	#[test]
+	fn unsupported_layers() {
+		use crate::network::layer::recurrent::CellType;
+
+		let mut network = Network::builder()
+			.input([3, 2, 1])
+			.recurrent(CellType::RNN, 4)
+			.output(2)
+			.build()
+			.unwrap();
+		let mut mixed = MixedPrecision::new(HalfType::BF16);
+		assert_eq!(
+			mixed.step(&mut network, &[(vec![0.0; 6], vec![1.0, 0.0])], 1.0),
+			Err(SequentialError::UnsupportedLayer {
+				index: 1,
+				name: String::from("RNN")
+			})
+		);
+	}
+}
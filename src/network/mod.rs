@@ -1,23 +1,53 @@
+mod autograd;
+mod backend;
+mod builder;
 mod change;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod gradient_check;
+mod half;
 pub mod layer;
+mod mixed_precision;
 mod neuron;
+mod prune;
+mod quantise;
+mod sequential;
+mod simd;
+mod sparse;
+mod tensor;
 mod utility;
 
 use crate::front_end::graph_results;
 
+use std::fmt;
+
 pub use neuron::{
 	activation_function::ActivationFunction,
-	cost_function::{CostFunction, Regularisation},
+	cost_function::{CostFunction, Loss, Regularisation},
 	initialisation::InitType,
 };
 
-pub use utility::Float;
+pub use autograd::{Gradients, Tape, Var};
+pub use builder::NetworkBuilder;
+pub use error::NetworkError;
+pub use gradient_check::{gradient_check, gradient_check_batch, ParameterGradient};
+pub use half::HalfType;
+pub use mixed_precision::{HalfNetwork, MixedPrecision, PrecisionReport};
+pub use prune::{PruningReport, PruningResult, PruningSchedule, SparseNetwork};
+pub use quantise::{Granularity, QuantisationReport, QuantiseError, QuantisedNetwork};
+pub use sequential::SequentialError;
+pub use simd::{set_simd, simd_enabled};
+pub use sparse::{SparseError, SparseNetworkData, SparseVector};
+pub use tensor::{Layout, ShapeError, Tensor, TensorView};
+pub use utility::{
+	flatten_sequence, general_matrix_multiply, matrix_multiply_sum, matrix_vec_multiply_add,
+	outer_product_add, plus_equals_matrix_multiplied, scale_elements,
+	transpose_matrix_multiply_vec, Float, Scalar,
+};
 use {
-	layer::{
-		feedforward::FeedForward, inputlayer::InputLayer, outputlayer::OutputLayer, Layer,
-		LayerInfo, LayerInfoTrait, LayerTrait,
-	},
-	utility::max_index,
+	layer::{shaped, Layer, LayerInfo, LayerTrait},
+	utility::count_correct,
 };
 
 use rand::prelude::SliceRandom;
@@ -25,8 +55,38 @@ use rand::thread_rng;
 
 pub type NetworkData = Vec<(Vec<Float>, Vec<Float>)>;
 
+// inputs are sequences with a vector for each timestep, all sequences must have
+// the same length as the network input
+pub type SequenceData = Vec<(Vec<Vec<Float>>, Vec<Float>)>;
+
+// converts sequences to the flattened inputs used by the network
+pub fn sequence_data(data: SequenceData) -> Result<NetworkData, NetworkError> {
+	data.into_iter()
+		.map(|(sequence, expected_output)| Ok((flatten_sequence(&sequence)?, expected_output)))
+		.collect()
+}
+
+// inputs are token ids for networks that start with an embedding layer
+pub type TokenData = Vec<(Vec<usize>, Vec<Float>)>;
+
+// token ids are passed through the input layer as floats,
+// every id below 2^24 is represented exactly
+pub fn token_input(tokens: &[usize]) -> Vec<Float> {
+	tokens.iter().map(|&token| token as Float).collect()
+}
+
+pub fn token_data(data: TokenData) -> NetworkData {
+	data.into_iter()
+		.map(|(tokens, expected_output)| (token_input(&tokens), expected_output))
+		.collect()
+}
+
 pub struct Network {
+	// connections[i] are the indices of the layers that layer i takes input from
+	// layers are ordered so that every layer comes after its inputs
+	connections: Vec<Vec<usize>>,
 	layers: Vec<Layer>,
+	training: bool,
 }
 
 impl Network {
@@ -40,107 +100,219 @@ impl Network {
 		}
 	}
 
-	fn backpropagation(&mut self, input: &Vec<Float>, expected_output: &Vec<Float>) -> Vec<Float> {
+	// runs a mini batch through the network together so layers such as batch norm
+	// can use statistics of the whole batch, returns the output for every sample
+	fn backpropagation(&mut self, mini_batch: &[(Vec<Float>, Vec<Float>)]) -> Vec<Vec<Float>> {
+		let shape = self.layers[0].info().output();
+		let inputs = self.layers[0].forward_batch(
+			mini_batch
+				.iter()
+				.map(|data| vec![shaped(data.0.clone(), &shape)])
+				.collect(),
+		);
+		let expected: Vec<&[Float]> = mini_batch.iter().map(|data| &data.1[..]).collect();
+		self.backpropagate_from(vec![inputs], &expected).0
+	}
+
+	// outputs[i][sample] is the output of layer i for that sample for the layers that have
+	// already been run, the rest of the network is run forward and backpropagated, returns the
+	// output of the network and the gradient with respect to the output of the last layer run
+	fn backpropagate_from(
+		&mut self,
+		mut outputs: Vec<Vec<Tensor>>,
+		expected: &[&[Float]],
+	) -> (Vec<Vec<Float>>, Vec<Tensor>) {
 		let len = self.layers.len();
-		let mut z_values = Vec::new();
-		let mut outputs = Vec::new();
-		let mut output = input.clone();
-		for layer in &mut self.layers {
-			layer.forward(output);
-			output = layer.last_output();
-			outputs.push(output.clone());
-			z_values.push(layer.last_z_values());
-		}
+		let batch_size = expected.len();
+		let first = outputs.len();
 
-		let o_len = expected_output.len();
+		for index in first..len {
+			let layer_inputs = (0..batch_size)
+				.map(|sample| {
+					self.connections[index]
+						.iter()
+						.map(|&input| outputs[input][sample].clone())
+						.collect()
+				})
+				.collect();
+			outputs.push(self.layers[index].forward_batch(layer_inputs));
+		}
 
-		let (mut errors, mut weights, mut dim) = self.layers[len - 1].backward(
-			&outputs[outputs.len() - 2],
-			&output,
-			(expected_output.clone(), [o_len, 1, 1]),
-		);
+		// gradients[i][sample] is the gradient of the cost with respect to the output of layer i
+		// summed over every layer that uses it
+		let mut gradients: Vec<Option<Vec<Tensor>>> = vec![None; len];
 
-		for i in 0..(len - 1) {
-			let layer_index = len - (i + 2);
+		let last_input = self.connections[len - 1][0];
+		let output_gradients = match &mut self.layers[len - 1] {
+			Layer::OutputLayer(layer) => expected
+				.iter()
+				.zip(outputs[last_input].iter())
+				.map(|(expected, input)| {
+					// the output layer only keeps the values from its last forward pass
+					if batch_size > 1 {
+						layer.forward(input.clone());
+					}
+					layer.cost_backward(input, expected)
+				})
+				.collect(),
+			_ => unreachable!("the last layer of a network is always an output layer"),
+		};
+		add_gradient(&mut gradients[last_input], output_gradients);
 
-			let outputs_index = if i == len - 2 { 0 } else { len - (i + 3) };
+		// the input layer has nothing to learn so there is no need to go back to it
+		for index in (first..(len - 1)).rev() {
+			let gradient = gradients[index]
+				.take()
+				.expect("the output of every layer is used by a later layer");
 
-			let (terrors, tweights, tweights_dim) =
-				self.layers[layer_index].backward(&outputs[outputs_index], &errors, (weights, dim));
+			let layer_inputs: Vec<Vec<&Tensor>> = (0..batch_size)
+				.map(|sample| {
+					self.connections[index]
+						.iter()
+						.map(|&input| &outputs[input][sample])
+						.collect()
+				})
+				.collect();
+			let mut input_gradients = self.layers[index].backward_batch(&layer_inputs, &gradient);
 
-			errors = terrors;
-			weights = tweights;
-			dim = tweights_dim;
+			for (position, &input) in self.connections[index].iter().enumerate().rev() {
+				let input_gradient = input_gradients
+					.iter_mut()
+					.map(|gradients| gradients.remove(position))
+					.collect();
+				add_gradient(&mut gradients[input], input_gradient);
+			}
 		}
 
-		output
+		let gradient = gradients[first - 1].take().unwrap_or_default();
+		let outputs = outputs.pop().unwrap().into_iter().map(Vec::from).collect();
+		(outputs, gradient)
 	}
 
-	pub fn forward(&mut self, input: Vec<Float>) -> Vec<Float> {
-		let mut next_input = input;
-		for layer in &mut self.layers {
-			layer.forward(next_input);
-			next_input = layer.last_output();
+	// adds the gradients of a mini batch to the change of every layer
+	fn accumulate_changes(&mut self, mini_batch: &[(Vec<Float>, Vec<Float>)]) {
+		// without layers that need batch statistics samples are run one at a time
+		// which avoids keeping the output of every layer for the whole mini batch
+		if self
+			.layers
+			.iter()
+			.any(|layer| layer.uses_batch_statistics())
+		{
+			self.backpropagation(mini_batch);
+		} else {
+			for data in mini_batch {
+				self.backpropagation(std::slice::from_ref(data));
+			}
 		}
-		next_input
 	}
 
-	fn from_layers(layers: Vec<Layer>) -> Self {
-		Network { layers }
+	pub fn forward(&mut self, input: Vec<Float>) -> Vec<Float> {
+		let shape = self.layers[0].info().output();
+		self.forward_tensor(shaped(input, &shape))
+			.unwrap_or_else(|error| panic!("{}", error))
+			.into_vec()
 	}
 
-	pub fn new(layer_infos: Vec<LayerInfo>) -> Self {
-		let mut layers = Vec::new();
-		let info_len = layer_infos.len();
-
-		if info_len == 0 {
-			panic!("Can't create an empty Network!");
+	// a layer that returns an output of the wrong shape is an error rather than a panic here
+	fn forward_tensor(&mut self, input: Tensor) -> Result<Tensor, NetworkError> {
+		self.layers[0].forward(input);
+		for index in 1..self.layers.len() {
+			let layer_inputs = self.layer_inputs(index)?;
+			self.layers[index].forward_many(layer_inputs);
 		}
+		self.layers[self.layers.len() - 1].try_last_output()
+	}
 
-		// handle input layer seperately
-		match &layer_infos[0] {
-			LayerInfo::InputLayer(info) => {
-				layers.push(Layer::InputLayer(InputLayer::new(*info)));
-			}
-			_ => panic!("Attempting to create network where first layer isn't input!"),
+	// runs a tensor through the network after checking it has the shape of the input layer,
+	// trailing axes of size 1 can be left out so [784] matches an input of [784, 1, 1]
+	pub fn predict(&mut self, input: &Tensor) -> Result<Tensor, NetworkError> {
+		let expected = self.layers[0].info().output();
+		let mut shape = input.shape().to_vec();
+		shape.resize(shape.len().max(3), 1);
+		if shape[..3] != expected || shape[3..].iter().any(|&size| size != 1) {
+			return Err(NetworkError::InputShape {
+				expected,
+				found: input.shape().to_vec(),
+			});
 		}
 
-		let mut previous_layer = &layer_infos[0];
+		let input = input.to_layout(Layout::ColumnMajor).reshape(&expected);
+		self.forward_tensor(input.unwrap())
+	}
 
-		// next_layer might not be needed?
-		for (layer, _next_layer) in layer_infos[1..(info_len - 1)]
-			.iter()
-			.zip(&layer_infos[2..info_len])
-		{
-			// note this is variable to change as different network types are supported
-			match layer {
-				LayerInfo::InputLayer(_) => {
-					panic!("Attempting to create input layer in middle of network!")
-				}
-				LayerInfo::FeedForward(info) => layers.push(Layer::FeedForward(FeedForward::new(
-					*info,
-					previous_layer.flattened_output(),
-				))),
+	// checks the tokens fit the input layer and the vocabulary of every embedding layer
+	// (which all take input from it) before running them through the network
+	pub fn forward_tokens(&mut self, tokens: &[usize]) -> Result<Vec<Float>, NetworkError> {
+		let expected = self.layers[0].info().output();
+		if tokens.len() != expected.iter().product::<usize>() {
+			return Err(NetworkError::InputShape {
+				expected,
+				found: vec![tokens.len()],
+			});
+		}
 
-				LayerInfo::OutputLayer(_) => {
-					panic!("Attempting to create output layer in middle of network!")
+		for (index, layer) in self.layers.iter().enumerate() {
+			if let Layer::Embedding(layer) = layer {
+				let vocabulary = layer.vocabulary();
+				if let Some(&token) = tokens.iter().find(|&&token| token >= vocabulary) {
+					return Err(NetworkError::InvalidToken {
+						index,
+						token,
+						vocabulary,
+					});
 				}
 			}
-
-			previous_layer = layer;
 		}
 
-		// handle output layer seperately
-		match &layer_infos[layer_infos.len() - 1] {
-			LayerInfo::OutputLayer(info) => layers.push(Layer::OutputLayer(OutputLayer::new(
-				*info,
-				previous_layer.flattened_output(),
-			))),
+		self.forward_tensor(shaped(token_input(tokens), &expected))
+			.map(Tensor::into_vec)
+	}
+
+	// the outputs of the layers connected to the layer at index
+	fn layer_inputs(&self, index: usize) -> Result<Vec<Tensor>, NetworkError> {
+		self.connections[index]
+			.iter()
+			.map(|&input| self.layers[input].try_last_output())
+			.collect()
+	}
 
-			_ => panic!("Attempting to create network where first layer isn't input!"),
+	fn from_layers(layers: Vec<Layer>, connections: Vec<Vec<usize>>) -> Self {
+		Network {
+			connections,
+			layers,
+			training: false,
 		}
+	}
+
+	// the indices of the layers that the layer at index takes input from
+	pub fn connections(&self, index: usize) -> &[usize] {
+		&self.connections[index]
+	}
 
-		Network::from_layers(layers)
+	pub fn layers(&self) -> std::slice::Iter<'_, Layer> {
+		self.layers.iter()
+	}
+
+	pub fn parameter_count(&self) -> usize {
+		self.layers
+			.iter()
+			.map(|layer| layer.parameter_count())
+			.sum()
+	}
+
+	pub fn summary(&self) {
+		println!("{}", self);
+	}
+
+	pub fn builder() -> NetworkBuilder {
+		NetworkBuilder::new()
+	}
+
+	pub fn new(layer_infos: Vec<LayerInfo>) -> Result<Self, NetworkError> {
+		layer_infos
+			.into_iter()
+			.fold(NetworkBuilder::new(), NetworkBuilder::layer)
+			.build()
 	}
 
 	pub fn sgd(
@@ -153,45 +325,34 @@ impl Network {
 		graph_output: Option<&str>,
 	) {
 		let mut results: Vec<(f32, f64)> = Vec::new();
-		if test_data.is_some() {
-			let mut correct = 0;
-			let num = test_data.as_ref().unwrap().len();
-
-			for data in test_data.as_ref().unwrap() {
-				let output = self.forward(data.0.clone());
-				if max_index(&output) == max_index(&data.1) {
-					correct += 1;
-				}
-			}
+		self.set_training(false);
+
+		if let Some(test_data) = &test_data {
+			let correct = self.evaluate(test_data);
+			let num = test_data.len();
 
 			let percent_correct = (correct * 100) as Float / num as Float;
 			println!("Epoch 0: {} / {} ({}%)", correct, num, percent_correct);
 		}
 
 		let mut max_correct = 0;
-		let mut min_correct = test_data.as_ref().unwrap().len();
+		let mut min_correct = test_data.as_ref().map_or(0, |test_data| test_data.len());
 		for i in 0..epochs {
 			training_data.shuffle(&mut thread_rng());
 
 			let mini_batches = training_data.chunks(mini_batch_size);
 
+			self.set_training(true);
 			for mini_batch in mini_batches {
-				for data in mini_batch {
-					self.backpropagation(&data.0, &data.1);
-				}
+				self.accumulate_changes(mini_batch);
 				self.apply_layer_changes(learning_rate, mini_batch_size);
 			}
+			self.set_training(false);
 
-			if test_data.is_some() {
-				let mut correct = 0;
-				let num = test_data.as_ref().unwrap().len();
+			if let Some(test_data) = &test_data {
+				let correct = self.evaluate(test_data);
+				let num = test_data.len();
 
-				for data in test_data.as_ref().unwrap() {
-					let output = self.forward(data.0.clone());
-					if max_index(&output) == max_index(&data.1) {
-						correct += 1;
-					}
-				}
 				if correct > max_correct {
 					max_correct = correct;
 				}
@@ -200,7 +361,8 @@ impl Network {
 					min_correct = correct;
 				}
 
-				let percent_correct = (correct * 100) as Float / num as Float;
+				// graph_results takes f64 whatever Float is
+				let percent_correct = (correct * 100) as f64 / num as f64;
 				println!(
 					"Epoch {}: {} / {} ({}%)",
 					i + 1,
@@ -208,31 +370,712 @@ impl Network {
 					num,
 					percent_correct
 				);
-				results.push(((i + 1) as Float, percent_correct as f64));
+				results.push(((i + 1) as f32, percent_correct));
 			} else {
 				println!("Epoch {} complete.", i + 1);
 			}
 		}
-		if test_data.is_some() {
-			let num = test_data.as_ref().unwrap().len();
+		if let Some(test_data) = &test_data {
+			let num = test_data.len();
 			println!(
 				"Highest accuracy: {} / {} ({}%)",
 				max_correct,
 				num,
 				(max_correct * 100) as Float / num as Float
 			);
-			match graph_output {
-				Some(name) => {
-					graph_results(
-						name,
-						epochs,
-						results,
-						(min_correct * 100) as f64 / num as f64,
-						(max_correct * 100) as f64 / num as f64,
-					);
+			if let Some(name) = graph_output {
+				graph_results(
+					name,
+					epochs,
+					results,
+					(min_correct * 100) as f64 / num as f64,
+					(max_correct * 100) as f64 / num as f64,
+				);
+			}
+		}
+	}
+
+	// returns the number of inputs where the largest output matches the expected output
+	pub fn evaluate(&mut self, test_data: &NetworkData) -> usize {
+		count_correct(test_data, |input| self.forward(input.clone()))
+	}
+
+	// in training mode layers such as dropout are active,
+	// networks start in evaluation mode and sgd leaves them in evaluation mode
+	pub fn set_training(&mut self, training: bool) {
+		self.training = training;
+		for layer in &mut self.layers {
+			layer.set_training(training);
+		}
+	}
+
+	pub fn training(&self) -> bool {
+		self.training
+	}
+}
+
+// gradients are given for every sample in the mini batch
+fn add_gradient(total: &mut Option<Vec<Tensor>>, gradient: Vec<Tensor>) {
+	match total {
+		Some(total) => {
+			for (total, gradient) in total.iter_mut().zip(gradient) {
+				for (total, value) in total.as_mut_slice().iter_mut().zip(gradient.as_slice()) {
+					*total += value;
 				}
-				_ => {}
 			}
 		}
+		None => *total = Some(gradient),
+	}
+}
+
+// keras style summary table
+impl fmt::Display for Network {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let header = [
+			String::from("Layer"),
+			String::from("Inputs"),
+			String::from("Output Shape"),
+			String::from("Params"),
+			String::from("Activation"),
+			String::from("Init"),
+		];
+
+		let rows: Vec<[String; 6]> = self
+			.layers
+			.iter()
+			.enumerate()
+			.map(|(i, layer)| {
+				let info = layer.info();
+				[
+					format!("{} ({})", i, info.name()),
+					match self.connections[i].is_empty() {
+						true => String::from("-"),
+						false => self.connections[i]
+							.iter()
+							.map(|input| input.to_string())
+							.collect::<Vec<String>>()
+							.join(", "),
+					},
+					format!("{:?}", info.output()),
+					layer.parameter_count().to_string(),
+					info.activation_function()
+						.map_or(String::from("-"), |function| format!("{:?}", function)),
+					info.init_type()
+						.map_or(String::from("-"), |init_type| format!("{:?}", init_type)),
+				]
+			})
+			.collect();
+
+		let mut widths = [0; 6];
+		for row in rows.iter().chain(std::iter::once(&header)) {
+			for (width, column) in widths.iter_mut().zip(row) {
+				*width = (*width).max(column.len());
+			}
+		}
+		let total_width = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+
+		let write_row = |f: &mut fmt::Formatter, row: &[String; 6]| -> fmt::Result {
+			let columns: Vec<String> = row
+				.iter()
+				.zip(widths.iter())
+				.map(|(column, &width)| format!("{:<width$}", column, width = width))
+				.collect();
+			writeln!(f, "{}", columns.join("  ").trim_end())
+		};
+
+		write_row(f, &header)?;
+		writeln!(f, "{}", "=".repeat(total_width))?;
+		for row in &rows {
+			write_row(f, row)?;
+		}
+		writeln!(f, "{}", "=".repeat(total_width))?;
+		write!(f, "Total params: {}", self.parameter_count())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use layer::{
+		feedforward::FeedForwardInfo, inputlayer::InputLayerInfo, merge::MergeType,
+		outputlayer::OutputLayerInfo, CustomLayerInfo, LayerInfoTrait,
+	};
+
+	fn input(length: usize) -> LayerInfo {
+		LayerInfo::InputLayer(InputLayerInfo::new([length, 1, 1]))
+	}
+
+	fn feedforward(length: usize) -> LayerInfo {
+		LayerInfo::FeedForward(FeedForwardInfo::new(
+			ActivationFunction::Sigmoid,
+			InitType::NormalisedXavier,
+			length,
+		))
+	}
+
+	fn output(length: usize) -> LayerInfo {
+		LayerInfo::OutputLayer(OutputLayerInfo::new(
+			ActivationFunction::Sigmoid,
+			CostFunction::CrossEntropy,
+			InitType::NormalisedXavier,
+			length,
+		))
+	}
+
+	fn error(layer_infos: Vec<LayerInfo>) -> NetworkError {
+		match Network::new(layer_infos) {
+			Ok(_) => panic!("network was created"),
+			Err(error) => error,
+		}
+	}
+
+	#[test]
+	fn valid_network() {
+		assert!(Network::new(vec![input(4), feedforward(3), output(2)]).is_ok());
+		assert!(Network::new(vec![input(4), output(2)]).is_ok());
+	}
+
+	#[test]
+	fn predict() {
+		let mut network = Network::new(vec![input(4), feedforward(3), output(2)]).unwrap();
+		let input = Tensor::new(vec![0.1, 0.2, 0.3, 0.4], &[4]).unwrap();
+
+		let output = network.predict(&input).unwrap();
+		assert_eq!(output.shape(), &[2, 1, 1]);
+		assert_eq!(output.into_vec(), network.forward(vec![0.1, 0.2, 0.3, 0.4]));
+
+		let input = input.reshape(&[2, 2]).unwrap();
+		assert_eq!(
+			network.predict(&input),
+			Err(NetworkError::InputShape {
+				expected: [4, 1, 1],
+				found: vec![2, 2]
+			})
+		);
+	}
+
+	#[test]
+	fn invalid_layer_order() {
+		assert_eq!(error(vec![]), NetworkError::EmptyNetwork);
+		assert_eq!(
+			error(vec![feedforward(3), output(2)]),
+			NetworkError::FirstLayerNotInput
+		);
+		assert_eq!(
+			error(vec![input(4), input(3), output(2)]),
+			NetworkError::InputLayerNotFirst { index: 1 }
+		);
+		assert_eq!(
+			error(vec![input(4), output(3), output(2)]),
+			NetworkError::OutputLayerNotLast { index: 1 }
+		);
+		assert_eq!(
+			error(vec![input(4), feedforward(3)]),
+			NetworkError::LastLayerNotOutput
+		);
+		assert_eq!(error(vec![input(4)]), NetworkError::LastLayerNotOutput);
+	}
+
+	#[test]
+	fn summary() {
+		let network = Network::new(vec![input(4), feedforward(3), output(2)]).unwrap();
+
+		let layer_parameters: Vec<usize> = network
+			.layers()
+			.map(|layer| layer.parameter_count())
+			.collect();
+		assert_eq!(layer_parameters, vec![0, 4 * 3 + 3, 3 * 2 + 2]);
+
+		let summary = network.to_string();
+		assert!(summary.contains("1 (FeedForward)  0       [3, 1, 1]"));
+		assert!(summary.ends_with("Total params: 23"));
+	}
+
+	struct ScaleInfo {
+		input: [usize; 3],
+	}
+
+	struct Scale {
+		change: Float,
+		info: ScaleInfo,
+		output: Tensor,
+		scale: [Float; 1],
+	}
+
+	impl LayerInfoTrait for ScaleInfo {
+		fn output(&self) -> [usize; 3] {
+			self.input
+		}
+
+		fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+			self.input = input;
+			Ok(())
+		}
+
+		fn name(&self) -> &str {
+			"Scale"
+		}
+	}
+
+	impl CustomLayerInfo for ScaleInfo {
+		fn create_layer(&self, input: [usize; 3]) -> Box<dyn LayerTrait> {
+			Box::new(Scale {
+				change: 0.0,
+				info: ScaleInfo { input },
+				output: Tensor::zeros(&input),
+				scale: [1.0],
+			})
+		}
+	}
+
+	impl LayerTrait for Scale {
+		fn backward(&mut self, input: &Tensor, output_gradient: &Tensor) -> Tensor {
+			self.update_change(output_gradient.as_slice(), input.as_slice());
+			output_gradient.map(|g| g * self.scale[0])
+		}
+
+		fn forward(&mut self, input: Tensor) {
+			self.output = input.map(|a| a * self.scale[0]);
+		}
+
+		fn last_output(&self) -> Tensor {
+			self.output.clone()
+		}
+
+		fn update(&mut self, learning_rate: Float, mini_batch_size: usize, _: &Regularisation) {
+			self.scale[0] -= learning_rate * self.change / mini_batch_size as Float;
+			self.change = 0.0;
+		}
+
+		fn update_change(&mut self, errors: &[Float], a: &[Float]) {
+			self.change += errors.iter().zip(a).map(|(e, a)| e * a).sum::<Float>();
+		}
+
+		fn info(&self) -> &dyn LayerInfoTrait {
+			&self.info
+		}
+
+		fn weights(&self) -> Option<&[Float]> {
+			Some(&self.scale)
+		}
+	}
+
+	#[test]
+	fn custom_layer() {
+		let mut network = Network::builder()
+			.input([2, 1, 1])
+			.custom(ScaleInfo { input: [0; 3] })
+			.output(1)
+			.build()
+			.unwrap();
+
+		assert!(network.to_string().contains("1 (Scale)"));
+		assert_eq!(network.parameter_count(), 1 + 2 + 1);
+
+		network.backpropagation(&[(vec![0.5, 1.0], vec![1.0])]);
+		network.apply_layer_changes(1.0, 1);
+
+		let scale = network.layers().nth(1).unwrap().weights().unwrap()[0];
+		assert!(scale != 1.0);
+	}
+
+	// tensors of the wrong shape are caught by Layer rather than somewhere inside the layer
+	#[test]
+	#[should_panic(
+		expected = "the output gradient of a Scale layer has the shape [3, 1, 1] but should have the shape [2, 1, 1]"
+	)]
+	fn layer_shapes() {
+		let mut network = Network::builder()
+			.input([2, 1, 1])
+			.custom(ScaleInfo { input: [0; 3] })
+			.output(1)
+			.build()
+			.unwrap();
+
+		let input = Tensor::zeros(&[2, 1, 1]);
+		network.layers[1].forward(input.clone());
+		network.layers[1].backward(&input, &Tensor::zeros(&[3, 1, 1]));
+	}
+
+	// a custom layer that always outputs the same tensor
+	struct FixedInfo {
+		input: [usize; 3],
+		output: Tensor,
+	}
+
+	struct Fixed(FixedInfo);
+
+	impl LayerInfoTrait for FixedInfo {
+		fn output(&self) -> [usize; 3] {
+			self.input
+		}
+
+		fn set_input(&mut self, input: [usize; 3]) -> Result<(), String> {
+			self.input = input;
+			Ok(())
+		}
+
+		fn name(&self) -> &str {
+			"Fixed"
+		}
+	}
+
+	impl CustomLayerInfo for FixedInfo {
+		fn create_layer(&self, input: [usize; 3]) -> Box<dyn LayerTrait> {
+			Box::new(Fixed(FixedInfo {
+				input,
+				output: self.output.clone(),
+			}))
+		}
+	}
+
+	impl LayerTrait for Fixed {
+		fn backward(&mut self, input: &Tensor, _: &Tensor) -> Tensor {
+			Tensor::zeros(input.shape())
+		}
+
+		fn forward(&mut self, _: Tensor) {}
+
+		fn last_output(&self) -> Tensor {
+			self.0.output.clone()
+		}
+
+		fn info(&self) -> &dyn LayerInfoTrait {
+			&self.0
+		}
+	}
+
+	#[test]
+	fn layer_output_errors() {
+		let fixed = |output| {
+			Network::builder()
+				.input([2, 3, 1])
+				.custom(FixedInfo {
+					input: [0; 3],
+					output,
+				})
+				.output(1)
+				.build()
+				.unwrap()
+		};
+		let input = Tensor::zeros(&[2, 3, 1]);
+
+		// other layouts are converted to the column major layout layers expect
+		let row_major = Tensor::with_layout(
+			vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0],
+			&[2, 3, 1],
+			Layout::RowMajor,
+		)
+		.unwrap();
+		let mut network = fixed(row_major.clone());
+		assert!(network.predict(&input).is_ok());
+		assert_eq!(
+			network.layers[1].last_output(),
+			row_major.to_layout(Layout::ColumnMajor)
+		);
+
+		let mut network = fixed(Tensor::zeros(&[3, 2, 1]));
+		assert_eq!(
+			network.predict(&input),
+			Err(NetworkError::TensorShape {
+				layer: "Fixed".to_string(),
+				tensor: "output",
+				shape: vec![3, 2, 1],
+				expected: vec![2, 3, 1],
+			})
+		);
+	}
+
+	#[test]
+	fn residual_network() {
+		let builder = Network::builder().input([4, 1, 1]).dense(3);
+		let skip = builder.last_index();
+		let mut network = builder
+			.dense(3)
+			.merge(MergeType::Add, &[skip])
+			.dense(5)
+			.merge(MergeType::Concatenate, &[0])
+			.output(2)
+			.build()
+			.unwrap();
+
+		assert_eq!(network.connections(3), &[2, 1]);
+		assert_eq!(network.layers().nth(5).unwrap().info().output(), [9, 1, 1]);
+
+		let input = vec![0.1, 0.2, 0.3, 0.4];
+		let outputs = network.backpropagation(&[(input.clone(), vec![1.0, 0.0])]);
+		assert_eq!(outputs[0], network.forward(input));
+	}
+
+	#[test]
+	fn invalid_connections() {
+		assert_eq!(
+			Network::builder()
+				.input([4, 1, 1])
+				.dense(3)
+				.inputs(&[2])
+				.output(2)
+				.build()
+				.err(),
+			Some(NetworkError::InvalidConnection { index: 1, input: 2 })
+		);
+		assert_eq!(
+			Network::builder()
+				.input([4, 1, 1])
+				.dense(3)
+				.dense(3)
+				.inputs(&[0])
+				.output(2)
+				.build()
+				.err(),
+			Some(NetworkError::UnusedLayer { index: 1 })
+		);
+		assert!(matches!(
+			Network::builder()
+				.input([4, 1, 1])
+				.dense(3)
+				.merge(MergeType::Add, &[0])
+				.output(2)
+				.build(),
+			Err(NetworkError::IncompatibleShape { index: 2, .. })
+		));
+	}
+
+	#[test]
+	fn batch_norm_network() {
+		let mut network = Network::builder()
+			.input([2, 1, 1])
+			.dense(4)
+			.batch_norm()
+			.output(2)
+			.build()
+			.unwrap();
+		assert!(network.layers().any(|layer| layer.uses_batch_statistics()));
+
+		let data: NetworkData = (0..8)
+			.map(|i| {
+				let x = i as Float / 8.0;
+				(
+					vec![x, 1.0 - x],
+					vec![(i % 2) as Float, 1.0 - (i % 2) as Float],
+				)
+			})
+			.collect();
+
+		network.set_training(true);
+		let outputs = network.backpropagation(&data[..4]);
+		assert_eq!(outputs.len(), 4);
+
+		// a partial last mini batch is used
+		network.sgd(data, None, 2, 3, 0.5, None);
+		assert!(!network.training());
+		assert!(network
+			.forward(vec![0.5, 0.5])
+			.iter()
+			.all(|value| value.is_finite()));
+	}
+
+	#[test]
+	fn recurrent_network() {
+		use layer::recurrent::CellType;
+
+		let mut network = Network::builder()
+			.input([5, 2, 1])
+			.recurrent(CellType::LSTM, 4)
+			.return_sequences()
+			.recurrent(CellType::GRU, 3)
+			.truncation(2)
+			.output(1)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(1).unwrap().info().output(), [5, 4, 1]);
+		assert!(network.to_string().contains("2 (GRU)"));
+
+		// predicts whether the first feature is increasing
+		let data: SequenceData = (0..8)
+			.map(|i| {
+				let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+				let sequence = (0..5).map(|t| vec![sign * t as Float / 5.0, 0.5]).collect();
+				(sequence, vec![(i % 2 == 0) as u8 as Float])
+			})
+			.collect();
+		let data = sequence_data(data).unwrap();
+		assert_eq!(
+			data[0].0,
+			vec![0.0, 0.2, 0.4, 0.6, 0.8, 0.5, 0.5, 0.5, 0.5, 0.5]
+		);
+
+		network.sgd(data, None, 2, 4, 0.5, None);
+		assert_eq!(
+			Network::builder()
+				.input([5, 2, 1])
+				.dense(3)
+				.truncation(2)
+				.output(1)
+				.build()
+				.err(),
+			Some(NetworkError::InvalidOption {
+				index: 1,
+				option: "truncation"
+			})
+		);
+	}
+
+	#[test]
+	fn embedding_network() {
+		let mut network = Network::builder()
+			.input([2, 1, 1])
+			.embedding(4, 3)
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(1).unwrap().info().output(), [2, 3, 1]);
+
+		// the first token decides the class
+		let data: TokenData = (0..16)
+			.map(|i| {
+				(
+					vec![i % 4, (i / 4) % 4],
+					vec![(i % 4 < 2) as u8 as Float, (i % 4 >= 2) as u8 as Float],
+				)
+			})
+			.collect();
+		let data = token_data(data);
+		network.sgd(data.clone(), None, 50, 4, 1.0, None);
+
+		assert_eq!(network.evaluate(&data), 16);
+		assert_eq!(
+			network.forward_tokens(&[0, 3]).unwrap(),
+			network.forward(vec![0.0, 3.0])
+		);
+		assert_eq!(
+			network.forward_tokens(&[0, 4]),
+			Err(NetworkError::InvalidToken {
+				index: 1,
+				token: 4,
+				vocabulary: 4
+			})
+		);
+		assert!(network.forward_tokens(&[0]).is_err());
+	}
+
+	#[test]
+	fn convolution_network() {
+		use layer::conv1d::Padding;
+
+		let network = Network::builder()
+			.input([8, 2, 1])
+			.conv1d(4, 3)
+			.padding(Padding::Causal)
+			.dilation(2)
+			.activation(ActivationFunction::ReLU)
+			.conv1d(2, 2)
+			.stride(2)
+			.output(1)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(1).unwrap().info().output(), [8, 4, 1]);
+		assert_eq!(network.layers().nth(2).unwrap().info().output(), [4, 2, 1]);
+		assert_eq!(
+			Network::builder()
+				.input([4, 1, 1])
+				.conv1d(2, 3)
+				.dilation(2)
+				.output(1)
+				.build()
+				.err()
+				.map(|error| matches!(error, NetworkError::IncompatibleShape { index: 1, .. })),
+			Some(true)
+		);
+	}
+
+	#[test]
+	fn upsampling_network() {
+		use layer::upsample::UpsampleType;
+
+		let mut network = Network::builder()
+			.input([2, 2, 1])
+			.conv_transpose2d(3, [3, 3])
+			.stride(2)
+			.crop(1)
+			.upsample(UpsampleType::Bilinear, 2)
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(1).unwrap().info().output(), [3, 3, 3]);
+		assert_eq!(network.layers().nth(2).unwrap().info().output(), [6, 6, 3]);
+
+		let data = vec![
+			(vec![1.0, 0.0, 0.0, 1.0], vec![1.0, 0.0]),
+			(vec![0.0, 1.0, 1.0, 0.0], vec![0.0, 1.0]),
+		];
+		network.sgd(data.clone(), None, 50, 2, 0.5, None);
+		assert_eq!(network.evaluate(&data), 2);
+	}
+
+	#[test]
+	fn reshape_network() {
+		let builder = Network::builder()
+			.input([6, 1, 1])
+			.reshape([3, 2, 1])
+			.conv1d(2, 2)
+			.flatten();
+		let flattened = builder.last_index();
+		let mut network = builder
+			.dense(3)
+			.inputs(&[0])
+			.concatenate(&[flattened])
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(3).unwrap().info().output(), [4, 1, 1]);
+		assert_eq!(network.layers().nth(5).unwrap().info().output(), [7, 1, 1]);
+		assert!(network.to_string().contains("Flatten"));
+
+		let data = vec![(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6], vec![1.0, 0.0])];
+		let outputs = network.backpropagation(&data);
+		assert_eq!(outputs[0], network.forward(data[0].0.clone()));
+
+		assert!(matches!(
+			Network::builder()
+				.input([6, 1, 1])
+				.reshape([4, 2, 1])
+				.output(2)
+				.build(),
+			Err(NetworkError::IncompatibleShape { index: 1, .. })
+		));
+	}
+
+	#[test]
+	fn pooling_network() {
+		let mut network = Network::builder()
+			.input([4, 4, 1])
+			.conv1d(4, 2)
+			.activation(ActivationFunction::Tanh)
+			.reshape([3, 1, 4])
+			.global_average_pool()
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(network.layers().nth(3).unwrap().info().output(), [4, 1, 1]);
+
+		// horizontal and vertical lines
+		let mut horizontal = vec![0.0; 16];
+		horizontal[4..8].fill(1.0);
+		let vertical: Vec<Float> = (0..16).map(|i| (i % 4 == 1) as u8 as Float).collect();
+		let data = vec![(horizontal, vec![1.0, 0.0]), (vertical, vec![0.0, 1.0])];
+		network.sgd(data.clone(), None, 100, 2, 0.5, None);
+		assert_eq!(network.evaluate(&data), 2);
+	}
+
+	#[test]
+	fn zero_sized_layer() {
+		assert_eq!(
+			error(vec![input(4), feedforward(0), output(2)]),
+			NetworkError::ZeroSizedLayer {
+				index: 1,
+				shape: [0, 1, 1]
+			}
+		);
 	}
 }
@@ -1,27 +1,88 @@
+use crate::network::simd;
+use crate::network::utility::hadamard_product;
 use crate::network::Float;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ActivationFunction {
+	ReLU,
 	Sigmoid,
 	Softmax,
+	Tanh,
 }
 
 impl ActivationFunction {
 	pub fn derivative(&self, value: Float) -> Float {
 		match self {
+			ActivationFunction::ReLU => (value > 0.0) as u8 as Float,
 			ActivationFunction::Sigmoid => d_sigmoid(value),
 			ActivationFunction::Softmax => {
-				unimplemented!()
+				unimplemented!("softmax depends on every value of a layer, use backward_all")
 			}
+			ActivationFunction::Tanh => 1.0 - value.tanh().powi(2),
 		}
 	}
 
 	pub fn evaluate(&self, value: Float) -> Float {
 		match self {
+			ActivationFunction::ReLU => value.max(0.0),
 			ActivationFunction::Sigmoid => sigmoid(value),
 			ActivationFunction::Softmax => {
-				unimplemented!()
+				unimplemented!("softmax depends on every value of a layer, use evaluate_all")
 			}
+			ActivationFunction::Tanh => value.tanh(),
+		}
+	}
+
+	// applies the activation function to every value of a layer
+	pub fn evaluate_all(&self, values: &[Float]) -> Vec<Float> {
+		match self {
+			ActivationFunction::Softmax => {
+				let max = values.iter().copied().fold(Float::MIN, Float::max);
+				let exponentials: Vec<Float> =
+					values.iter().map(|value| (value - max).exp()).collect();
+				let sum: Float = exponentials.iter().sum();
+				exponentials.iter().map(|value| value / sum).collect()
+			}
+			ActivationFunction::ReLU => simd::relu(values),
+			ActivationFunction::Sigmoid => simd::sigmoid(values),
+			ActivationFunction::Tanh => simd::tanh(values),
+		}
+	}
+
+	// the derivative of every value of a layer, see backward_all for softmax
+	pub fn derivative_all(&self, values: &[Float]) -> Vec<Float> {
+		match self {
+			ActivationFunction::ReLU => simd::relu_derivative(values),
+			ActivationFunction::Sigmoid => simd::sigmoid_derivative(values),
+			ActivationFunction::Softmax => {
+				unimplemented!("softmax depends on every value of a layer, use backward_all")
+			}
+			ActivationFunction::Tanh => simd::tanh_derivative(values),
+		}
+	}
+
+	// takes the gradient with respect to the outputs of evaluate_all
+	// and returns the gradient with respect to the values
+	pub fn backward_all(
+		&self,
+		values: &[Float],
+		outputs: &[Float],
+		output_gradient: &[Float],
+	) -> Vec<Float> {
+		match self {
+			ActivationFunction::Softmax => {
+				let weighted_sum: Float = output_gradient
+					.iter()
+					.zip(outputs)
+					.map(|(gradient, output)| gradient * output)
+					.sum();
+				outputs
+					.iter()
+					.zip(output_gradient)
+					.map(|(output, gradient)| output * (gradient - weighted_sum))
+					.collect()
+			}
+			_ => hadamard_product(output_gradient, &self.derivative_all(values)),
 		}
 	}
 }
@@ -1,22 +1,110 @@
+use crate::network::autograd::{Tape, Var};
+use crate::network::tensor::{Layout, ShapeError, Tensor};
 use crate::network::{ActivationFunction, Float};
 
-#[derive(Copy, Clone)]
+// a cost built from the output and expected output ([length, 1] column vectors)
+// with autograd, the elements of the result are summed
+pub type Loss = fn(&mut Tape, Var, Var) -> Result<Var, ShapeError>;
+
+#[derive(Copy, Clone, Debug)]
 pub enum CostFunction {
 	CrossEntropy,
+	Custom(Loss),
 	Quadratic,
 	LogLikelyhood,
 }
 
 impl CostFunction {
+	// custom costs can depend on every output so they only have a gradient
 	pub fn derivative(&self, value: Float, expected_value: Float) -> Float {
 		match self {
 			CostFunction::Quadratic => d_quadratic_cost(value, expected_value),
-			CostFunction::CrossEntropy => {
-				unimplemented!()
+			CostFunction::CrossEntropy => d_cross_entropy_cost(value, expected_value),
+			CostFunction::LogLikelyhood => d_log_likelyhood_cost(value, expected_value),
+			CostFunction::Custom(_) => {
+				unreachable!(
+					"custom costs can depend on every output so errors and gradient use the tape"
+				)
 			}
-			CostFunction::LogLikelyhood => {
-				unimplemented!()
+		}
+	}
+
+	// the gradient of the cost with respect to every output
+	pub fn gradient(&self, output: &[Float], expected_output: &[Float]) -> Vec<Float> {
+		match self {
+			CostFunction::Custom(loss) => {
+				let mut tape = Tape::new();
+				let (output_var, cost) = custom_cost(&mut tape, *loss, output, expected_output)
+					.expect("custom costs are checked when the network is built");
+				let ones = tape.value(cost).map(|_| 1.0);
+				let gradients = tape.backward(cost, ones).unwrap();
+				match gradients.get(output_var) {
+					Some(gradient) => gradient.to_layout(Layout::ColumnMajor).into_vec(),
+					None => vec![0.0; output.len()],
+				}
 			}
+			_ => output
+				.iter()
+				.zip(expected_output)
+				.map(|(&value, &expected_value)| self.derivative(value, expected_value))
+				.collect(),
+		}
+	}
+
+	// the total cost of every output
+	pub fn evaluate(&self, output: &[Float], expected_output: &[Float]) -> Float {
+		if let CostFunction::Custom(loss) = self {
+			let mut tape = Tape::new();
+			let (_, cost) = custom_cost(&mut tape, *loss, output, expected_output)
+				.expect("custom costs are checked when the network is built");
+			return tape.value(cost).as_slice().iter().sum();
+		}
+		output
+			.iter()
+			.zip(expected_output)
+			.map(|(&value, &expected_value)| match self {
+				CostFunction::Quadratic => 0.5 * (value - expected_value).powi(2),
+				CostFunction::CrossEntropy => {
+					-(expected_value * value.ln() + (1.0 - expected_value) * (1.0 - value).ln())
+				}
+				CostFunction::LogLikelyhood => -expected_value * value.ln(),
+				CostFunction::Custom(_) => unreachable!(),
+			})
+			.sum()
+	}
+
+	// runs a custom cost once on zeros so shape errors are found when the network is built
+	pub(crate) fn check(&self, length: usize) -> Result<(), ShapeError> {
+		if let CostFunction::Custom(loss) = self {
+			let zeros = vec![0.0; length];
+			custom_cost(&mut Tape::new(), *loss, &zeros, &zeros)?;
+		}
+		Ok(())
+	}
+
+	// the gradient of the cost with respect to the z values of an output layer
+	pub(crate) fn errors(
+		&self,
+		activation_function: &ActivationFunction,
+		z: &[Float],
+		output: &[Float],
+		expected_output: &[Float],
+	) -> Vec<Float> {
+		match (activation_function, self) {
+			// every softmax output depends on every z so the cost gradient can't be used one output at a time
+			// as are custom costs
+			(ActivationFunction::Softmax, CostFunction::CrossEntropy | CostFunction::Quadratic)
+			| (_, CostFunction::Custom(_)) => {
+				activation_function.backward_all(z, output, &self.gradient(output, expected_output))
+			}
+			_ => output
+				.iter()
+				.zip(expected_output)
+				.zip(z)
+				.map(|((output, expected_value), z)| {
+					self.c_dz(activation_function, output, expected_value, z)
+				})
+				.collect(),
 		}
 	}
 
@@ -33,16 +121,27 @@ impl CostFunction {
 			}
 			CostFunction::CrossEntropy => match activation_function {
 				ActivationFunction::Sigmoid => output - expected_value,
+				ActivationFunction::ReLU | ActivationFunction::Tanh => {
+					self.derivative(*output, *expected_value) * activation_function.derivative(*z)
+				}
 				ActivationFunction::Softmax => {
-					unimplemented!()
+					unimplemented!("softmax depends on every output, use backward_all")
 				}
 			},
 			CostFunction::LogLikelyhood => match activation_function {
-				ActivationFunction::Sigmoid => {
-					unimplemented!()
+				ActivationFunction::ReLU
+				| ActivationFunction::Sigmoid
+				| ActivationFunction::Tanh => {
+					self.derivative(*output, *expected_value) * activation_function.derivative(*z)
 				}
+				// assumes the expected values add up to 1
 				ActivationFunction::Softmax => output - expected_value,
 			},
+			CostFunction::Custom(_) => {
+				unreachable!(
+					"custom costs can depend on every output so errors and gradient use the tape"
+				)
+			}
 		}
 	}
 }
@@ -53,6 +152,28 @@ pub enum Regularisation {
 	None,
 }
 
+// records a custom cost on tape, returns the output and the cost
+fn custom_cost(
+	tape: &mut Tape,
+	loss: Loss,
+	output: &[Float],
+	expected_output: &[Float],
+) -> Result<(Var, Var), ShapeError> {
+	let column = |values: &[Float]| Tensor::new(values.to_vec(), &[values.len(), 1]);
+	let output = tape.input(column(output)?);
+	let expected_output = tape.input(column(expected_output)?);
+	let cost = loss(tape, output, expected_output)?;
+	Ok((output, cost))
+}
+
 fn d_quadratic_cost(value: Float, expected_value: Float) -> Float {
 	value - expected_value
 }
+
+fn d_cross_entropy_cost(value: Float, expected_value: Float) -> Float {
+	(value - expected_value) / (value * (1.0 - value))
+}
+
+fn d_log_likelyhood_cost(value: Float, expected_value: Float) -> Float {
+	-expected_value / value
+}
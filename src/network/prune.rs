@@ -0,0 +1,441 @@
+// magnitude pruning of the weights of dense layers
+//
+// the smallest weights of each dense layer are set to zero and masked so they stay zero while
+// the network is fine tuned, pruning can be done at once or gradually while training and the
+// surviving weights can be rewound to their initial values for lottery ticket experiments,
+// a pruned network can be stored with compressed sparse rows (CSR) for inference
+
+use crate::network::layer::{Layer, LayerTrait};
+use crate::network::utility::count_correct;
+use crate::network::{ActivationFunction, Float, Network, NetworkData, SequentialError};
+
+use rand::prelude::SliceRandom;
+use rand::thread_rng;
+
+use std::fmt;
+
+// gradual pruning from "To prune, or not to prune" (Zhu and Gupta), the sparsity goes from
+// initial_sparsity to final_sparsity between start_epoch and end_epoch, quickly at first
+// then slowly so the network can recover from losing weights
+#[derive(Copy, Clone, Debug)]
+pub struct PruningSchedule {
+	pub end_epoch: usize,
+	pub final_sparsity: Float,
+	pub initial_sparsity: Float,
+	pub start_epoch: usize,
+}
+
+// a weight matrix in compressed sparse row format
+struct SparseMatrix {
+	column_indices: Vec<u32>,
+	// row r has the values row_offsets[r]..row_offsets[r + 1]
+	row_offsets: Vec<u32>,
+	values: Vec<Float>,
+}
+
+struct SparseLayer {
+	activation_function: ActivationFunction,
+	biases: Vec<Float>,
+	weights: SparseMatrix,
+}
+
+// a network of dense layers with only the nonzero weights stored
+pub struct SparseNetwork {
+	layers: Vec<SparseLayer>,
+}
+
+// accuracies are the fraction of test data classified correctly and sizes are in bytes
+#[derive(Copy, Clone, Debug)]
+pub struct PruningResult {
+	pub accuracy: f64,
+	pub sparse_size: usize,
+	pub sparsity: Float,
+}
+
+// accuracy against sparsity starting from the unpruned network
+#[derive(Clone, Debug)]
+pub struct PruningReport {
+	pub results: Vec<PruningResult>,
+	pub size: usize,
+}
+
+impl PruningSchedule {
+	pub fn sparsity(&self, epoch: usize) -> Float {
+		if epoch <= self.start_epoch {
+			return self.initial_sparsity;
+		}
+		if epoch >= self.end_epoch {
+			return self.final_sparsity;
+		}
+		let progress =
+			(epoch - self.start_epoch) as Float / (self.end_epoch - self.start_epoch) as Float;
+		self.final_sparsity
+			+ (self.initial_sparsity - self.final_sparsity) * (1.0 - progress).powi(3)
+	}
+}
+
+impl Network {
+	// prunes the weights with the smallest magnitude in every dense layer so sparsity
+	// (0 to 1) of each layer's weights are zero, weights that are already pruned stay pruned
+	pub fn prune(&mut self, sparsity: Float) {
+		for layer in self.layers.iter_mut() {
+			if let Layer::FeedForward(layer) = layer {
+				let weights = layer.data().weights();
+				let pruned = layer
+					.data()
+					.mask()
+					.map_or(0, |mask| mask.iter().filter(|&&keep| !keep).count());
+				let count = ((sparsity * weights.len() as Float).round() as usize)
+					.clamp(pruned, weights.len());
+
+				// already pruned weights come first so they are always pruned again
+				let mut order: Vec<usize> = (0..weights.len()).collect();
+				let key = |&i: &usize| {
+					let kept = layer.data().mask().map_or(true, |mask| mask[i]);
+					(kept, weights[i].abs())
+				};
+				order.sort_by(|a, b| {
+					let ((a_kept, a), (b_kept, b)) = (key(a), key(b));
+					a_kept.cmp(&b_kept).then(a.total_cmp(&b))
+				});
+
+				let mut mask = vec![true; weights.len()];
+				for &i in &order[..count] {
+					mask[i] = false;
+				}
+				layer.set_mask(Some(mask));
+			}
+		}
+	}
+
+	// lets pruned weights train again, they start from zero
+	pub fn remove_masks(&mut self) {
+		for layer in self.layers.iter_mut() {
+			if let Layer::FeedForward(layer) = layer {
+				layer.set_mask(None);
+			}
+		}
+	}
+
+	// the fraction of the weights of dense layers that are zero
+	pub fn sparsity(&self) -> Float {
+		let (zeros, total) = self
+			.layers
+			.iter()
+			.filter_map(|layer| match layer {
+				Layer::FeedForward(layer) => Some(layer.data().weights()),
+				_ => None,
+			})
+			.fold((0, 0), |(zeros, total), weights| {
+				let layer_zeros = weights.iter().filter(|&&weight| weight == 0.0).count();
+				(zeros + layer_zeros, total + weights.len())
+			});
+		match total {
+			0 => 0.0,
+			_ => zeros as Float / total as Float,
+		}
+	}
+
+	// a copy of the parameters of every layer, used to rewind a network to its initialisation,
+	// it goes through parameters_mut so the values are in the order rewind sets them
+	pub fn parameter_snapshot(&mut self) -> Vec<Vec<Float>> {
+		self.layers
+			.iter_mut()
+			.flat_map(|layer| layer.parameters_mut())
+			.map(|parameters| parameters.to_vec())
+			.collect()
+	}
+
+	// sets every parameter to the values from parameters keeping pruned weights at zero,
+	// rewinding to the initial parameters after pruning finds a lottery ticket
+	pub fn rewind(&mut self, parameters: &[Vec<Float>]) {
+		let mut count = 0;
+		for (current, parameters) in self
+			.layers
+			.iter_mut()
+			.flat_map(|layer| layer.parameters_mut())
+			.zip(parameters)
+		{
+			current.copy_from_slice(parameters);
+			count += 1;
+		}
+		assert_eq!(
+			count,
+			parameters.len(),
+			"parameters must come from this network"
+		);
+		self.apply_masks();
+	}
+
+	// sets pruned weights back to zero after parameters are changed through parameters_mut,
+	// which goes around the masks that FeedForward::update applies
+	pub(crate) fn apply_masks(&mut self) {
+		for layer in self.layers.iter_mut() {
+			if let Layer::FeedForward(layer) = layer {
+				layer.apply_mask();
+			}
+		}
+	}
+
+	// the same as sgd without test data, the network is pruned to the sparsity of the
+	// schedule at the start of each epoch
+	pub fn sgd_gradual_pruning(
+		&mut self,
+		mut training_data: NetworkData,
+		epochs: usize,
+		mini_batch_size: usize,
+		learning_rate: Float,
+		schedule: PruningSchedule,
+	) {
+		for i in 0..epochs {
+			self.prune(schedule.sparsity(i));
+			training_data.shuffle(&mut thread_rng());
+
+			self.set_training(true);
+			for mini_batch in training_data.chunks(mini_batch_size) {
+				self.accumulate_changes(mini_batch);
+				self.apply_layer_changes(learning_rate, mini_batch_size);
+			}
+			self.set_training(false);
+
+			println!(
+				"Epoch {} complete. Sparsity: {:.1}%",
+				i + 1,
+				self.sparsity() * 100.0
+			);
+		}
+	}
+}
+
+impl SparseMatrix {
+	// weights is column major with dimensions [rows, columns]
+	fn new(weights: &[Float], dimensions: [usize; 2]) -> Self {
+		let rows = dimensions[0];
+		let mut column_indices = Vec::new();
+		let mut row_offsets = vec![0];
+		let mut values = Vec::new();
+		for row in 0..rows {
+			for (column, &weight) in weights.iter().skip(row).step_by(rows).enumerate() {
+				if weight != 0.0 {
+					column_indices.push(column as u32);
+					values.push(weight);
+				}
+			}
+			row_offsets.push(values.len() as u32);
+		}
+
+		SparseMatrix {
+			column_indices,
+			row_offsets,
+			values,
+		}
+	}
+
+	// performs c += self * b
+	fn multiply_add(&self, b: &[Float], c: &mut [Float]) {
+		assert_eq!(c.len() + 1, self.row_offsets.len());
+		for (row, c) in c.iter_mut().enumerate() {
+			let range = self.row_offsets[row] as usize..self.row_offsets[row + 1] as usize;
+			*c += self.column_indices[range.clone()]
+				.iter()
+				.zip(&self.values[range])
+				.map(|(&column, value)| value * b[column as usize])
+				.sum::<Float>();
+		}
+	}
+
+	fn size(&self) -> usize {
+		self.values.len() * std::mem::size_of::<Float>()
+			+ (self.column_indices.len() + self.row_offsets.len()) * std::mem::size_of::<u32>()
+	}
+}
+
+impl SparseNetwork {
+	pub fn new(network: &Network) -> Result<Self, SequentialError> {
+		let layers = network
+			.dense_layers()?
+			.into_iter()
+			.map(|layer| SparseLayer {
+				activation_function: layer.activation_function,
+				biases: layer.biases.to_vec(),
+				weights: SparseMatrix::new(layer.weights, layer.weight_dimensions),
+			})
+			.collect();
+		Ok(SparseNetwork { layers })
+	}
+
+	pub fn forward(&self, input: &[Float]) -> Vec<Float> {
+		self.layers.iter().fold(input.to_vec(), |input, layer| {
+			let mut z = layer.biases.clone();
+			layer.weights.multiply_add(&input, &mut z);
+			layer.activation_function.evaluate_all(&z)
+		})
+	}
+
+	// the same as Network::evaluate
+	pub fn evaluate(&self, test_data: &NetworkData) -> usize {
+		count_correct(test_data, |input| self.forward(input))
+	}
+
+	// the size of the nonzero weights, their indices and the biases in bytes
+	pub fn size(&self) -> usize {
+		self.layers
+			.iter()
+			.map(|layer| layer.weights.size() + layer.biases.len() * std::mem::size_of::<Float>())
+			.sum()
+	}
+}
+
+impl PruningReport {
+	// starts the report with the accuracy of network before it is pruned
+	pub fn new(network: &mut Network, test_data: &NetworkData) -> Result<Self, SequentialError> {
+		let mut report = PruningReport {
+			results: Vec::new(),
+			size: network.parameter_count() * std::mem::size_of::<Float>(),
+		};
+		report.add(network, test_data)?;
+		Ok(report)
+	}
+
+	// adds the accuracy of network at its current sparsity
+	pub fn add(
+		&mut self,
+		network: &mut Network,
+		test_data: &NetworkData,
+	) -> Result<(), SequentialError> {
+		let sparse = SparseNetwork::new(network)?;
+		self.results.push(PruningResult {
+			accuracy: sparse.evaluate(test_data) as f64 / test_data.len() as f64,
+			sparse_size: sparse.size(),
+			sparsity: network.sparsity(),
+		});
+		Ok(())
+	}
+}
+
+impl fmt::Display for PruningReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Dense size: {} bytes", self.size)?;
+		write!(
+			f,
+			"{:>10} {:>10} {:>14}",
+			"Sparsity", "Accuracy", "Sparse size"
+		)?;
+		for result in &self.results {
+			write!(
+				f,
+				"\n{:>9.1}% {:>9.2}% {:>8} bytes",
+				result.sparsity * 100.0,
+				result.accuracy * 100.0,
+				result.sparse_size
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::fixtures::data;
+
+	fn network() -> Network {
+		Network::builder()
+			.input([6, 1, 1])
+			.dense(32)
+			.activation(ActivationFunction::Tanh)
+			.output(2)
+			.activation(ActivationFunction::Sigmoid)
+			.build()
+			.unwrap()
+	}
+
+	#[test]
+	fn schedule() {
+		let schedule = PruningSchedule {
+			end_epoch: 4,
+			final_sparsity: 0.8,
+			initial_sparsity: 0.0,
+			start_epoch: 1,
+		};
+		let sparsities: Vec<Float> = (0..6).map(|epoch| schedule.sparsity(epoch)).collect();
+		assert_eq!(sparsities[..2], [0.0, 0.0]);
+		assert_eq!(sparsities[4..], [0.8, 0.8]);
+		assert!(sparsities[2] > 0.8 / 3.0 && sparsities[3] > sparsities[2]);
+	}
+
+	#[test]
+	fn mixed_precision_keeps_masks() {
+		use crate::network::{HalfType, MixedPrecision};
+
+		let mut network = network();
+		network.prune(0.5);
+		MixedPrecision::new(HalfType::BF16)
+			.sgd(&mut network, data(50), 1, 10, 0.1)
+			.unwrap();
+		assert_eq!(network.sparsity(), 0.5);
+	}
+
+	#[test]
+	fn masks() {
+		let mut network = network();
+		let initial = network.parameter_snapshot();
+		network.prune(0.5);
+		assert_eq!(network.sparsity(), 0.5);
+
+		// the largest pruned weight is no larger than the smallest weight left
+		let weights = network.parameter_snapshot()[0].clone();
+		let largest_pruned = initial[0]
+			.iter()
+			.zip(&weights)
+			.filter(|(_, &weight)| weight == 0.0)
+			.fold(0.0, |max: Float, (initial, _)| max.max(initial.abs()));
+		assert!(weights
+			.iter()
+			.filter(|&&weight| weight != 0.0)
+			.all(|weight| weight.abs() >= largest_pruned));
+
+		// fine tuning keeps pruned weights at zero
+		network.sgd(data(200), None, 2, 10, 0.1, None);
+		assert_eq!(network.sparsity(), 0.5);
+
+		// rewinding resets every other parameter, including biases and the output layer
+		network.rewind(&initial);
+		assert_eq!(network.sparsity(), 0.5);
+		let rewound = network.parameter_snapshot();
+		assert_eq!(rewound[0], weights);
+		assert_eq!(rewound[1..], initial[1..]);
+
+		// pruning to a lower sparsity doesn't bring weights back
+		network.prune(0.25);
+		assert_eq!(network.sparsity(), 0.5);
+		network.remove_masks();
+		network.sgd(data(200), None, 1, 10, 0.1, None);
+		assert!(network.sparsity() < 0.5);
+	}
+
+	#[test]
+	fn sparse_network() {
+		let mut network = network();
+		let schedule = PruningSchedule {
+			end_epoch: 4,
+			final_sparsity: 0.9,
+			initial_sparsity: 0.0,
+			start_epoch: 0,
+		};
+		network.sgd_gradual_pruning(data(400), 6, 10, 0.1, schedule);
+		assert!((network.sparsity() - 0.9).abs() < 0.01);
+
+		let sparse = SparseNetwork::new(&network).unwrap();
+		for (input, _) in data(20) {
+			let output = network.forward(input.clone());
+			for (value, sparse) in output.iter().zip(sparse.forward(&input)) {
+				assert!((value - sparse).abs() < 1e-5);
+			}
+		}
+
+		let report = PruningReport::new(&mut network, &data(200)).unwrap();
+		assert!(report.results[0].accuracy > 0.8);
+		assert!(report.results[0].sparse_size < report.size);
+	}
+}
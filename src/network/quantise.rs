@@ -0,0 +1,360 @@
+// int8 post training quantisation of dense networks for inference
+//
+// weights are stored as i8 with a scale for the whole layer or for each output (channel),
+// the input to each layer is quantised with a scale found by running calibration data
+// through the original network so matrix multiplications are done with integers
+
+use crate::network::layer::LayerTrait;
+use crate::network::utility::count_correct;
+use crate::network::{ActivationFunction, Float, Network, NetworkData, SequentialError};
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Granularity {
+	PerLayer,
+	// a scale for each output of a layer, more accurate when the rows of a weight matrix
+	// have very different ranges
+	PerChannel,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuantiseError {
+	EmptyCalibration,
+	Sequential(SequentialError),
+}
+
+impl fmt::Display for QuantiseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			QuantiseError::EmptyCalibration => {
+				write!(f, "at least one sample is needed to calibrate a network")
+			}
+			QuantiseError::Sequential(error) => write!(f, "{}", error),
+		}
+	}
+}
+
+impl Error for QuantiseError {}
+
+impl From<SequentialError> for QuantiseError {
+	fn from(error: SequentialError) -> Self {
+		QuantiseError::Sequential(error)
+	}
+}
+
+struct QuantisedLayer {
+	activation_function: ActivationFunction,
+	// quantised with input_scale * weight_scale
+	biases: Vec<i32>,
+	input_scale: Float,
+	// column major with dimensions [outputs, inputs]
+	weights: Vec<i8>,
+	weight_dimensions: [usize; 2],
+	// one scale for the layer or one for each output
+	weight_scales: Vec<Float>,
+}
+
+pub struct QuantisedNetwork {
+	granularity: Granularity,
+	layers: Vec<QuantisedLayer>,
+}
+
+// accuracies are the fraction of test data classified correctly and sizes are in bytes
+#[derive(Copy, Clone, Debug)]
+pub struct QuantisationReport {
+	pub accuracy: f64,
+	pub granularity: Granularity,
+	pub quantised_accuracy: f64,
+	pub quantised_size: usize,
+	pub size: usize,
+}
+
+impl QuantisedNetwork {
+	// calibration is usually a few hundred samples of the training data
+	pub fn new(
+		network: &mut Network,
+		calibration: &NetworkData,
+		granularity: Granularity,
+	) -> Result<Self, QuantiseError> {
+		if calibration.is_empty() {
+			return Err(QuantiseError::EmptyCalibration);
+		}
+
+		// the indices of the dense and output layers
+		let indices: Vec<usize> = network
+			.dense_layers()?
+			.iter()
+			.map(|layer| layer.index)
+			.collect();
+
+		// the largest absolute value of the input to each layer
+		let training = network.training();
+		network.set_training(false);
+		let mut ranges: Vec<Float> = vec![0.0; indices.len()];
+		for (input, _) in calibration {
+			network.forward(input.clone());
+			for (range, &index) in ranges.iter_mut().zip(&indices) {
+				let input = network.layers[index - 1].last_output();
+				*range = input
+					.as_slice()
+					.iter()
+					.fold(*range, |range, value| range.max(value.abs()));
+			}
+		}
+		network.set_training(training);
+
+		let layers = network
+			.dense_layers()?
+			.into_iter()
+			.zip(ranges)
+			.map(|(layer, range)| {
+				QuantisedLayer::new(
+					layer.activation_function,
+					layer.weights,
+					layer.biases,
+					layer.weight_dimensions,
+					scale(range),
+					granularity,
+				)
+			})
+			.collect();
+
+		Ok(QuantisedNetwork {
+			granularity,
+			layers,
+		})
+	}
+
+	pub fn forward(&self, input: &[Float]) -> Vec<Float> {
+		self.layers
+			.iter()
+			.fold(input.to_vec(), |input, layer| layer.forward(&input))
+	}
+
+	// the same as Network::evaluate
+	pub fn evaluate(&self, test_data: &NetworkData) -> usize {
+		count_correct(test_data, |input| self.forward(input))
+	}
+
+	// the size of the weights, biases and scales in bytes
+	pub fn size(&self) -> usize {
+		self.layers
+			.iter()
+			.map(|layer| {
+				layer.weights.len()
+					+ layer.biases.len() * std::mem::size_of::<i32>()
+					+ (layer.weight_scales.len() + 1) * std::mem::size_of::<Float>()
+			})
+			.sum()
+	}
+
+	// compares the accuracy and size with the network it was made from
+	pub fn report(&self, network: &mut Network, test_data: &NetworkData) -> QuantisationReport {
+		let training = network.training();
+		network.set_training(false);
+		let accuracy = network.evaluate(test_data) as f64 / test_data.len() as f64;
+		network.set_training(training);
+
+		QuantisationReport {
+			accuracy,
+			granularity: self.granularity,
+			quantised_accuracy: self.evaluate(test_data) as f64 / test_data.len() as f64,
+			quantised_size: self.size(),
+			size: network.parameter_count() * std::mem::size_of::<Float>(),
+		}
+	}
+}
+
+impl QuantisedLayer {
+	fn new(
+		activation_function: ActivationFunction,
+		weights: &[Float],
+		biases: &[Float],
+		weight_dimensions: [usize; 2],
+		input_scale: Float,
+		granularity: Granularity,
+	) -> Self {
+		let rows = weight_dimensions[0];
+		let weight_scales: Vec<Float> = match granularity {
+			Granularity::PerLayer => {
+				vec![scale(
+					weights
+						.iter()
+						.fold(0.0, |max, weight| max.max(weight.abs())),
+				)]
+			}
+			Granularity::PerChannel => (0..rows)
+				.map(|row| {
+					let range = weights
+						.iter()
+						.skip(row)
+						.step_by(rows)
+						.fold(0.0, |max: Float, weight| max.max(weight.abs()));
+					scale(range)
+				})
+				.collect(),
+		};
+		let weight_scale = |row: usize| weight_scales[row % weight_scales.len()];
+
+		let biases = biases
+			.iter()
+			.enumerate()
+			.map(|(row, bias)| (bias / (input_scale * weight_scale(row))).round() as i32)
+			.collect();
+		let weights = weights
+			.iter()
+			.enumerate()
+			.map(|(i, weight)| quantise(*weight, weight_scale(i % rows)))
+			.collect();
+
+		QuantisedLayer {
+			activation_function,
+			biases,
+			input_scale,
+			weights,
+			weight_dimensions,
+			weight_scales,
+		}
+	}
+
+	fn forward(&self, input: &[Float]) -> Vec<Float> {
+		let input: Vec<i8> = input
+			.iter()
+			.map(|value| quantise(*value, self.input_scale))
+			.collect();
+
+		let mut z = self.biases.clone();
+		integer_matrix_vec_multiply_add(&self.weights, &input, &mut z, self.weight_dimensions);
+
+		let z: Vec<Float> = z
+			.iter()
+			.enumerate()
+			.map(|(row, &value)| {
+				let weight_scale = self.weight_scales[row % self.weight_scales.len()];
+				value as Float * self.input_scale * weight_scale
+			})
+			.collect();
+		self.activation_function.evaluate_all(&z)
+	}
+}
+
+impl fmt::Display for QuantisationReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Int8 quantisation ({:?})", self.granularity)?;
+		writeln!(
+			f,
+			"Accuracy: {:.2}% -> {:.2}% ({:+.2}%)",
+			self.accuracy * 100.0,
+			self.quantised_accuracy * 100.0,
+			(self.quantised_accuracy - self.accuracy) * 100.0
+		)?;
+		write!(
+			f,
+			"Size: {} bytes -> {} bytes ({:.1}x smaller)",
+			self.size,
+			self.quantised_size,
+			self.size as f64 / self.quantised_size as f64
+		)
+	}
+}
+
+// symmetric scale mapping -range..range to -127..127
+fn scale(range: Float) -> Float {
+	match range > 0.0 {
+		true => range / 127.0,
+		false => 1.0,
+	}
+}
+
+fn quantise(value: Float, scale: Float) -> i8 {
+	(value / scale).round().clamp(-127.0, 127.0) as i8
+}
+
+// performs c += a * b where a is column major with dimensions dim,
+// products are accumulated in i32 so they can't overflow for fewer than 2^17 inputs
+fn integer_matrix_vec_multiply_add(a: &[i8], b: &[i8], c: &mut [i32], dim: [usize; 2]) {
+	assert_eq!(a.len(), dim[0] * dim[1]);
+	assert_eq!(b.len(), dim[1]);
+	assert_eq!(c.len(), dim[0]);
+	for (column, &b) in a.chunks_exact(dim[0]).zip(b) {
+		if b == 0 {
+			continue;
+		}
+		for (c, &a) in c.iter_mut().zip(column) {
+			*c += a as i32 * b as i32;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::fixtures::data;
+	use crate::network::layer::recurrent::CellType;
+
+	#[test]
+	fn integer_multiply() {
+		// [[1, -2], [3, 4]] * [5, -6]
+		let mut c = vec![1, 0];
+		integer_matrix_vec_multiply_add(&[1, 3, -2, 4], &[5, -6], &mut c, [2, 2]);
+		assert_eq!(c, vec![18, -9]);
+	}
+
+	#[test]
+	fn quantised_network() {
+		let mut network = Network::builder()
+			.input([6, 1, 1])
+			.dense(16)
+			.activation(ActivationFunction::ReLU)
+			.dropout(0.1)
+			.output(2)
+			.activation(ActivationFunction::Sigmoid)
+			.build()
+			.unwrap();
+		network.sgd(data(400), None, 5, 10, 0.1, None);
+
+		for granularity in [Granularity::PerLayer, Granularity::PerChannel] {
+			let quantised = QuantisedNetwork::new(&mut network, &data(50), granularity).unwrap();
+
+			for (input, _) in data(20) {
+				let output = network.forward(input.clone());
+				for (value, quantised) in output.iter().zip(quantised.forward(&input)) {
+					assert!((value - quantised).abs() < 0.05, "{} {}", value, quantised);
+				}
+			}
+
+			let report = quantised.report(&mut network, &data(200));
+			assert!((report.quantised_accuracy - report.accuracy).abs() < 0.05);
+			assert_eq!(
+				report.size,
+				(6 * 16 + 16 + 16 * 2 + 2) * std::mem::size_of::<Float>()
+			);
+			assert!(report.quantised_size * 2 < report.size);
+		}
+	}
+
+	#[test]
+	fn unsupported_layers() {
+		let mut network = Network::builder()
+			.input([3, 2, 1])
+			.recurrent(CellType::RNN, 4)
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(
+			QuantisedNetwork::new(&mut network, &data(1), Granularity::PerLayer).err(),
+			Some(QuantiseError::Sequential(
+				SequentialError::UnsupportedLayer {
+					index: 1,
+					name: String::from("RNN")
+				}
+			))
+		);
+		assert_eq!(
+			QuantisedNetwork::new(&mut network, &Vec::new(), Granularity::PerLayer).err(),
+			Some(QuantiseError::EmptyCalibration)
+		);
+	}
+}
@@ -0,0 +1,84 @@
+// the dense layers of a sequential network, used by the quantised and sparse networks which only
+// support input, dense, dropout and output layers
+
+use crate::network::layer::{Layer, LayerTrait};
+use crate::network::{ActivationFunction, Float, Network};
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SequentialError {
+	// only input, dense, dropout and output layers are supported
+	UnsupportedLayer { index: usize, name: String },
+	// layers must each take input from the layer before them
+	NotSequential { index: usize },
+}
+
+pub(crate) struct DenseLayer<'a> {
+	pub activation_function: ActivationFunction,
+	pub biases: &'a [Float],
+	// the index of the layer in the network
+	pub index: usize,
+	// column major with dimensions [outputs, inputs]
+	pub weights: &'a [Float],
+	pub weight_dimensions: [usize; 2],
+}
+
+impl fmt::Display for SequentialError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SequentialError::UnsupportedLayer { index, name } => write!(
+				f,
+				"layer {} ({}) isn't supported, only input, dense, dropout and output layers are",
+				index, name
+			),
+			SequentialError::NotSequential { index } => write!(
+				f,
+				"layer {} doesn't take input from the layer before it, only sequential networks are supported",
+				index
+			),
+		}
+	}
+}
+
+impl Error for SequentialError {}
+
+impl Network {
+	// the dense and output layers in order, dropout layers are skipped
+	pub(crate) fn dense_layers(&self) -> Result<Vec<DenseLayer<'_>>, SequentialError> {
+		let mut layers = Vec::new();
+		for (index, layer) in self.layers.iter().enumerate().skip(1) {
+			if self.connections[index] != [index - 1] {
+				return Err(SequentialError::NotSequential { index });
+			}
+			let (weights, biases, weight_dimensions) = match layer {
+				Layer::Dropout(_) => continue,
+				Layer::FeedForward(layer) => (
+					layer.data().weights(),
+					layer.data().biases(),
+					layer.data().weight_dimensions(),
+				),
+				Layer::OutputLayer(layer) => (
+					layer.data().weights(),
+					layer.data().biases(),
+					layer.data().weight_dimensions(),
+				),
+				_ => {
+					return Err(SequentialError::UnsupportedLayer {
+						index,
+						name: layer.info().name().to_string(),
+					})
+				}
+			};
+			layers.push(DenseLayer {
+				activation_function: layer.info().activation_function().unwrap(),
+				biases,
+				index,
+				weights,
+				weight_dimensions,
+			});
+		}
+		Ok(layers)
+	}
+}
@@ -0,0 +1,464 @@
+// vectorised versions of the elementwise operations used by the layers
+//
+// AVX is used when the cpu supports it (checked at runtime), everything else
+// falls back to the scalar loops which the kernels are tested against
+
+use crate::network::Float;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+// turns the vectorised kernels on or off, mostly useful to compare performance
+pub fn set_simd(enabled: bool) {
+	ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// whether the vectorised kernels are enabled and supported by this cpu
+pub fn simd_enabled() -> bool {
+	ENABLED.load(Ordering::Relaxed) && avx_supported()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn avx_supported() -> bool {
+	// the result is cached by std so this is cheap to call for every kernel
+	is_x86_feature_detected!("avx")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn avx_supported() -> bool {
+	false
+}
+
+// a * b for each element
+pub fn multiply(a: &[Float], b: &[Float]) -> Vec<Float> {
+	assert_eq!(a.len(), b.len());
+	let mut output = vec![0.0; a.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::multiply(a, b, &mut output) };
+		return output;
+	}
+	scalar::multiply(a, b, &mut output);
+	output
+}
+
+// a += b for each element
+pub fn add_assign(a: &mut [Float], b: &[Float]) {
+	assert_eq!(a.len(), b.len());
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		return unsafe { avx::add_assign(a, b) };
+	}
+	scalar::add_assign(a, b)
+}
+
+// max(value, 0) for each element
+pub fn relu(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::relu(values, &mut output) };
+		return output;
+	}
+	scalar::relu(values, &mut output);
+	output
+}
+
+// 1 where value > 0 otherwise 0 for each element
+pub fn relu_derivative(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::relu_derivative(values, &mut output) };
+		return output;
+	}
+	scalar::relu_derivative(values, &mut output);
+	output
+}
+
+// 1 / (1 + exp(-value)) for each element
+pub fn sigmoid(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::sigmoid(values, &mut output) };
+		return output;
+	}
+	scalar::sigmoid(values, &mut output);
+	output
+}
+
+// sigmoid(value) * (1 - sigmoid(value)) for each element
+pub fn sigmoid_derivative(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::sigmoid_derivative(values, &mut output) };
+		return output;
+	}
+	scalar::sigmoid_derivative(values, &mut output);
+	output
+}
+
+// tanh(value) for each element
+pub fn tanh(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::tanh(values, &mut output) };
+		return output;
+	}
+	scalar::tanh(values, &mut output);
+	output
+}
+
+// 1 - tanh(value)^2 for each element
+pub fn tanh_derivative(values: &[Float]) -> Vec<Float> {
+	let mut output = vec![0.0; values.len()];
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		unsafe { avx::tanh_derivative(values, &mut output) };
+		return output;
+	}
+	scalar::tanh_derivative(values, &mut output);
+	output
+}
+
+// weight += multiplier * (change + lambda * signum(weight)) for each weight
+pub fn l2_update(weights: &mut [Float], changes: &[Float], multiplier: Float, lambda: Float) {
+	assert_eq!(weights.len(), changes.len());
+	#[cfg(target_arch = "x86_64")]
+	if simd_enabled() {
+		return unsafe { avx::l2_update(weights, changes, multiplier, lambda) };
+	}
+	scalar::l2_update(weights, changes, multiplier, lambda)
+}
+
+mod scalar {
+	use crate::network::Float;
+
+	pub fn multiply(a: &[Float], b: &[Float], output: &mut [Float]) {
+		for (output, (a, b)) in output.iter_mut().zip(a.iter().zip(b)) {
+			*output = a * b;
+		}
+	}
+
+	pub fn add_assign(a: &mut [Float], b: &[Float]) {
+		for (a, b) in a.iter_mut().zip(b) {
+			*a += b;
+		}
+	}
+
+	pub fn relu(values: &[Float], output: &mut [Float]) {
+		for (output, value) in output.iter_mut().zip(values) {
+			*output = value.max(0.0);
+		}
+	}
+
+	pub fn relu_derivative(values: &[Float], output: &mut [Float]) {
+		for (output, &value) in output.iter_mut().zip(values) {
+			*output = (value > 0.0) as u8 as Float;
+		}
+	}
+
+	pub fn sigmoid(values: &[Float], output: &mut [Float]) {
+		for (output, value) in output.iter_mut().zip(values) {
+			*output = 1.0 / (1.0 + (-value).exp());
+		}
+	}
+
+	pub fn sigmoid_derivative(values: &[Float], output: &mut [Float]) {
+		sigmoid(values, output);
+		for output in output.iter_mut() {
+			*output *= 1.0 - *output;
+		}
+	}
+
+	pub fn tanh(values: &[Float], output: &mut [Float]) {
+		for (output, value) in output.iter_mut().zip(values) {
+			*output = value.tanh();
+		}
+	}
+
+	pub fn tanh_derivative(values: &[Float], output: &mut [Float]) {
+		for (output, value) in output.iter_mut().zip(values) {
+			*output = 1.0 - value.tanh().powi(2);
+		}
+	}
+
+	pub fn l2_update(weights: &mut [Float], changes: &[Float], multiplier: Float, lambda: Float) {
+		for (weight, change) in weights.iter_mut().zip(changes) {
+			*weight += multiplier * (change + lambda * weight.signum());
+		}
+	}
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx {
+	use super::scalar;
+	use crate::network::Float;
+
+	use std::arch::x86_64::{
+		_CMP_GT_OQ, _CMP_UNORD_Q, _MM_FROUND_NO_EXC, _MM_FROUND_TO_NEAREST_INT,
+	};
+
+	// the f32 intrinsics, Float is f32
+	use std::arch::x86_64::{
+		__m256 as Register, _mm256_add_ps as add, _mm256_and_ps as and, _mm256_blendv_ps as blend,
+		_mm256_cmp_ps as compare, _mm256_div_ps as div, _mm256_loadu_ps as load,
+		_mm256_max_ps as max, _mm256_min_ps as min, _mm256_mul_ps as mul, _mm256_or_ps as or,
+		_mm256_round_ps as round, _mm256_set1_ps as splat, _mm256_setzero_ps as zero,
+		_mm256_storeu_ps as store, _mm256_sub_ps as sub,
+	};
+
+	// the number of Floats in a 256 bit register
+	const LANES: usize = 32 / std::mem::size_of::<Float>();
+
+	// the kernels below handle whole registers and leave the remainder to the scalar loops
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn multiply(a: &[Float], b: &[Float], output: &mut [Float]) {
+		let length = output.len() - output.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let product = mul(load(a.as_ptr().add(i)), load(b.as_ptr().add(i)));
+			store(output.as_mut_ptr().add(i), product);
+		}
+		scalar::multiply(&a[length..], &b[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn add_assign(a: &mut [Float], b: &[Float]) {
+		let length = a.len() - a.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let sum = add(load(a.as_ptr().add(i)), load(b.as_ptr().add(i)));
+			store(a.as_mut_ptr().add(i), sum);
+		}
+		scalar::add_assign(&mut a[length..], &b[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn relu(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			store(
+				output.as_mut_ptr().add(i),
+				max(load(values.as_ptr().add(i)), zero()),
+			);
+		}
+		scalar::relu(&values[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn relu_derivative(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			// the comparison sets every bit of a lane when true so masking 1 with it gives 1 or 0
+			let positive = compare::<_CMP_GT_OQ>(load(values.as_ptr().add(i)), zero());
+			store(output.as_mut_ptr().add(i), and(positive, splat(1.0)));
+		}
+		scalar::relu_derivative(&values[length..], &mut output[length..]);
+	}
+
+	// exp(x) = 2^n * exp(r) where n = round(x / ln(2)) and r = x - n * ln(2) is at most
+	// ln(2) / 2 in size, so a short Taylor series for exp(r) is accurate to about 1 ulp
+	#[target_feature(enable = "avx")]
+	unsafe fn exp(x: Register) -> Register {
+		// ln(2) split so n * LN_2_HIGH is exact
+		const LN_2_HIGH: Float = 355.0 / 512.0;
+		const LN_2_LOW: Float = -2.121_944_4e-4;
+		const TERMS: usize = 7;
+
+		// exp overflows or is subnormal outside this range, max and min return the bound for NaN
+		// so NaN lanes are put back at the end
+		let nan = compare::<_CMP_UNORD_Q>(x, x);
+		let clamped = min(max(x, splat(-MAX_EXPONENT)), splat(MAX_EXPONENT));
+		let n = round::<{ _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC }>(mul(
+			clamped,
+			splat(std::f64::consts::LOG2_E as Float),
+		));
+		let r = sub(
+			sub(clamped, mul(n, splat(LN_2_HIGH))),
+			mul(n, splat(LN_2_LOW)),
+		);
+
+		let mut series = splat(1.0);
+		for k in (1..=TERMS).rev() {
+			series = add(splat(1.0), mul(mul(r, series), splat(1.0 / k as Float)));
+		}
+		blend(mul(series, power_of_two(n)), x, nan)
+	}
+
+	// the largest x where exp(x) and exp(-x) are finite normal numbers
+	const MAX_EXPONENT: Float = 87.0;
+
+	// 2^n for whole numbers n by putting n + bias in the exponent bits
+	#[target_feature(enable = "avx")]
+	unsafe fn power_of_two(n: Register) -> Register {
+		use std::arch::x86_64::{_mm256_castsi256_ps, _mm256_cvtps_epi32};
+		// (n + 127) * 2^23 is the exponent bits and is exact in f32 so no integer shift is needed
+		_mm256_castsi256_ps(_mm256_cvtps_epi32(mul(
+			add(n, splat(127.0)),
+			splat(8_388_608.0),
+		)))
+	}
+
+	#[target_feature(enable = "avx")]
+	unsafe fn sigmoid_register(x: Register) -> Register {
+		let one = splat(1.0);
+		div(one, add(one, exp(sub(zero(), x))))
+	}
+
+	// 1 - 2 / (exp(2x) + 1) which is -1 and 1 when exp(2x) is clamped
+	#[target_feature(enable = "avx")]
+	unsafe fn tanh_register(x: Register) -> Register {
+		let one = splat(1.0);
+		sub(one, div(splat(2.0), add(exp(add(x, x)), one)))
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn sigmoid(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let sigmoid = sigmoid_register(load(values.as_ptr().add(i)));
+			store(output.as_mut_ptr().add(i), sigmoid);
+		}
+		scalar::sigmoid(&values[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn sigmoid_derivative(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let sigmoid = sigmoid_register(load(values.as_ptr().add(i)));
+			store(
+				output.as_mut_ptr().add(i),
+				mul(sigmoid, sub(splat(1.0), sigmoid)),
+			);
+		}
+		scalar::sigmoid_derivative(&values[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn tanh(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let tanh = tanh_register(load(values.as_ptr().add(i)));
+			store(output.as_mut_ptr().add(i), tanh);
+		}
+		scalar::tanh(&values[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn tanh_derivative(values: &[Float], output: &mut [Float]) {
+		let length = values.len() - values.len() % LANES;
+		for i in (0..length).step_by(LANES) {
+			let tanh = tanh_register(load(values.as_ptr().add(i)));
+			store(output.as_mut_ptr().add(i), sub(splat(1.0), mul(tanh, tanh)));
+		}
+		scalar::tanh_derivative(&values[length..], &mut output[length..]);
+	}
+
+	#[target_feature(enable = "avx")]
+	pub unsafe fn l2_update(
+		weights: &mut [Float],
+		changes: &[Float],
+		multiplier: Float,
+		lambda: Float,
+	) {
+		let length = weights.len() - weights.len() % LANES;
+		// signum is 1 with the sign bit of the weight, like Float::signum for non NaN values
+		let sign_bit = splat(-0.0);
+		let one = splat(1.0);
+		for i in (0..length).step_by(LANES) {
+			let weight = load(weights.as_ptr().add(i));
+			let signum = or(and(weight, sign_bit), one);
+			let change = add(load(changes.as_ptr().add(i)), mul(splat(lambda), signum));
+			store(
+				weights.as_mut_ptr().add(i),
+				add(weight, mul(splat(multiplier), change)),
+			);
+		}
+		scalar::l2_update(
+			&mut weights[length..],
+			&changes[length..],
+			multiplier,
+			lambda,
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	type Kernel = fn(&[Float]) -> Vec<Float>;
+	type ScalarKernel = fn(&[Float], &mut [Float]);
+
+	// compares each kernel with the scalar version for lengths that do
+	// and don't fill a whole number of registers
+	#[test]
+	fn kernels_match_scalar() {
+		for length in 0..40 {
+			let a: Vec<Float> = (0..length).map(|i| (i as Float * 0.7).sin()).collect();
+			let mut b: Vec<Float> = (0..length).map(|i| (i as Float * 1.3).cos()).collect();
+			// zeros of both signs for relu and signum
+			if length > 3 {
+				b[1] = 0.0;
+				b[3] = -0.0;
+			}
+
+			let mut expected = vec![0.0; length];
+			scalar::multiply(&a, &b, &mut expected);
+			assert_eq!(multiply(&a, &b), expected);
+
+			scalar::relu(&b, &mut expected);
+			assert_eq!(relu(&b), expected);
+
+			scalar::relu_derivative(&b, &mut expected);
+			assert_eq!(relu_derivative(&b), expected);
+
+			// wide enough to reach the clamped ends of exp
+			let wide: Vec<Float> = b.iter().map(|value| value * 1000.0).collect();
+			// NaN stays NaN rather than becoming the value at a clamped end
+			let nan: Vec<Float> = a
+				.iter()
+				.enumerate()
+				.map(|(i, &value)| if i % 3 == 0 { Float::NAN } else { value })
+				.collect();
+			let kernels: [(Kernel, ScalarKernel); 4] = [
+				(sigmoid, scalar::sigmoid),
+				(sigmoid_derivative, scalar::sigmoid_derivative),
+				(tanh, scalar::tanh),
+				(tanh_derivative, scalar::tanh_derivative),
+			];
+			for (kernel, scalar_kernel) in kernels {
+				for values in [&a, &b, &wide, &nan] {
+					scalar_kernel(values, &mut expected);
+					for (value, expected) in kernel(values).iter().zip(&expected) {
+						assert!(
+							(value - expected).abs() < 1e-6 || value.is_nan() && expected.is_nan(),
+							"{} {}",
+							value,
+							expected
+						);
+					}
+				}
+			}
+
+			let mut sum = a.clone();
+			add_assign(&mut sum, &b);
+			let mut expected = a.clone();
+			scalar::add_assign(&mut expected, &b);
+			assert_eq!(sum, expected);
+
+			let mut weights = b.clone();
+			l2_update(&mut weights, &a, -0.1, 0.01);
+			let mut expected = b.clone();
+			scalar::l2_update(&mut expected, &a, -0.1, 0.01);
+			for (weight, expected) in weights.iter().zip(expected) {
+				assert!((weight - expected).abs() < 1e-6);
+			}
+		}
+	}
+}
@@ -0,0 +1,406 @@
+// sparse input for inputs that are mostly zeros such as bag of words or one hot features
+//
+// a SparseVector stores the index and value of each nonzero element (a row of a CSR matrix),
+// it is only used by the first dense layer which multiplies and accumulates its weight change
+// with the columns for nonzero inputs, every later layer sees the usual dense output
+
+use crate::network::layer::feedforward::FeedForward;
+use crate::network::layer::{Layer, LayerTrait};
+use crate::network::utility::{plus_equals_matrix_multiplied, try_count_correct};
+use crate::network::{Float, Network, NetworkError};
+
+use rand::prelude::SliceRandom;
+use rand::thread_rng;
+
+use std::error::Error;
+use std::fmt;
+
+pub type SparseNetworkData = Vec<(SparseVector, Vec<Float>)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SparseVector {
+	indices: Vec<usize>,
+	length: usize,
+	values: Vec<Float>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SparseError {
+	LengthMismatch { indices: usize, values: usize },
+	IndexOutOfBounds { index: usize, length: usize },
+	// indices must be strictly increasing
+	Unsorted { position: usize },
+}
+
+impl fmt::Display for SparseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SparseError::LengthMismatch { indices, values } => write!(
+				f,
+				"there are {} indices but {} values, there must be a value for each index",
+				indices, values
+			),
+			SparseError::IndexOutOfBounds { index, length } => write!(
+				f,
+				"index {} is out of bounds for a vector of length {}",
+				index, length
+			),
+			SparseError::Unsorted { position } => write!(
+				f,
+				"the index at position {} isn't greater than the index before it",
+				position
+			),
+		}
+	}
+}
+
+impl Error for SparseError {}
+
+impl SparseVector {
+	pub fn new(
+		length: usize,
+		indices: Vec<usize>,
+		values: Vec<Float>,
+	) -> Result<Self, SparseError> {
+		if indices.len() != values.len() {
+			return Err(SparseError::LengthMismatch {
+				indices: indices.len(),
+				values: values.len(),
+			});
+		}
+		if let Some(position) = (1..indices.len()).find(|&i| indices[i] <= indices[i - 1]) {
+			return Err(SparseError::Unsorted { position });
+		}
+		if let Some(&index) = indices.last().filter(|&&index| index >= length) {
+			return Err(SparseError::IndexOutOfBounds { index, length });
+		}
+
+		Ok(SparseVector {
+			indices,
+			length,
+			values,
+		})
+	}
+
+	pub fn from_dense(dense: &[Float]) -> Self {
+		let (indices, values) = dense
+			.iter()
+			.enumerate()
+			.filter(|(_, &value)| value != 0.0)
+			.unzip();
+		SparseVector {
+			indices,
+			length: dense.len(),
+			values,
+		}
+	}
+
+	pub fn to_dense(&self) -> Vec<Float> {
+		let mut dense = vec![0.0; self.length];
+		for (&index, &value) in self.indices.iter().zip(&self.values) {
+			dense[index] = value;
+		}
+		dense
+	}
+
+	pub fn indices(&self) -> &[usize] {
+		&self.indices
+	}
+
+	pub fn values(&self) -> &[Float] {
+		&self.values
+	}
+
+	// the length of the dense vector
+	pub fn len(&self) -> usize {
+		self.length
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.length == 0
+	}
+
+	// the number of stored (nonzero) elements
+	pub fn nonzeros(&self) -> usize {
+		self.indices.len()
+	}
+}
+
+impl Network {
+	// the same as forward for sparse input
+	pub fn forward_sparse(&mut self, input: &SparseVector) -> Result<Vec<Float>, NetworkError> {
+		self.check_sparse_input(input)?;
+
+		self.sparse_layer().forward_sparse(input);
+		for index in 2..self.layers.len() {
+			let layer_inputs = self.layer_inputs(index)?;
+			self.layers[index].forward_many(layer_inputs);
+		}
+		Ok(self.layers[self.layers.len() - 1]
+			.try_last_output()?
+			.into_vec())
+	}
+
+	// the same as evaluate for sparse input
+	pub fn evaluate_sparse(
+		&mut self,
+		test_data: &SparseNetworkData,
+	) -> Result<usize, NetworkError> {
+		try_count_correct(test_data, |input| self.forward_sparse(input))
+	}
+
+	// the same as sgd for sparse input, the first layer after the input layer must be a dense
+	// layer and the only layer that takes input from the input layer
+	pub fn sgd_sparse(
+		&mut self,
+		mut training_data: SparseNetworkData,
+		test_data: Option<SparseNetworkData>,
+		epochs: usize,
+		mini_batch_size: usize,
+		learning_rate: Float,
+	) -> Result<(), NetworkError> {
+		for (input, _) in training_data.iter().chain(test_data.iter().flatten()) {
+			self.check_sparse_input(input)?;
+		}
+		self.set_training(false);
+
+		for i in 0..epochs {
+			training_data.shuffle(&mut thread_rng());
+
+			self.set_training(true);
+			for mini_batch in training_data.chunks(mini_batch_size) {
+				self.accumulate_sparse_changes(mini_batch);
+				self.apply_layer_changes(learning_rate, mini_batch_size);
+			}
+			self.set_training(false);
+
+			match &test_data {
+				Some(test_data) => {
+					let correct = self.evaluate_sparse(test_data)?;
+					let num = test_data.len();
+					let percent_correct = (correct * 100) as Float / num as Float;
+					println!(
+						"Epoch {}: {} / {} ({}%)",
+						i + 1,
+						correct,
+						num,
+						percent_correct
+					);
+				}
+				None => println!("Epoch {} complete.", i + 1),
+			}
+		}
+		Ok(())
+	}
+
+	fn accumulate_sparse_changes(&mut self, mini_batch: &[(SparseVector, Vec<Float>)]) {
+		if self
+			.layers
+			.iter()
+			.any(|layer| layer.uses_batch_statistics())
+		{
+			self.sparse_backpropagation(mini_batch);
+		} else {
+			for data in mini_batch {
+				self.sparse_backpropagation(std::slice::from_ref(data));
+			}
+		}
+	}
+
+	fn sparse_backpropagation(&mut self, mini_batch: &[(SparseVector, Vec<Float>)]) {
+		let layer = self.sparse_layer();
+		let outputs = mini_batch
+			.iter()
+			.map(|(input, _)| {
+				layer.forward_sparse(input);
+				layer.last_output()
+			})
+			.collect();
+
+		// the input layer isn't run, nothing else uses its output
+		let inputs = Vec::new();
+		let expected: Vec<&[Float]> = mini_batch.iter().map(|data| &data.1[..]).collect();
+		let (_, gradients) = self.backpropagate_from(vec![inputs, outputs], &expected);
+
+		let layer = self.sparse_layer();
+		for ((input, _), gradient) in mini_batch.iter().zip(gradients) {
+			// the layer only keeps the values from its last forward pass
+			if mini_batch.len() > 1 {
+				layer.forward_sparse(input);
+			}
+			layer.backward_sparse(input, gradient.as_slice());
+		}
+	}
+
+	fn check_sparse_input(&self, input: &SparseVector) -> Result<(), NetworkError> {
+		let only_input_user =
+			(2..self.layers.len()).all(|index| !self.connections[index].contains(&0));
+		match self.layers.get(1) {
+			Some(Layer::FeedForward(_)) if only_input_user => {}
+			_ => return Err(NetworkError::SparseInputUnsupported),
+		}
+
+		let expected = self.layers[0].info().output();
+		if input.len() != expected.iter().product::<usize>() {
+			return Err(NetworkError::InputShape {
+				expected,
+				found: vec![input.len()],
+			});
+		}
+		Ok(())
+	}
+
+	fn sparse_layer(&mut self) -> &mut FeedForward {
+		match &mut self.layers[1] {
+			Layer::FeedForward(layer) => layer,
+			_ => unreachable!("checked by check_sparse_input"),
+		}
+	}
+}
+
+// performs c += a * b where a is column major with dimensions dim,
+// only the columns of a for the nonzero elements of b are used
+pub fn sparse_matrix_vec_multiply_add(
+	a: &[Float],
+	b: &SparseVector,
+	c: &mut [Float],
+	dim: &[usize; 2],
+) {
+	assert_eq!(a.len(), dim[0] * dim[1]);
+	assert_eq!(b.len(), dim[1]);
+	assert_eq!(c.len(), dim[0]);
+	for (&index, &value) in b.indices.iter().zip(&b.values) {
+		plus_equals_matrix_multiplied(c, value, &a[index * dim[0]..(index + 1) * dim[0]]);
+	}
+}
+
+// performs c += a * b^T where c is column major with dimensions [a.len(), b.len()],
+// only the columns of c for the nonzero elements of b change
+pub fn sparse_outer_product_add(a: &[Float], b: &SparseVector, c: &mut [Float]) {
+	let rows = a.len();
+	assert_eq!(c.len(), rows * b.len());
+	for (&index, &value) in b.indices.iter().zip(&b.values) {
+		plus_equals_matrix_multiplied(&mut c[index * rows..(index + 1) * rows], value, a);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::network::fixtures::{fix_parameters, labelled_data, parameters};
+	use crate::network::utility::{matrix_vec_multiply_add, outer_product_add};
+	use crate::network::ActivationFunction;
+
+	// inputs with about 1 in 10 elements nonzero
+	fn data(length: usize) -> SparseNetworkData {
+		let value = |i: usize, j: usize| match (i * 7 + j * 3) % 10 {
+			0 => ((i + j) as Float * 0.37).sin(),
+			_ => 0.0,
+		};
+		labelled_data(length, 40, 20, value)
+			.into_iter()
+			.map(|(input, expected)| (SparseVector::from_dense(&input), expected))
+			.collect()
+	}
+
+	fn network() -> Network {
+		let mut network = Network::builder()
+			.input([40, 1, 1])
+			.dense(8)
+			.activation(ActivationFunction::Tanh)
+			.dense(4)
+			.activation(ActivationFunction::Tanh)
+			.output(2)
+			.activation(ActivationFunction::Sigmoid)
+			.build()
+			.unwrap();
+		fix_parameters(&mut network);
+		network
+	}
+
+	#[test]
+	fn sparse_products() {
+		let a: Vec<Float> = (0..12).map(|i| i as Float).collect();
+		let b = SparseVector::new(4, vec![1, 3], vec![2.0, -1.0]).unwrap();
+		let mut dense = vec![1.0; 3];
+		let mut sparse = dense.clone();
+		matrix_vec_multiply_add(&a, &b.to_dense(), &mut dense, &[3, 4]);
+		sparse_matrix_vec_multiply_add(&a, &b, &mut sparse, &[3, 4]);
+		assert_eq!(dense, sparse);
+
+		let mut dense = a.clone();
+		let mut sparse = a;
+		outer_product_add(&[1.0, 2.0, 3.0], &b.to_dense(), &mut dense);
+		sparse_outer_product_add(&[1.0, 2.0, 3.0], &b, &mut sparse);
+		assert_eq!(dense, sparse);
+
+		assert_eq!(
+			SparseVector::new(4, vec![1, 1], vec![2.0, -1.0]),
+			Err(SparseError::Unsorted { position: 1 })
+		);
+		assert_eq!(
+			SparseVector::new(4, vec![1, 4], vec![2.0, -1.0]),
+			Err(SparseError::IndexOutOfBounds {
+				index: 4,
+				length: 4
+			})
+		);
+	}
+
+	#[test]
+	fn matches_dense() {
+		let sparse_data = data(10);
+		let dense_data: Vec<(Vec<Float>, Vec<Float>)> = sparse_data
+			.iter()
+			.map(|(input, expected)| (input.to_dense(), expected.clone()))
+			.collect();
+
+		let mut dense = network();
+		let mut sparse = network();
+		for ((sparse_input, _), (dense_input, _)) in sparse_data.iter().zip(&dense_data) {
+			let output = sparse.forward_sparse(sparse_input).unwrap();
+			for (sparse, dense) in output.iter().zip(dense.forward(dense_input.clone())) {
+				assert!((sparse - dense).abs() < 1e-5);
+			}
+		}
+
+		dense.accumulate_changes(&dense_data);
+		dense.apply_layer_changes(0.5, dense_data.len());
+		sparse.accumulate_sparse_changes(&sparse_data);
+		sparse.apply_layer_changes(0.5, sparse_data.len());
+		for (dense, sparse) in parameters(&mut dense).iter().zip(parameters(&mut sparse)) {
+			assert!((dense - sparse).abs() < 1e-5);
+		}
+
+		sparse.sgd_sparse(data(200), None, 10, 10, 0.5).unwrap();
+		assert!(sparse.evaluate_sparse(&data(100)).unwrap() > 80);
+	}
+
+	#[test]
+	fn unsupported_networks() {
+		let input = SparseVector::from_dense(&[0.0, 1.0, 0.0]);
+		let mut network = Network::builder()
+			.input([3, 1, 1])
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(
+			network.forward_sparse(&input),
+			Err(NetworkError::SparseInputUnsupported)
+		);
+
+		let mut network = Network::builder()
+			.input([4, 1, 1])
+			.dense(3)
+			.output(2)
+			.build()
+			.unwrap();
+		assert_eq!(
+			network.forward_sparse(&input),
+			Err(NetworkError::InputShape {
+				expected: [4, 1, 1],
+				found: vec![3]
+			})
+		);
+	}
+}
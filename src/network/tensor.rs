@@ -0,0 +1,501 @@
+use crate::network::simd;
+use crate::network::utility::general_matrix_multiply;
+use crate::network::Float;
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+// the order elements of a tensor are stored in, layers use column major
+// where the first index changes fastest so [w, h, c] is stored at x + w * (y + h * c)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Layout {
+	ColumnMajor,
+	RowMajor,
+}
+
+impl Layout {
+	// the distance between consecutive elements along each axis
+	pub fn strides(&self, shape: &[usize]) -> Vec<usize> {
+		let mut strides = vec![0; shape.len()];
+		let mut stride = 1;
+		let mut set = |axis: usize| {
+			strides[axis] = stride;
+			stride *= shape[axis];
+		};
+		match self {
+			Layout::ColumnMajor => (0..shape.len()).for_each(&mut set),
+			Layout::RowMajor => (0..shape.len()).rev().for_each(&mut set),
+		}
+		strides
+	}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeError {
+	LengthMismatch {
+		shape: Vec<usize>,
+		length: usize,
+	},
+	IncompatibleShapes {
+		operation: &'static str,
+		left: Vec<usize>,
+		right: Vec<usize>,
+	},
+	IndexOutOfBounds {
+		index: Vec<usize>,
+		shape: Vec<usize>,
+	},
+	InvalidAxis {
+		axis: usize,
+		rank: usize,
+	},
+}
+
+impl fmt::Display for ShapeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ShapeError::LengthMismatch { shape, length } => write!(
+				f,
+				"a tensor of shape {:?} needs {} elements but {} were given",
+				shape,
+				shape.iter().product::<usize>(),
+				length
+			),
+			ShapeError::IncompatibleShapes {
+				operation,
+				left,
+				right,
+			} => write!(
+				f,
+				"can't {} tensors of shape {:?} and {:?}",
+				operation, left, right
+			),
+			ShapeError::IndexOutOfBounds { index, shape } => write!(
+				f,
+				"index {:?} is out of bounds for a tensor of shape {:?}",
+				index, shape
+			),
+			ShapeError::InvalidAxis { axis, rank } => write!(
+				f,
+				"axis {} doesn't exist in a tensor with {} axes",
+				axis, rank
+			),
+		}
+	}
+}
+
+impl Error for ShapeError {}
+
+// an owned n dimensional array of Floats stored contiguously in the given layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tensor {
+	data: Vec<Float>,
+	layout: Layout,
+	shape: Vec<usize>,
+}
+
+// a borrowed tensor that can have any strides e.g. after transposing or slicing
+#[derive(Clone, Debug)]
+pub struct TensorView<'a> {
+	data: &'a [Float],
+	offset: usize,
+	shape: Vec<usize>,
+	strides: Vec<usize>,
+}
+
+impl Tensor {
+	// creates a column major tensor, the layout used by layers
+	pub fn new(data: Vec<Float>, shape: &[usize]) -> Result<Self, ShapeError> {
+		Tensor::with_layout(data, shape, Layout::ColumnMajor)
+	}
+
+	pub fn with_layout(
+		data: Vec<Float>,
+		shape: &[usize],
+		layout: Layout,
+	) -> Result<Self, ShapeError> {
+		if shape.iter().product::<usize>() != data.len() {
+			return Err(ShapeError::LengthMismatch {
+				shape: shape.to_vec(),
+				length: data.len(),
+			});
+		}
+		Ok(Tensor {
+			data,
+			layout,
+			shape: shape.to_vec(),
+		})
+	}
+
+	pub fn zeros(shape: &[usize]) -> Self {
+		Tensor {
+			data: vec![0.0; shape.iter().product()],
+			layout: Layout::ColumnMajor,
+			shape: shape.to_vec(),
+		}
+	}
+
+	pub fn shape(&self) -> &[usize] {
+		&self.shape
+	}
+
+	pub fn strides(&self) -> Vec<usize> {
+		self.layout.strides(&self.shape)
+	}
+
+	pub fn layout(&self) -> Layout {
+		self.layout
+	}
+
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	// the elements in storage order
+	pub fn as_slice(&self) -> &[Float] {
+		&self.data
+	}
+
+	pub fn as_mut_slice(&mut self) -> &mut [Float] {
+		&mut self.data
+	}
+
+	pub fn into_vec(self) -> Vec<Float> {
+		self.data
+	}
+
+	pub fn get(&self, index: &[usize]) -> Result<Float, ShapeError> {
+		self.view().get(index)
+	}
+
+	pub fn view(&self) -> TensorView<'_> {
+		TensorView {
+			data: &self.data,
+			offset: 0,
+			shape: self.shape.clone(),
+			strides: self.strides(),
+		}
+	}
+
+	// the same elements in the other layout
+	pub fn to_layout(&self, layout: Layout) -> Tensor {
+		match layout == self.layout {
+			true => self.clone(),
+			false => self.view().to_tensor(layout),
+		}
+	}
+
+	// the same as to_layout without copying the data when the layout already matches
+	pub fn into_layout(self, layout: Layout) -> Tensor {
+		match layout == self.layout {
+			true => self,
+			false => self.view().to_tensor(layout),
+		}
+	}
+
+	// keeps the storage order so a column major [w, h, c] tensor reshaped
+	// to [w * h * c] is the flattened input a dense layer expects
+	pub fn reshape(self, shape: &[usize]) -> Result<Tensor, ShapeError> {
+		Tensor::with_layout(self.data, shape, self.layout)
+	}
+
+	pub fn add(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+		let other = self.matching(other, "add")?;
+		let mut data = self.data.clone();
+		simd::add_assign(&mut data, &other.data);
+		Ok(Tensor {
+			data,
+			layout: self.layout,
+			shape: self.shape.clone(),
+		})
+	}
+
+	// the elementwise (hadamard) product
+	pub fn multiply(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+		let other = self.matching(other, "multiply")?;
+		Ok(Tensor {
+			data: simd::multiply(&self.data, &other.data),
+			layout: self.layout,
+			shape: self.shape.clone(),
+		})
+	}
+
+	pub fn scale(&self, multiplier: Float) -> Tensor {
+		self.map(|value| value * multiplier)
+	}
+
+	// applies function to every element
+	pub fn map(&self, function: impl Fn(Float) -> Float) -> Tensor {
+		Tensor {
+			data: self.data.iter().map(|&value| function(value)).collect(),
+			layout: self.layout,
+			shape: self.shape.clone(),
+		}
+	}
+
+	// the matrix product of two [rows, columns] tensors, the result is column major
+	pub fn matmul(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+		if self.shape.len() != 2 || other.shape.len() != 2 || self.shape[1] != other.shape[0] {
+			return Err(ShapeError::IncompatibleShapes {
+				operation: "matrix multiply",
+				left: self.shape.clone(),
+				right: other.shape.clone(),
+			});
+		}
+		let [m, k, n] = [self.shape[0], self.shape[1], other.shape[1]];
+		// a row major matrix is the transpose of the column major matrix with the same data
+		let transpose = [
+			self.layout == Layout::RowMajor,
+			other.layout == Layout::RowMajor,
+		];
+
+		let mut data = vec![0.0; m * n];
+		general_matrix_multiply(
+			transpose,
+			[m, n, k],
+			1.0,
+			&self.data,
+			&other.data,
+			0.0,
+			&mut data,
+		);
+		Tensor::new(data, &[m, n])
+	}
+
+	// other in the layout of self if the shapes are the same
+	fn matching<'a>(
+		&self,
+		other: &'a Tensor,
+		operation: &'static str,
+	) -> Result<Cow<'a, Tensor>, ShapeError> {
+		if self.shape != other.shape {
+			return Err(ShapeError::IncompatibleShapes {
+				operation,
+				left: self.shape.clone(),
+				right: other.shape.clone(),
+			});
+		}
+		Ok(match self.layout == other.layout {
+			true => Cow::Borrowed(other),
+			false => Cow::Owned(other.to_layout(self.layout)),
+		})
+	}
+}
+
+impl From<Tensor> for Vec<Float> {
+	fn from(tensor: Tensor) -> Self {
+		tensor.data
+	}
+}
+
+impl<'a> TensorView<'a> {
+	// a column major view of a slice
+	pub fn new(data: &'a [Float], shape: &[usize]) -> Result<Self, ShapeError> {
+		if shape.iter().product::<usize>() != data.len() {
+			return Err(ShapeError::LengthMismatch {
+				shape: shape.to_vec(),
+				length: data.len(),
+			});
+		}
+		Ok(TensorView {
+			data,
+			offset: 0,
+			shape: shape.to_vec(),
+			strides: Layout::ColumnMajor.strides(shape),
+		})
+	}
+
+	pub fn shape(&self) -> &[usize] {
+		&self.shape
+	}
+
+	pub fn strides(&self) -> &[usize] {
+		&self.strides
+	}
+
+	pub fn len(&self) -> usize {
+		self.shape.iter().product()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn get(&self, index: &[usize]) -> Result<Float, ShapeError> {
+		if index.len() != self.shape.len()
+			|| index
+				.iter()
+				.zip(&self.shape)
+				.any(|(index, size)| index >= size)
+		{
+			return Err(ShapeError::IndexOutOfBounds {
+				index: index.to_vec(),
+				shape: self.shape.clone(),
+			});
+		}
+		Ok(self.data[self.position(index)])
+	}
+
+	// reverses the order of the axes without copying
+	pub fn transpose(&self) -> TensorView<'a> {
+		TensorView {
+			data: self.data,
+			offset: self.offset,
+			shape: self.shape.iter().rev().copied().collect(),
+			strides: self.strides.iter().rev().copied().collect(),
+		}
+	}
+
+	// the elements with an index in range along axis
+	pub fn slice(&self, axis: usize, range: Range<usize>) -> Result<TensorView<'a>, ShapeError> {
+		if axis >= self.shape.len() {
+			return Err(ShapeError::InvalidAxis {
+				axis,
+				rank: self.shape.len(),
+			});
+		}
+		if range.start > range.end || range.end > self.shape[axis] {
+			let mut index = vec![0; self.shape.len()];
+			index[axis] = range.end;
+			return Err(ShapeError::IndexOutOfBounds {
+				index,
+				shape: self.shape.clone(),
+			});
+		}
+		let mut shape = self.shape.clone();
+		shape[axis] = range.len();
+		Ok(TensorView {
+			data: self.data,
+			offset: self.offset + range.start * self.strides[axis],
+			shape,
+			strides: self.strides.clone(),
+		})
+	}
+
+	// the elements in column major order
+	pub fn iter(&self) -> impl Iterator<Item = Float> + '_ {
+		let mut index = vec![0; self.shape.len()];
+		(0..self.len()).map(move |i| {
+			if i > 0 {
+				// increments the index with the first axis changing fastest
+				for (value, size) in index.iter_mut().zip(&self.shape) {
+					*value += 1;
+					if *value < *size {
+						break;
+					}
+					*value = 0;
+				}
+			}
+			self.data[self.position(&index)]
+		})
+	}
+
+	// copies the elements into a contiguous tensor
+	pub fn to_tensor(&self, layout: Layout) -> Tensor {
+		let tensor = Tensor {
+			data: self.iter().collect(),
+			layout: Layout::ColumnMajor,
+			shape: self.shape.clone(),
+		};
+		match layout {
+			Layout::ColumnMajor => tensor,
+			// a row major tensor stores the elements of the transpose in column major order
+			Layout::RowMajor => Tensor {
+				data: tensor.view().transpose().iter().collect(),
+				layout,
+				shape: self.shape.clone(),
+			},
+		}
+	}
+
+	fn position(&self, index: &[usize]) -> usize {
+		self.offset
+			+ index
+				.iter()
+				.zip(&self.strides)
+				.map(|(index, stride)| index * stride)
+				.sum::<usize>()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn range(shape: &[usize]) -> Tensor {
+		let length = shape.iter().product::<usize>();
+		Tensor::new((0..length).map(|i| i as Float).collect(), shape).unwrap()
+	}
+
+	#[test]
+	fn layouts_and_views() {
+		// [w, h, c] = [2, 3, 2] with x + w * (y + h * c)
+		let tensor = range(&[2, 3, 2]);
+		assert_eq!(tensor.strides(), vec![1, 2, 6]);
+		assert_eq!(tensor.get(&[1, 2, 1]), Ok(11.0));
+		assert!(tensor.get(&[2, 0, 0]).is_err());
+
+		let row_major = tensor.to_layout(Layout::RowMajor);
+		assert_eq!(row_major.strides(), vec![6, 2, 1]);
+		assert_eq!(row_major.get(&[1, 2, 1]), Ok(11.0));
+		assert_eq!(row_major.to_layout(Layout::ColumnMajor), tensor);
+
+		let transposed = tensor.view().transpose();
+		assert_eq!(transposed.shape(), &[2, 3, 2]);
+		assert_eq!(transposed.get(&[1, 2, 0]), tensor.get(&[0, 2, 1]));
+
+		// the second channel
+		let channel = tensor.view().slice(2, 1..2).unwrap();
+		assert_eq!(
+			channel.iter().collect::<Vec<Float>>(),
+			vec![6.0, 7.0, 8.0, 9.0, 10.0, 11.0]
+		);
+		let column = channel.slice(0, 1..2).unwrap();
+		assert_eq!(
+			column.to_tensor(Layout::ColumnMajor).into_vec(),
+			vec![7.0, 9.0, 11.0]
+		);
+		assert_eq!(
+			tensor.view().slice(3, 0..1).unwrap_err(),
+			ShapeError::InvalidAxis { axis: 3, rank: 3 }
+		);
+	}
+
+	#[test]
+	fn operations() {
+		let a = range(&[2, 3]);
+		let b = range(&[3, 2]);
+
+		// [[0, 2, 4], [1, 3, 5]] * [[0, 3], [1, 4], [2, 5]]
+		let product = a.matmul(&b).unwrap();
+		assert_eq!(product.shape(), &[2, 2]);
+		assert_eq!(product.as_slice(), &[10.0, 13.0, 28.0, 40.0]);
+		assert_eq!(
+			a.to_layout(Layout::RowMajor)
+				.matmul(&b.to_layout(Layout::RowMajor))
+				.unwrap(),
+			product
+		);
+		assert!(a.matmul(&a).is_err());
+
+		let sum = a.add(&a.to_layout(Layout::RowMajor)).unwrap();
+		assert_eq!(sum, a.scale(2.0));
+		assert_eq!(
+			a.multiply(&b),
+			Err(ShapeError::IncompatibleShapes {
+				operation: "multiply",
+				left: vec![2, 3],
+				right: vec![3, 2],
+			})
+		);
+
+		assert!(Tensor::new(vec![0.0; 5], &[2, 3]).is_err());
+		assert_eq!(a.reshape(&[6]).unwrap().get(&[4]), Ok(4.0));
+	}
+}
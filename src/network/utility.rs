@@ -1,14 +1,221 @@
-use blas::{saxpy, sgemm, sgemv, sger, sscal};
+use crate::network::backend::{self, Number};
+use crate::network::simd;
+use crate::network::NetworkError;
 
+use std::convert::Infallible;
+
+#[cfg(feature = "openblas")]
+use blas::{daxpy, dgemm, dgemv, dger, dscal, saxpy, sgemm, sgemv, sger, sscal};
+
+// the scalar type used by networks
 pub type Float = f32;
 
+// floating point types with BLAS style routines, the functions below work with any of them
+// so f64 can be used alongside networks (e.g. checking f32 results against f64)
+//
+// the routines default to the pure rust versions in backend,
+// with the openblas feature they are replaced with calls to OpenBLAS
+//
+// the routines are unsafe like the BLAS ones they call, the slices must be long enough for the
+// sizes, strides and leading dimensions passed, the functions below check this before calling them
+#[allow(clippy::too_many_arguments, clippy::missing_safety_doc)]
+pub trait Scalar: Number {
+	const ONE: Self;
+
+	unsafe fn axpy(n: i32, alpha: Self, x: &[Self], incx: i32, y: &mut [Self], incy: i32) {
+		backend::axpy(n as usize, alpha, x, incx as usize, y, incy as usize)
+	}
+	unsafe fn gemm(
+		transa: u8,
+		transb: u8,
+		m: i32,
+		n: i32,
+		k: i32,
+		alpha: Self,
+		a: &[Self],
+		lda: i32,
+		b: &[Self],
+		ldb: i32,
+		beta: Self,
+		c: &mut [Self],
+		ldc: i32,
+	) {
+		backend::gemm(
+			transa,
+			transb,
+			m as usize,
+			n as usize,
+			k as usize,
+			alpha,
+			a,
+			lda as usize,
+			b,
+			ldb as usize,
+			beta,
+			c,
+			ldc as usize,
+		)
+	}
+	unsafe fn gemv(
+		trans: u8,
+		m: i32,
+		n: i32,
+		alpha: Self,
+		a: &[Self],
+		lda: i32,
+		x: &[Self],
+		incx: i32,
+		beta: Self,
+		y: &mut [Self],
+		incy: i32,
+	) {
+		backend::gemv(
+			trans,
+			m as usize,
+			n as usize,
+			alpha,
+			a,
+			lda as usize,
+			x,
+			incx as usize,
+			beta,
+			y,
+			incy as usize,
+		)
+	}
+	unsafe fn ger(
+		m: i32,
+		n: i32,
+		alpha: Self,
+		x: &[Self],
+		incx: i32,
+		y: &[Self],
+		incy: i32,
+		a: &mut [Self],
+		lda: i32,
+	) {
+		backend::ger(
+			m as usize,
+			n as usize,
+			alpha,
+			x,
+			incx as usize,
+			y,
+			incy as usize,
+			a,
+			lda as usize,
+		)
+	}
+	unsafe fn scal(n: i32, alpha: Self, x: &mut [Self], incx: i32) {
+		backend::scal(n as usize, alpha, x, incx as usize)
+	}
+}
+
+#[cfg(not(feature = "openblas"))]
+impl Scalar for f32 {
+	const ONE: Self = 1.0;
+}
+
+#[cfg(not(feature = "openblas"))]
+impl Scalar for f64 {
+	const ONE: Self = 1.0;
+}
+
+// implements Scalar by forwarding to the BLAS routines with the given prefix
+#[cfg(feature = "openblas")]
+macro_rules! scalar {
+	($type:ty, $axpy:ident, $gemm:ident, $gemv:ident, $ger:ident, $scal:ident) => {
+		impl Scalar for $type {
+			const ONE: Self = 1.0;
+
+			unsafe fn axpy(n: i32, alpha: Self, x: &[Self], incx: i32, y: &mut [Self], incy: i32) {
+				$axpy(n, alpha, x, incx, y, incy)
+			}
+			unsafe fn gemm(
+				transa: u8,
+				transb: u8,
+				m: i32,
+				n: i32,
+				k: i32,
+				alpha: Self,
+				a: &[Self],
+				lda: i32,
+				b: &[Self],
+				ldb: i32,
+				beta: Self,
+				c: &mut [Self],
+				ldc: i32,
+			) {
+				$gemm(transa, transb, m, n, k, alpha, a, lda, b, ldb, beta, c, ldc)
+			}
+			unsafe fn gemv(
+				trans: u8,
+				m: i32,
+				n: i32,
+				alpha: Self,
+				a: &[Self],
+				lda: i32,
+				x: &[Self],
+				incx: i32,
+				beta: Self,
+				y: &mut [Self],
+				incy: i32,
+			) {
+				$gemv(trans, m, n, alpha, a, lda, x, incx, beta, y, incy)
+			}
+			unsafe fn ger(
+				m: i32,
+				n: i32,
+				alpha: Self,
+				x: &[Self],
+				incx: i32,
+				y: &[Self],
+				incy: i32,
+				a: &mut [Self],
+				lda: i32,
+			) {
+				$ger(m, n, alpha, x, incx, y, incy, a, lda)
+			}
+			unsafe fn scal(n: i32, alpha: Self, x: &mut [Self], incx: i32) {
+				$scal(n, alpha, x, incx)
+			}
+		}
+	};
+}
+
+#[cfg(feature = "openblas")]
+scalar!(f32, saxpy, sgemm, sgemv, sger, sscal);
+#[cfg(feature = "openblas")]
+scalar!(f64, daxpy, dgemm, dgemv, dger, dscal);
+
 /*
 Find BLAS function definitions here:
 http://www.netlib.org/blas/
 */
 
 pub fn hadamard_product(a: &[Float], b: &[Float]) -> Vec<Float> {
-	a.iter().zip(b).map(|(&a, b)| a * b).collect()
+	simd::multiply(a, b)
+}
+
+// flattens a sequence of vectors (one per timestep) into the [seq_len, features, 1] layout
+// used by recurrent layers where timesteps are next to each other,
+// every timestep must have the same number of features
+pub fn flatten_sequence(sequence: &[Vec<Float>]) -> Result<Vec<Float>, NetworkError> {
+	let expected = sequence.first().map_or(0, |step| step.len());
+	if let Some((timestep, step)) = sequence
+		.iter()
+		.enumerate()
+		.find(|(_, step)| step.len() != expected)
+	{
+		return Err(NetworkError::RaggedSequence {
+			timestep,
+			features: step.len(),
+			expected,
+		});
+	}
+	Ok((0..expected)
+		.flat_map(|d| sequence.iter().map(move |step| step[d]))
+		.collect())
 }
 
 /// max() over a slice of floats, gets the index of a largest value
@@ -24,22 +231,51 @@ pub fn max_index(nets: &[Float]) -> usize {
 	index
 }
 
+// the number of samples where the largest output is at the index of the largest expected value,
+// used by evaluate for every kind of network
+pub(crate) fn count_correct<I>(
+	test_data: &[(I, Vec<Float>)],
+	mut forward: impl FnMut(&I) -> Vec<Float>,
+) -> usize {
+	match try_count_correct(test_data, |input| Ok::<_, Infallible>(forward(input))) {
+		Ok(correct) => correct,
+		Err(never) => match never {},
+	}
+}
+
+// the same as count_correct for a forward pass that can fail
+pub(crate) fn try_count_correct<I, E>(
+	test_data: &[(I, Vec<Float>)],
+	mut forward: impl FnMut(&I) -> Result<Vec<Float>, E>,
+) -> Result<usize, E> {
+	let mut correct = 0;
+	for (input, expected) in test_data {
+		if max_index(&forward(input)?) == max_index(expected) {
+			correct += 1;
+		}
+	}
+	Ok(correct)
+}
+
 const NORMAL: u8 = b'N';
 const TRANSPOSE: u8 = b'T';
 
 // performs c += a * b
-pub fn matrix_vec_multiply_add(a: &[Float], b: &[Float], c: &mut [Float], dim: &[usize; 2]) {
+pub fn matrix_vec_multiply_add<T: Scalar>(a: &[T], b: &[T], c: &mut [T], dim: &[usize; 2]) {
+	check_matrix(a, *dim);
+	check_vector("b", b, dim[1]);
+	check_vector("c", c, dim[0]);
 	unsafe {
-		sgemv(
+		T::gemv(
 			NORMAL,
 			dim[0] as i32,
 			dim[1] as i32,
-			1.0,
+			T::ONE,
 			a,
 			dim[0] as i32,
 			b,
 			1,
-			1.0,
+			T::ONE,
 			c,
 			1,
 		);
@@ -47,12 +283,13 @@ pub fn matrix_vec_multiply_add(a: &[Float], b: &[Float], c: &mut [Float], dim: &
 }
 
 // performs c += a * b^T
-pub fn outer_product_add(a: &[Float], b: &[Float], c: &mut [Float]) {
+pub fn outer_product_add<T: Scalar>(a: &[T], b: &[T], c: &mut [T]) {
+	check_matrix(c, [a.len(), b.len()]);
 	unsafe {
-		sger(
+		T::ger(
 			a.len() as i32,
 			b.len() as i32,
-			1.0,
+			T::ONE,
 			a,
 			1,
 			b,
@@ -64,14 +301,14 @@ pub fn outer_product_add(a: &[Float], b: &[Float], c: &mut [Float]) {
 }
 
 // performs base_matrix += multiplier * matrix
-pub fn plus_equals_matrix_multiplied(
-	base_matrix: &mut [Float],
-	multiplier: Float,
-	matrix: &[Float],
+pub fn plus_equals_matrix_multiplied<T: Scalar>(
+	base_matrix: &mut [T],
+	multiplier: T,
+	matrix: &[T],
 ) {
 	assert_eq!(base_matrix.len(), matrix.len());
 	unsafe {
-		saxpy(
+		T::axpy(
 			base_matrix.len() as i32,
 			multiplier,
 			matrix,
@@ -83,61 +320,125 @@ pub fn plus_equals_matrix_multiplied(
 }
 
 // performs result = matrix^T * vector
-pub fn transpose_matrix_multiply_vec(
-	matrix: &[Float],
-	vector: &[Float],
+pub fn transpose_matrix_multiply_vec<T: Scalar>(
+	matrix: &[T],
+	vector: &[T],
 	dim: [usize; 2],
-	result: &mut Vec<Float>,
+	result: &mut Vec<T>,
 ) {
-	result.reserve_exact(dim[1]);
+	check_matrix(matrix, dim);
+	check_vector("vector", vector, dim[0]);
+	result.clear();
+	result.resize(dim[1], T::ZERO);
 	unsafe {
-		sgemv(
+		T::gemv(
 			TRANSPOSE,
 			dim[0] as i32,
 			dim[1] as i32,
-			1.0,
+			T::ONE,
 			matrix,
 			dim[0] as i32,
 			vector,
 			1,
-			0.0,
+			T::ZERO,
 			result,
 			1,
 		);
-		result.set_len(dim[1]);
 	}
 }
 
 // performs C = A*B + C
-pub fn matrix_multiply_sum(
-	mat_a: &[Float],
-	mat_b: &[Float],
-	dim: [usize; 2],
-	result: &mut [Float],
-) {
+pub fn matrix_multiply_sum<T: Scalar>(mat_a: &[T], mat_b: &[T], dim: [usize; 2], result: &mut [T]) {
+	check_matrix(mat_a, dim);
+	check_vector("mat_b", mat_b, dim[1]);
+	check_vector("result", result, dim[0]);
 	unsafe {
-		sgemm(
+		T::gemm(
 			NORMAL,
 			NORMAL,
 			dim[0] as i32,
 			1,
 			dim[1] as i32,
-			1.0,
+			T::ONE,
 			mat_a,
 			dim[0] as i32,
 			mat_b,
 			dim[1] as i32,
-			1.0,
+			T::ONE,
 			result,
 			dim[1] as i32,
 		);
 	}
 }
 
+// performs c = alpha * op(a) * op(b) + beta * c where op(a) is [m, k], op(b) is [k, n] and c is [m, n],
+// op transposes a matrix if its transpose flag is set
+pub fn general_matrix_multiply<T: Scalar>(
+	transpose: [bool; 2],
+	dim: [usize; 3],
+	alpha: T,
+	a: &[T],
+	b: &[T],
+	beta: T,
+	c: &mut [T],
+) {
+	let [m, n, k] = dim;
+	let (transpose_a, lda) = match transpose[0] {
+		true => (TRANSPOSE, k),
+		false => (NORMAL, m),
+	};
+	let (transpose_b, ldb) = match transpose[1] {
+		true => (TRANSPOSE, n),
+		false => (NORMAL, k),
+	};
+	assert!(a.len() >= m * k && b.len() >= k * n && c.len() >= m * n);
+	unsafe {
+		T::gemm(
+			transpose_a,
+			transpose_b,
+			m as i32,
+			n as i32,
+			k as i32,
+			alpha,
+			a,
+			lda.max(1) as i32,
+			b,
+			ldb.max(1) as i32,
+			beta,
+			c,
+			m.max(1) as i32,
+		);
+	}
+}
+
+// shapes are checked before calling BLAS which would otherwise read out of bounds
+// or fail with an error like "On entry to SGER parameter number 9 had an illegal value"
+fn check_matrix<T>(matrix: &[T], dim: [usize; 2]) {
+	assert_eq!(
+		matrix.len(),
+		dim[0] * dim[1],
+		"a {:?} matrix needs {} elements but has {}",
+		dim,
+		dim[0] * dim[1],
+		matrix.len()
+	);
+}
+
+fn check_vector<T>(name: &str, vector: &[T], length: usize) {
+	assert_eq!(
+		vector.len(),
+		length,
+		"{} has length {} but the matrix needs {}",
+		name,
+		vector.len(),
+		length
+	);
+}
+
 // performs a *= multiplier;
-pub fn scale_elements(a: &mut [Float], multiplier: Float) {
+pub fn scale_elements<T: Scalar>(a: &mut [T], multiplier: T) {
 	unsafe {
-		sscal(a.len() as i32, multiplier, a, 1);
+		T::scal(a.len() as i32, multiplier, a, 1);
 	}
 }
 
@@ -148,7 +449,7 @@ mod tests {
 	#[test]
 	fn gemv() {
 		// note column major
-		let a = [3.2, 5.3, 0.0, 1.2, -0.2, -1.1];
+		let a: [Float; 6] = [3.2, 5.3, 0.0, 1.2, -0.2, -1.1];
 
 		let dim = [3, 2];
 
@@ -170,7 +471,7 @@ mod tests {
 
 	#[test]
 	fn saxpy() {
-		let mut a = [3.2, -0.2, 1.2, 4.5];
+		let mut a: [f32; 4] = [3.2, -0.2, 1.2, 4.5];
 		let multiplier = -0.5;
 		let b = [4.0, 1.2, -6.0, -5.0];
 
@@ -181,7 +482,7 @@ mod tests {
 
 	#[test]
 	fn outer_product() {
-		let a = [1.0, 2.0, 3.0];
+		let a: [Float; 3] = [1.0, 2.0, 3.0];
 		let b = [4.0, 5.0];
 		let mut c = [0.2, -1.0, -0.5, 4.3, 5.0, 0.7];
 
@@ -190,9 +491,36 @@ mod tests {
 		assert_eq!(c, [4.2, 7.0, 11.5, 9.3, 15.0, 15.7]);
 	}
 
+	#[test]
+	fn gemm_transpose() {
+		// note column major, a is [2, 3] and b is [2, 3] so b^T is [3, 2]
+		let a: [Float; 6] = [1.0, 4.0, 2.0, 5.0, 3.0, 6.0];
+		let b = [1.0, 0.0, -1.0, 2.0, 1.0, 0.5];
+		let mut c = [1.0; 4];
+
+		general_matrix_multiply([false, true], [2, 2, 3], 2.0, &a, &b, 1.0, &mut c);
+
+		assert_eq!(c, [5.0, 11.0, 12.0, 27.0]);
+	}
+
+	#[test]
+	fn double_precision() {
+		let a: [f64; 6] = [1.0, 4.0, 2.0, 5.0, 3.0, 6.0];
+		let b = [1.0, 0.0, -1.0, 2.0, 1.0, 0.5];
+		let mut c = [1.0; 4];
+		general_matrix_multiply([false, true], [2, 2, 3], 2.0, &a, &b, 1.0, &mut c);
+		assert_eq!(c, [5.0, 11.0, 12.0, 27.0]);
+
+		// 1 + 1e-10 can't be represented as an f32
+		let mut a = [1.0_f64];
+		plus_equals_matrix_multiplied(&mut a, 1e-10, &[1.0]);
+		scale_elements(&mut a, 2.0);
+		assert_eq!(a, [2.0 + 2e-10]);
+	}
+
 	#[test]
 	fn sgemv_transpose_no_add() {
-		let a = [3.2, 5.7, 1.2, -6.0, -0.3, 9.5];
+		let a: [Float; 6] = [3.2, 5.7, 1.2, -6.0, -0.3, 9.5];
 		let dim = [3, 2];
 		let b = [-0.5, 5.5, 1.3];
 		let mut res = Vec::new();
@@ -200,4 +528,16 @@ mod tests {
 
 		assert!(res[0] > 31.3099 && res[0] < 32.31001 && res[1] > 13.6999 && res[1] < 13.70001);
 	}
+
+	#[test]
+	fn ragged_sequence() {
+		assert_eq!(
+			flatten_sequence(&[vec![1.0, 2.0], vec![3.0]]),
+			Err(NetworkError::RaggedSequence {
+				timestep: 1,
+				features: 1,
+				expected: 2
+			})
+		);
+	}
 }